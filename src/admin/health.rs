@@ -0,0 +1,162 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// What the readiness probe looks at: the connection to the dispatcher socket, the views and the writers of batched
+/// views, each failing for at most its error budget of events or batches in a row.
+pub(crate) struct Health {
+    started: Instant,
+    input_connected: AtomicBool,
+    views: Mutex<BTreeMap<String, ComponentHealth>>,
+    writers: Mutex<BTreeMap<String, ComponentHealth>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ComponentHealth {
+    ready: bool,
+    delivered: u64,
+    failed: u64,
+    consecutive_failures: u64,
+    error_budget: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Liveness {
+    status: &'static str,
+    uptime_seconds: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Readiness {
+    pub(crate) ready: bool,
+    input: InputHealth,
+    views: BTreeMap<String, ComponentHealth>,
+    writers: BTreeMap<String, ComponentHealth>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct InputHealth {
+    ready: bool,
+    connected: bool,
+}
+
+/// The health of this process, tracked from its start.
+pub(crate) fn health() -> &'static Health {
+    static HEALTH: OnceLock<Health> = OnceLock::new();
+    return HEALTH.get_or_init(|| Health {
+        started: Instant::now(),
+        input_connected: AtomicBool::new(false),
+        views: Mutex::new(BTreeMap::new()),
+        writers: Mutex::new(BTreeMap::new()),
+    });
+}
+
+impl Health {
+    pub(crate) fn set_input_connected(&self, connected: bool) {
+        self.input_connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// Makes the view part of readiness, ready until it fails more than `error_budget` events in a row.
+    pub(crate) fn register_view(&self, name: &str, error_budget: u64) {
+        self.views
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), ComponentHealth::new(error_budget));
+    }
+
+    /// Counts an event rendered by a registered view, others are not part of readiness.
+    pub(crate) fn record_view(&self, name: &str, delivered: bool) {
+        if let Some(view) = self.views.lock().unwrap().get_mut(name) {
+            view.record(delivered);
+        }
+    }
+
+    /// Makes the writer of a batched view part of readiness, ready until it fails more than `error_budget` batches
+    /// in a row.
+    pub(crate) fn register_writer(&self, name: &str, error_budget: u64) {
+        self.writers
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), ComponentHealth::new(error_budget));
+    }
+
+    /// Counts a batch written by a registered writer.
+    pub(crate) fn record_write(&self, name: &str, written: bool) {
+        if let Some(writer) = self.writers.lock().unwrap().get_mut(name) {
+            writer.record(written);
+        }
+    }
+
+    pub(crate) fn liveness(&self) -> Liveness {
+        return Liveness {
+            status: "alive",
+            uptime_seconds: self.started.elapsed().as_secs(),
+        };
+    }
+
+    /// Ready while the input is connected and no view or writer has run out of its error budget.
+    pub(crate) fn readiness(&self) -> Readiness {
+        let connected = self.input_connected.load(Ordering::Relaxed);
+        let views = self.views.lock().unwrap().clone();
+        let writers = self.writers.lock().unwrap().clone();
+        let ready = connected && views.values().chain(writers.values()).all(|x| x.ready);
+        return Readiness {
+            ready,
+            input: InputHealth {
+                ready: connected,
+                connected,
+            },
+            views,
+            writers,
+        };
+    }
+}
+
+impl ComponentHealth {
+    fn new(error_budget: u64) -> Self {
+        return Self {
+            ready: true,
+            delivered: 0,
+            failed: 0,
+            consecutive_failures: 0,
+            error_budget,
+        };
+    }
+
+    fn record(&mut self, succeeded: bool) {
+        match succeeded {
+            true => {
+                self.delivered += 1;
+                self.consecutive_failures = 0;
+            }
+            false => {
+                self.failed += 1;
+                self.consecutive_failures += 1;
+            }
+        }
+        self.ready = self.consecutive_failures <= self.error_budget;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::admin::health::ComponentHealth;
+
+    #[test]
+    fn component_should_be_ready_until_error_budget_is_spent() {
+        let mut component = ComponentHealth::new(2);
+
+        component.record(false);
+        component.record(false);
+        assert!(component.ready);
+        component.record(false);
+        assert!(!component.ready);
+        assert_eq!(component.consecutive_failures, 3);
+        component.record(true);
+        assert!(component.ready);
+        assert_eq!(component.failed, 3);
+        assert_eq!(component.delivered, 1);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::view::ViewError;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const NAMESPACE: &str = "linux_fs_audit";
+/// Seconds, from a local file append up to a remote view retrying for a while.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+/// Views of a composite view queue events before rendering them.
+pub(crate) const DISPATCH_STAGE: &str = "dispatch";
+/// Batched views queue events before writing them as one batch.
+pub(crate) const BATCH_STAGE: &str = "batch";
+/// Handing a single event over to a view.
+pub(crate) const RENDER_STAGE: &str = "render";
+
+/// Everything the agent counts, exposed on `/metrics` in the Prometheus text format.
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) records_received: IntCounter,
+    pub(crate) records_parsed: IntCounter,
+    pub(crate) records_rejected: IntCounter,
+    pub(crate) socket_reconnects: IntCounter,
+    audit_events: IntCounterVec,
+    view_events: IntCounterVec,
+    view_latency: HistogramVec,
+    queue_depth: IntGaugeVec,
+}
+
+/// The metrics of this process, registered on first use.
+pub(crate) fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    return METRICS.get_or_init(Metrics::new);
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str| {
+            let counter =
+                IntCounter::with_opts(Opts::new(name, help).namespace(NAMESPACE)).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            return counter;
+        };
+        let counters = |name: &str, help: &str, labels: &[&str]| {
+            let counters =
+                IntCounterVec::new(Opts::new(name, help).namespace(NAMESPACE), labels).unwrap();
+            registry.register(Box::new(counters.clone())).unwrap();
+            return counters;
+        };
+        let records_received = counter(
+            "records_received_total",
+            "Records read from the dispatcher socket.",
+        );
+        let records_parsed = counter(
+            "records_parsed_total",
+            "Records which held an operation or the files it touched.",
+        );
+        let records_rejected = counter(
+            "records_rejected_total",
+            "Records which held nothing the agent understands.",
+        );
+        let socket_reconnects = counter(
+            "socket_reconnects_total",
+            "Connections to the dispatcher socket made again after losing one.",
+        );
+        let audit_events = counters(
+            "audit_events_total",
+            "Operations observed, by audit key and user.",
+            &["key", "user"],
+        );
+        let view_events = counters(
+            "view_events_total",
+            "Events handed over to a view, by whether it delivered, failed or dropped them.",
+            &["view", "outcome"],
+        );
+        let view_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "view_latency_seconds",
+                "Time a view took to render an event, or a batched view to write a batch.",
+            )
+            .namespace(NAMESPACE)
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["view", "stage"],
+        )
+        .unwrap();
+        registry.register(Box::new(view_latency.clone())).unwrap();
+        let queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "view_queue_depth",
+                "Events waiting for a view, in front of a composite view's view or in a batch.",
+            )
+            .namespace(NAMESPACE),
+            &["view", "stage"],
+        )
+        .unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        return Self {
+            registry,
+            records_received,
+            records_parsed,
+            records_rejected,
+            socket_reconnects,
+            audit_events,
+            view_events,
+            view_latency,
+            queue_depth,
+        };
+    }
+
+    pub(crate) fn record_audit_event(&self, key: &str, user: &str) {
+        self.audit_events.with_label_values(&[key, user]).inc();
+    }
+
+    /// Counts an event the view rendered or failed to, together with how long it took.
+    pub(crate) fn record_render(
+        &self,
+        view: &str,
+        result: &Result<(), ViewError>,
+        elapsed: Duration,
+    ) {
+        let outcome = match result {
+            Ok(_) => "delivered",
+            Err(_) => "failed",
+        };
+        self.view_events.with_label_values(&[view, outcome]).inc();
+        self.record_latency(view, RENDER_STAGE, elapsed);
+    }
+
+    /// Counts the events of a batch a batched view wrote, or gave up on.
+    pub(crate) fn record_batch(&self, view: &str, written: bool, events: usize) {
+        let outcome = match written {
+            true => "delivered",
+            false => "failed",
+        };
+        self.view_events
+            .with_label_values(&[view, outcome])
+            .inc_by(events as u64);
+    }
+
+    /// Counts an event a view rendering in the background could not take in, together with how long it took.
+    /// What became of the events it took in is counted once they are rendered.
+    pub(crate) fn record_handover(
+        &self,
+        view: &str,
+        result: &Result<(), ViewError>,
+        elapsed: Duration,
+    ) {
+        if result.is_err() {
+            self.record_drop(view);
+        }
+        self.record_latency(view, RENDER_STAGE, elapsed);
+    }
+
+    /// Counts an event which never reached the view, as its queue was full.
+    pub(crate) fn record_drop(&self, view: &str) {
+        self.view_events.with_label_values(&[view, "dropped"]).inc();
+    }
+
+    pub(crate) fn record_latency(&self, view: &str, stage: &str, elapsed: Duration) {
+        self.view_latency
+            .with_label_values(&[view, stage])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn set_queue_depth(&self, view: &str, stage: &str, depth: usize) {
+        self.queue_depth
+            .with_label_values(&[view, stage])
+            .set(depth as i64);
+    }
+
+    /// Every metric in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Fatal: Metrics cannot be encoded.");
+        return String::from_utf8(buffer).expect("Fatal: Metrics are not valid UTF-8.");
+    }
+
+    #[cfg(test)]
+    pub(crate) fn view_events(&self, view: &str, outcome: &str) -> u64 {
+        return self.view_events.with_label_values(&[view, outcome]).get();
+    }
+}
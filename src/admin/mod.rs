@@ -0,0 +1,123 @@
+use hyper::http::response::Builder;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::SocketAddr;
+
+mod health;
+mod metrics;
+
+pub(crate) use health::health;
+pub(crate) use metrics::{metrics, BATCH_STAGE, DISPATCH_STAGE};
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Serves the admin endpoints at `address` in the background, returning the address it is bound to.
+pub(crate) fn spawn(address: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    let address: SocketAddr = address.parse()?;
+    let server = Server::try_bind(&address)?.serve(make_service_fn(|_| async {
+        return Ok::<_, Infallible>(service_fn(respond));
+    }));
+    let bound = server.local_addr();
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("Admin server stopped: {}", e);
+        }
+    });
+    return Ok(bound);
+}
+
+async fn respond(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .body(Body::from(metrics().render())),
+        (&Method::GET, "/healthz") => json(Response::builder(), &health().liveness()),
+        (&Method::GET, "/readyz") => {
+            let readiness = health().readiness();
+            let status = match readiness.ready {
+                true => StatusCode::OK,
+                false => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            json(Response::builder().status(status), &readiness)
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    return Ok(response.expect("Fatal: Admin response cannot be built."));
+}
+
+fn json(response: Builder, body: &impl Serialize) -> Result<Response<Body>, hyper::http::Error> {
+    let body = serde_json::to_string(body).expect("Fatal: Health cannot be serialized.");
+    return response
+        .header(header::CONTENT_TYPE, JSON_CONTENT_TYPE)
+        .body(Body::from(body));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::admin::{health, metrics, spawn};
+    use reqwest::StatusCode;
+    use serde_json::Value;
+
+    #[tokio::test]
+    async fn metrics_should_be_served_in_prometheus_format() {
+        let address = spawn("127.0.0.1:0").unwrap();
+        metrics().records_received.inc();
+        metrics().record_audit_event("WRITE", "admin-test");
+
+        let response = reqwest::get(format!("http://{}/metrics", address))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("# TYPE linux_fs_audit_records_received_total counter"));
+        assert!(
+            body.contains("linux_fs_audit_audit_events_total{key=\"WRITE\",user=\"admin-test\"} 1")
+        );
+        let missing = reqwest::get(format!("http://{}/nothing", address))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn probes_should_break_down_health_per_component() {
+        let address = spawn("127.0.0.1:0").unwrap();
+        health().register_view("readiness-test", 1);
+        health().record_view("readiness-test", false);
+
+        let alive = reqwest::get(format!("http://{}/healthz", address))
+            .await
+            .unwrap();
+        assert_eq!(alive.status(), StatusCode::OK);
+        let alive: Value = alive.json().await.unwrap();
+        assert_eq!(alive["status"], "alive");
+
+        let ready = |address| async move {
+            let response = reqwest::get(format!("http://{}/readyz", address))
+                .await
+                .unwrap();
+            return (response.status(), response.json::<Value>().await.unwrap());
+        };
+        let (status, body) = ready(address).await;
+        let view = &body["views"]["readiness-test"];
+        assert_eq!(view["ready"], true);
+        assert_eq!(view["consecutive_failures"], 1);
+        assert_eq!(view["error_budget"], 1);
+        assert!(body["input"]["connected"].is_boolean());
+        assert_eq!(status == StatusCode::OK, body["ready"] == true);
+
+        health().record_view("readiness-test", false);
+        let (status, body) = ready(address).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["views"]["readiness-test"]["ready"], false);
+        assert_eq!(body["views"]["readiness-test"]["failed"], 2);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::cli::{Command, ExportCommand, QueryCommand, UsageError};
+use crate::query::{OutputFormat, PathMatch, QueryFilter};
+use chrono::DateTime;
+
+const MIN_SEARCH_TERM_LENGTH: usize = 3;
+
+impl Command {
+    /// Parses the arguments following the executable name. Relative times are resolved against `now_millis`.
+    pub(crate) fn parse(arguments: &[String], now_millis: u64) -> Result<Self, UsageError> {
+        return match arguments.first().map(|x| x.as_str()) {
+            None => Ok(Command::Run),
+            Some("help") | Some("--help") | Some("-h") => Ok(Command::Help),
+            Some("query") => {
+                let command = QueryCommand::parse(&arguments[1..], now_millis, false)?;
+                Ok(Command::Query(Box::new(command)))
+            }
+            Some("search") => {
+                let command = QueryCommand::parse(&arguments[1..], now_millis, true)?;
+                if command.filter.search.is_empty() {
+                    return Err(usage_error("search needs at least one term".to_string()));
+                }
+                Ok(Command::Query(Box::new(command)))
+            }
+            Some("export") => {
+                let command = ExportCommand::parse(&arguments[1..], now_millis)?;
+                Ok(Command::Export(Box::new(command)))
+            }
+            Some(other) => Err(usage_error(format!("unknown command '{}'", other))),
+        };
+    }
+}
+
+impl QueryCommand {
+    /// Arguments other than options are search terms when `searching`, and rejected otherwise.
+    fn parse(arguments: &[String], now_millis: u64, searching: bool) -> Result<Self, UsageError> {
+        let mut command = QueryCommand {
+            db_path: None,
+            filter: QueryFilter::default(),
+            format: OutputFormat::Table,
+        };
+        let mut remaining = arguments.iter();
+        while let Some(option) = remaining.next() {
+            if searching && !option.starts_with("--") {
+                command.filter.search.push(parse_search_term(option)?);
+                continue;
+            }
+            let value = remaining
+                .next()
+                .ok_or_else(|| usage_error(format!("missing value of '{}'", option)))?
+                .clone();
+            let filter = &mut command.filter;
+            match option.as_str() {
+                "--db" => command.db_path = Some(value),
+                "--path" => set_path(filter, PathMatch::Exact(value))?,
+                "--path-prefix" => set_path(filter, PathMatch::Prefix(value))?,
+                "--path-glob" => set_path(filter, PathMatch::Glob(value))?,
+                "--user" => filter.user = Some(value),
+                "--executable" => filter.executable = Some(value),
+                "--key" => filter.key = Some(value),
+                "--syscall" => filter.syscall = Some(value),
+                "--since" => set_since(filter, now_millis.saturating_sub(parse_duration(&value)?))?,
+                "--from" => set_since(filter, parse_time(&value)?)?,
+                "--until" => filter.until = Some(parse_time(&value)?),
+                "--limit" => {
+                    filter.limit = Some(
+                        value
+                            .parse()
+                            .map_err(|_| usage_error(format!("invalid limit '{}'", value)))?,
+                    )
+                }
+                "--format" => command.format = parse_format(&value)?,
+                _ => return Err(usage_error(format!("unknown option '{}'", option))),
+            }
+        }
+        return Ok(command);
+    }
+}
+
+impl ExportCommand {
+    /// Takes `--out` and the query options other than `--format`.
+    fn parse(arguments: &[String], now_millis: u64) -> Result<Self, UsageError> {
+        let mut directory = None;
+        let mut query = Vec::with_capacity(arguments.len());
+        let mut remaining = arguments.iter();
+        while let Some(option) = remaining.next() {
+            match option.as_str() {
+                "--out" => {
+                    let value = remaining
+                        .next()
+                        .ok_or_else(|| usage_error(format!("missing value of '{}'", option)))?;
+                    directory = Some(value.clone());
+                }
+                "--format" => {
+                    return Err(usage_error(
+                        "export always writes Parquet, --format cannot be given".to_string(),
+                    ))
+                }
+                _ => query.push(option.clone()),
+            }
+        }
+        let directory = directory.ok_or_else(|| usage_error("export needs --out".to_string()))?;
+        let command = QueryCommand::parse(&query, now_millis, false)?;
+        return Ok(ExportCommand {
+            db_path: command.db_path,
+            filter: command.filter,
+            directory,
+        });
+    }
+}
+
+fn set_path(filter: &mut QueryFilter, path: PathMatch) -> Result<(), UsageError> {
+    if filter.path.is_some() {
+        return Err(usage_error(
+            "only one of --path, --path-prefix and --path-glob can be given".to_string(),
+        ));
+    }
+    filter.path = Some(path);
+    return Ok(());
+}
+
+fn set_since(filter: &mut QueryFilter, since: u64) -> Result<(), UsageError> {
+    if filter.since.is_some() {
+        return Err(usage_error(
+            "only one of --since and --from can be given".to_string(),
+        ));
+    }
+    filter.since = Some(since);
+    return Ok(());
+}
+
+/// Search terms are matched by their three character parts, shorter ones could never match.
+fn parse_search_term(value: &str) -> Result<String, UsageError> {
+    if value.chars().count() < MIN_SEARCH_TERM_LENGTH {
+        return Err(usage_error(format!(
+            "search term '{}' is shorter than {} characters",
+            value, MIN_SEARCH_TERM_LENGTH
+        )));
+    }
+    return Ok(value.to_string());
+}
+
+/// A number followed by `s`, `m`, `h` or `d`, in milliseconds.
+fn parse_duration(value: &str) -> Result<u64, UsageError> {
+    let invalid = || {
+        usage_error(format!(
+            "invalid duration '{}', expected e.g. 30m, 24h or 7d",
+            value
+        ))
+    };
+    let unit = match value.chars().last() {
+        Some('s') => 1_000,
+        Some('m') => 60_000,
+        Some('h') => 3_600_000,
+        Some('d') => 86_400_000,
+        _ => return Err(invalid()),
+    };
+    let amount: u64 = value[..value.len() - 1].parse().map_err(|_| invalid())?;
+    return amount.checked_mul(unit).ok_or_else(invalid);
+}
+
+/// Unix seconds or an RFC 3339 time, in milliseconds since the epoch.
+fn parse_time(value: &str) -> Result<u64, UsageError> {
+    let invalid = || {
+        usage_error(format!(
+            "invalid time '{}', expected unix seconds or RFC 3339",
+            value
+        ))
+    };
+    if let Ok(seconds) = value.parse::<u64>() {
+        return seconds.checked_mul(1000).ok_or_else(invalid);
+    }
+    return DateTime::parse_from_rfc3339(value)
+        .map(|x| x.timestamp_millis().max(0) as u64)
+        .map_err(|_| invalid());
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, UsageError> {
+    return match value {
+        "table" => Ok(OutputFormat::Table),
+        "json" => Ok(OutputFormat::Json),
+        "csv" => Ok(OutputFormat::Csv),
+        _ => Err(usage_error(format!("unknown format '{}'", value))),
+    };
+}
+
+fn usage_error(message: String) -> UsageError {
+    return UsageError { message };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::{Command, ExportCommand, QueryCommand};
+    use crate::query::{OutputFormat, PathMatch, QueryFilter};
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn parse(arguments: &str) -> Result<Command, String> {
+        let arguments: Vec<String> = arguments
+            .split_whitespace()
+            .map(|x| x.to_string())
+            .collect();
+        return Command::parse(&arguments, NOW).map_err(|e| e.to_string());
+    }
+
+    #[test]
+    fn without_arguments_should_run_agent() {
+        assert_eq!(parse(""), Ok(Command::Run));
+    }
+
+    #[test]
+    fn should_parse_who_touched_file_in_last_day() {
+        assert_eq!(
+            parse("query --path /etc/shadow --since 24h --format json"),
+            Ok(Command::Query(Box::new(QueryCommand {
+                db_path: None,
+                filter: QueryFilter {
+                    path: Some(PathMatch::Exact("/etc/shadow".to_string())),
+                    since: Some(NOW - 86_400_000),
+                    ..Default::default()
+                },
+                format: OutputFormat::Json,
+            })))
+        );
+    }
+
+    #[test]
+    fn should_parse_what_user_modified() {
+        assert_eq!(
+            parse("query --db r.sqlite --user maciek --key WRITE --path-prefix /home --from 2023-11-14T22:13:20Z --until 1700086400 --limit 5"),
+            Ok(Command::Query(Box::new(QueryCommand {
+                db_path: Some("r.sqlite".to_string()),
+                filter: QueryFilter {
+                    path: Some(PathMatch::Prefix("/home".to_string())),
+                    user: Some("maciek".to_string()),
+                    key: Some("WRITE".to_string()),
+                    since: Some(NOW),
+                    until: Some(NOW + 86_400_000),
+                    limit: Some(5),
+                    ..Default::default()
+                },
+                format: OutputFormat::Table,
+            })))
+        );
+    }
+
+    #[test]
+    fn should_parse_search_terms_among_options() {
+        assert_eq!(
+            parse("search id_rsa --since 7d curl --format csv"),
+            Ok(Command::Query(Box::new(QueryCommand {
+                db_path: None,
+                filter: QueryFilter {
+                    search: vec!["id_rsa".to_string(), "curl".to_string()],
+                    since: Some(NOW - 7 * 86_400_000),
+                    ..Default::default()
+                },
+                format: OutputFormat::Csv,
+            })))
+        );
+    }
+
+    #[test]
+    fn should_parse_export_of_last_month() {
+        assert_eq!(
+            parse("export --key WRITE --out /data/audit --since 30d"),
+            Ok(Command::Export(Box::new(ExportCommand {
+                db_path: None,
+                filter: QueryFilter {
+                    key: Some("WRITE".to_string()),
+                    since: Some(NOW - 30 * 86_400_000),
+                    ..Default::default()
+                },
+                directory: "/data/audit".to_string(),
+            })))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_arguments() {
+        assert_eq!(
+            parse("serve"),
+            Err("Error: unknown command 'serve'".to_string())
+        );
+        assert_eq!(
+            parse("query --user"),
+            Err("Error: missing value of '--user'".to_string())
+        );
+        assert!(parse("query --since 24").is_err());
+        assert!(parse("query --since 99999999999999999d").is_err());
+        assert!(parse("query --since 7d --from 1700000000").is_err());
+        assert!(parse("query --from yesterday").is_err());
+        assert!(parse("query --until 18446744073709552").is_err());
+        assert!(parse("query --format xml").is_err());
+        assert!(parse("query --path /a --path-glob /b*").is_err());
+        assert!(parse("query id_rsa").is_err());
+        assert!(parse("search --user root").is_err());
+        assert!(parse("search ls").is_err());
+        assert_eq!(
+            parse("export --since 7d"),
+            Err("Error: export needs --out".to_string())
+        );
+        assert!(parse("export --out /data --format csv").is_err());
+        assert!(parse("export --out").is_err());
+    }
+}
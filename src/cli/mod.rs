@@ -0,0 +1,66 @@
+use crate::query::{OutputFormat, QueryFilter};
+use std::error::Error;
+use std::fmt;
+
+mod arguments;
+
+pub(crate) const USAGE: &str = "\
+Usage:
+  linux-fs-audit                     forward audit events to the configured views
+  linux-fs-audit query [options]     look up file accesses in the Sqlite store
+  linux-fs-audit search <term>... [options]
+                                     look up file accesses with any of the terms in their path,
+                                     executable or command line, terms of at least 3 characters
+  linux-fs-audit export --out <dir> [options]
+                                     write file accesses to Parquet files partitioned by date and key,
+                                     takes the query options other than --format
+
+Query and search options:
+  --db <path>                        database, defaults to the one in settings.json
+  --path <path>                      accesses of exactly this path
+  --path-prefix <prefix>             accesses of paths starting with the prefix
+  --path-glob <glob>                 accesses of paths matching the glob, `*` matches `/` as well
+  --user <name>                      accesses by the user
+  --executable <path>                accesses by the executable
+  --key <key>                        accesses audited under the key, e.g. READ or WRITE
+  --syscall <syscall>                accesses through the syscall
+  --since <duration>                 accesses in the last duration, e.g. 30m, 24h or 7d
+  --from <time>                      accesses at or after the time, unix seconds or RFC 3339, not with --since
+  --until <time>                     accesses before the time, unix seconds or RFC 3339
+  --limit <count>                    at most this many accesses, newest first
+  --format <table|json|csv>          output format, table by default";
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Run,
+    Help,
+    Query(Box<QueryCommand>),
+    Export(Box<ExportCommand>),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct QueryCommand {
+    pub(crate) db_path: Option<String>,
+    pub(crate) filter: QueryFilter,
+    pub(crate) format: OutputFormat,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct ExportCommand {
+    pub(crate) db_path: Option<String>,
+    pub(crate) filter: QueryFilter,
+    pub(crate) directory: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct UsageError {
+    message: String,
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error: {}", self.message)
+    }
+}
+
+impl Error for UsageError {}
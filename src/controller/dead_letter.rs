@@ -0,0 +1,123 @@
+use crate::view::{ViewError, ViewEvent};
+use colored::Colorize;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::OpenOptions;
+use tokio::io;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Appends events no view could render to a file, one json object per line,
+/// so they can be inspected or replayed later.
+pub(crate) struct DeadLetterFile {
+    path: String,
+    lock: Mutex<()>,
+}
+
+/// Events which could not be rendered, counted across the input port and every view,
+/// and written to the dead letter file when one is configured.
+#[derive(Default)]
+pub(crate) struct FailedEvents {
+    dead_letter: Option<DeadLetterFile>,
+    count: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    error: String,
+    retryable: bool,
+    #[serde(flatten)]
+    event: &'a ViewEvent,
+}
+
+impl DeadLetterFile {
+    pub(crate) fn new(path: &str) -> Self {
+        return Self {
+            path: path.to_string(),
+            lock: Mutex::new(()),
+        };
+    }
+
+    pub(crate) async fn write(&self, event: &ViewEvent, error: &ViewError) -> io::Result<()> {
+        let mut line = serde_json::to_string(&DeadLetter {
+            error: error.to_string(),
+            retryable: error.is_retryable(),
+            event,
+        })?;
+        line.push('\n');
+        let _guard = self.lock.lock().await;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        return file.flush().await;
+    }
+}
+
+impl FailedEvents {
+    pub(crate) fn new(dead_letter_path: Option<&str>) -> Self {
+        return Self {
+            dead_letter: dead_letter_path.map(DeadLetterFile::new),
+            count: AtomicU64::new(0),
+        };
+    }
+
+    /// Counts the event and dead letters it, returning how many events failed so far.
+    pub(crate) async fn record(&self, event: &ViewEvent, error: &ViewError) -> u64 {
+        let failed = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(dead_letter) = &self.dead_letter {
+            if let Err(e) = dead_letter.write(event, error).await {
+                log::error!("{} {}", "Error: writing dead letter failed:".red(), e);
+            }
+        }
+        return failed;
+    }
+
+    #[cfg(test)]
+    pub(crate) fn count(&self) -> u64 {
+        return self.count.load(Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::controller::dead_letter::DeadLetterFile;
+    use crate::serializer::FileOperatedOn;
+    use crate::view::{ViewError, ViewEvent};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn should_append_one_line_per_event() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("dead_letters.jsonl");
+        let dead_letter = DeadLetterFile::new(path.to_str().unwrap());
+        let event = ViewEvent::File(FileOperatedOn {
+            name: "/etc/shadow".to_string(),
+            timestamp: "123".to_string(),
+            serial: 570,
+            audit_time: 1698576562955,
+        });
+
+        dead_letter
+            .write(&event, &ViewError::retryable("Inserting"))
+            .await
+            .unwrap();
+        dead_letter
+            .write(&event, &ViewError::permanent("Inserting"))
+            .await
+            .unwrap();
+
+        let written = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["retryable"], true);
+        assert_eq!(lines[1]["retryable"], false);
+        assert_eq!(lines[1]["file"]["name"], "/etc/shadow");
+        assert_eq!(lines[1]["error"], "Inserting (permanent)");
+    }
+}
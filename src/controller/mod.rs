@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use tokio::io;
 
+pub mod dead_letter;
 pub mod unix_port;
 
 #[async_trait]
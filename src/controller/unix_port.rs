@@ -1,89 +1,151 @@
+use crate::admin::{health, metrics};
+use crate::controller::dead_letter::FailedEvents;
 use crate::controller::InputPort;
 use crate::encode;
 use crate::serializer::{FileOperatedOn, Operation};
-use crate::view::View;
+use crate::view::{render, View, ViewEvent};
 use async_trait::async_trait;
 use colored::Colorize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io;
 use tokio::io::Interest;
 use tokio::net::UnixStream;
 
 const STREAM_MAX_SIZE_IN_BYTES: usize = 1024;
 const INITIAL_BUFFER_VALUE: u8 = 0;
+const RECONNECT_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
 
 pub(crate) struct UnixSocketPort {
     socket_path: String,
     view: Box<dyn View>,
+    view_name: Option<String>,
+    failed_events: Arc<FailedEvents>,
 }
 
 pub(crate) struct UnixSocketSettings {
     pub socket_path: String,
+    /// Shared with the views of a composite view, which count and dead letter their own failures.
+    pub failed_events: Arc<FailedEvents>,
+    /// Name the deliveries of the view are counted under, `None` for a composite view, whose views count their own.
+    pub view_name: Option<String>,
 }
 
 #[async_trait]
 impl InputPort for UnixSocketPort {
+    /// Fails only when the socket cannot be connected to at all, a connection lost later is made again.
     async fn receive(&self) -> io::Result<()> {
-        let data_stream_from_unix_socket = UnixStream::connect(&self.socket_path).await?;
+        let mut stream = UnixStream::connect(&self.socket_path).await?;
+        health().set_input_connected(true);
         let mut previous_timestamp = String::new();
         loop {
-            let stream_status = data_stream_from_unix_socket
-                .ready(Interest::READABLE)
-                .await?;
+            let error = self.forward(&stream, &mut previous_timestamp).await;
+            health().set_input_connected(false);
+            log::warn!(
+                "{} {}, reconnecting.",
+                "Lost connection to the dispatcher socket:".yellow(),
+                error
+            );
+            stream = self.reconnect().await;
+            health().set_input_connected(true);
+            metrics().socket_reconnects.inc();
+        }
+    }
+}
+
+impl UnixSocketPort {
+    pub(crate) fn new(
+        init_settings: UnixSocketSettings,
+        output_view: Box<dyn View>,
+    ) -> Box<UnixSocketPort> {
+        return Box::new(UnixSocketPort {
+            socket_path: init_settings.socket_path,
+            view: output_view,
+            view_name: init_settings.view_name,
+            failed_events: init_settings.failed_events,
+        });
+    }
+    /// Renders everything read from the stream, until reading fails or the dispatcher closes it.
+    async fn forward(&self, stream: &UnixStream, previous_timestamp: &mut String) -> io::Error {
+        loop {
+            let stream_status = match stream.ready(Interest::READABLE).await {
+                Ok(status) => status,
+                Err(e) => return e,
+            };
 
             let mut read_data = vec![INITIAL_BUFFER_VALUE; STREAM_MAX_SIZE_IN_BYTES];
             if stream_status.is_readable() {
                 log::info!("Unix stream is readable.");
-                match data_stream_from_unix_socket.try_read(&mut read_data) {
+                match stream.try_read(&mut read_data) {
+                    Ok(0) => return io::Error::from(io::ErrorKind::UnexpectedEof),
                     Ok(_) => {
                         let encoded_values = encode!(read_data);
                         log::debug!("Received message: {}", encoded_values);
-                        let operation = Operation::new(encoded_values.clone());
-                        if operation.is_some() {
-                            previous_timestamp = operation.as_ref().unwrap().timestamp.clone();
-                            log::debug!("{} : {}", "Previous timestamp".cyan(), previous_timestamp);
-                            self.update_operation_observed(operation).await;
-                        }
-                        let files_changed =
-                            FileOperatedOn::new(encoded_values, previous_timestamp.clone());
-                        if files_changed.is_some() {
-                            self.report_checked_files(files_changed).await;
-                        }
+                        self.process(encoded_values, previous_timestamp).await;
                     }
                     Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                         log::warn!("{}", "Blocking error while reading from socket".yellow());
                         continue;
                     }
-                    Err(e) => {
-                        return Err(e.into());
-                    }
+                    Err(e) => return e,
                 }
             }
         }
     }
-}
 
-impl UnixSocketPort {
-    pub(crate) fn new(
-        init_settings: UnixSocketSettings,
-        output_view: Box<dyn View>,
-    ) -> Box<UnixSocketPort> {
-        return Box::new(UnixSocketPort {
-            socket_path: String::from(init_settings.socket_path),
-            view: output_view,
-        });
+    async fn process(&self, encoded_values: String, previous_timestamp: &mut String) {
+        metrics().records_received.inc();
+        let operation = Operation::new(encoded_values.clone());
+        let parsed = operation.is_some();
+        if let Some(observed) = operation.as_ref() {
+            *previous_timestamp = observed.timestamp.clone();
+            log::debug!("{} : {}", "Previous timestamp".cyan(), previous_timestamp);
+            metrics().record_audit_event(&observed.key.to_string(), &observed.user);
+            self.update_operation_observed(operation).await;
+        }
+        let files_changed = FileOperatedOn::new(encoded_values, previous_timestamp.clone());
+        match parsed || files_changed.is_some() {
+            true => metrics().records_parsed.inc(),
+            false => metrics().records_rejected.inc(),
+        }
+        if files_changed.is_some() {
+            self.report_checked_files(files_changed).await;
+        }
     }
+
+    /// Connects again, waiting longer after every failed attempt.
+    async fn reconnect(&self) -> UnixStream {
+        let mut backoff = RECONNECT_BACKOFF_MIN;
+        loop {
+            tokio::time::sleep(backoff).await;
+            match UnixStream::connect(&self.socket_path).await {
+                Ok(stream) => {
+                    log::info!("Reconnected to {}.", self.socket_path.cyan());
+                    return stream;
+                }
+                Err(e) => {
+                    log::warn!("Could not reconnect to {}: {}", self.socket_path.red(), e);
+                    backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                }
+            }
+        }
+    }
+
     fn ascii_encode_and_join(read_data: Vec<u8>) -> String {
         read_data.iter().map(|x| *x as char).collect()
     }
 
+    /// Waits until the view rendered everything received so far.
+    pub(crate) async fn flush(&self) {
+        if let Err(e) = self.view.flush().await {
+            log::error!("{} {}", "Error: flushing view failed:".red(), e);
+        }
+    }
+
     async fn report_checked_files(&self, files_changed: Option<Vec<FileOperatedOn>>) {
-        let tasks: Vec<_> = files_changed
-            .unwrap()
-            .iter_mut()
-            .map(|item| self.view.report(item.clone()))
-            .collect();
-        for task in tasks {
-            task.await.unwrap()
+        for item in files_changed.unwrap() {
+            self.render_or_dead_letter(ViewEvent::File(item)).await;
         }
     }
 
@@ -94,10 +156,31 @@ impl UnixSocketPort {
             operation.iter().clone()
         );
 
-        self.view
-            .update(operation.unwrap())
-            .await
-            .expect("Error: rendering view is impossible.");
+        self.render_or_dead_letter(ViewEvent::Operation(operation.unwrap()))
+            .await;
+    }
+
+    async fn render_or_dead_letter(&self, event: ViewEvent) {
+        let started = Instant::now();
+        let rendered = render(self.view.as_ref(), &event).await;
+        if let Some(view_name) = &self.view_name {
+            match self.view.renders_in_background() {
+                true => metrics().record_handover(view_name, &rendered, started.elapsed()),
+                false => metrics().record_render(view_name, &rendered, started.elapsed()),
+            }
+            health().record_view(view_name, rendered.is_ok());
+        }
+        let error = match rendered {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        let failed_events = self.failed_events.record(&event, &error).await;
+        log::error!(
+            "{} {} ({} events failed so far)",
+            "Error: rendering view failed:".red(),
+            error,
+            failed_events
+        );
     }
 }
 
@@ -112,16 +195,113 @@ mod unix_port_macros {
 
 #[cfg(test)]
 mod test {
+    use crate::admin::metrics;
+    use crate::controller::dead_letter::FailedEvents;
     use crate::controller::unix_port::{UnixSocketPort, UnixSocketSettings};
-    use crate::view::MockView;
+    use crate::controller::InputPort;
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::view::{MockView, View, ViewError};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixListener;
+    use tokio::sync::mpsc;
+
+    const WRITE_LOG_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 comm=\"vi\" exe=\"/usr/bin/vi\" key=\"WRITE\"ARCH=x86_64 UID=\"reconnect-test\" GID=\"maciek\"";
+
+    /// Passes every operation on to the test.
+    struct ForwardingView {
+        operations: mpsc::UnboundedSender<Operation>,
+    }
+
+    #[async_trait]
+    impl View for ForwardingView {
+        async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+            self.operations.send(operation).unwrap();
+            return Ok(());
+        }
+
+        async fn report(&self, _files: FileOperatedOn) -> Result<(), ViewError> {
+            return Ok(());
+        }
+    }
+
+    struct FailingView {}
+
+    #[async_trait]
+    impl View for FailingView {
+        async fn update(&self, _operation: Operation) -> Result<(), ViewError> {
+            return Err(ViewError::permanent("Rendering operation"));
+        }
+
+        async fn report(&self, _files: FileOperatedOn) -> Result<(), ViewError> {
+            return Err(ViewError::permanent("Rendering file"));
+        }
+    }
 
     #[test]
     fn should_construct_with_correct_path() {
         let path = "path".to_string();
         let config = UnixSocketSettings {
             socket_path: path.clone(),
+            failed_events: Arc::new(FailedEvents::new(None)),
+            view_name: None,
         };
         let port = UnixSocketPort::new(config, Box::new(MockView {}));
         assert_eq!(port.socket_path, path)
     }
+
+    #[tokio::test]
+    async fn failed_events_should_be_counted_and_dead_lettered() {
+        let directory = tempdir().unwrap();
+        let dead_letter_path = directory.path().join("dead_letters.jsonl");
+        let config = UnixSocketSettings {
+            socket_path: "path".to_string(),
+            failed_events: Arc::new(FailedEvents::new(dead_letter_path.to_str())),
+            view_name: Some("dead-lettered".to_string()),
+        };
+        let port = UnixSocketPort::new(config, Box::new(FailingView {}));
+        let files = FileOperatedOn::new(
+            "type=PATH msg=audit(1364481363.243:24287): item=0 name=\"/etc/shadow\"".to_string(),
+            "123".to_string(),
+        );
+
+        port.report_checked_files(files).await;
+
+        assert_eq!(port.failed_events.count(), 1);
+        assert_eq!(metrics().view_events("dead-lettered", "failed"), 1);
+        let dead_letters = std::fs::read_to_string(dead_letter_path).unwrap();
+        assert_eq!(dead_letters.lines().count(), 1);
+        assert!(dead_letters.contains("/etc/shadow"));
+    }
+
+    #[tokio::test]
+    async fn lost_connection_should_be_made_again() {
+        let directory = tempdir().unwrap();
+        let socket_path = directory.path().join("dispatcher");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let (operations, mut received) = mpsc::unbounded_channel();
+        let config = UnixSocketSettings {
+            socket_path: socket_path.to_str().unwrap().to_string(),
+            failed_events: Arc::new(FailedEvents::new(None)),
+            view_name: None,
+        };
+        let port = UnixSocketPort::new(config, Box::new(ForwardingView { operations }));
+        let reconnects = metrics().socket_reconnects.get();
+        tokio::spawn(async move { port.receive().await });
+
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(WRITE_LOG_LINE.as_bytes()).await.unwrap();
+            let operation = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(operation.user, "reconnect-test");
+        }
+
+        assert!(metrics().socket_reconnects.get() > reconnects);
+    }
 }
@@ -0,0 +1,403 @@
+use crate::query::{for_each_access, AccessRecord, QueryFilter};
+use crate::serializer::{FileOperatedOn, Operation};
+use arrow_array::{
+    ArrayRef, BooleanArray, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::DateTime;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// How many accesses the export keeps in memory before writing them out.
+const ROWS_PER_PART: usize = 100_000;
+/// Stands in for the key of a file reported without its operation.
+const UNKNOWN_KEY: &str = "unknown";
+/// Tells apart the parts written in the same millisecond by this process.
+static PART_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// One file access with the operation behind it, or an operation which touched no file. Files reported
+/// without their operation, and columns the Sqlite store does not keep, are left empty.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct AccessRow {
+    pub(crate) audit_time: u64,
+    pub(crate) serial: u64,
+    pub(crate) user: Option<String>,
+    pub(crate) group: Option<String>,
+    pub(crate) executable: Option<String>,
+    pub(crate) command: Option<String>,
+    pub(crate) pid: Option<u32>,
+    pub(crate) syscall: Option<String>,
+    pub(crate) key: Option<String>,
+    pub(crate) success: Option<bool>,
+    pub(crate) path: Option<String>,
+}
+
+/// The partition an access is written to, `date=YYYY-MM-DD/key=KEY` below the export directory.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Partition {
+    date: String,
+    key: String,
+}
+
+impl From<AccessRecord> for AccessRow {
+    fn from(record: AccessRecord) -> Self {
+        return Self {
+            audit_time: record.audit_time,
+            serial: record.serial,
+            user: Some(record.user),
+            group: Some(record.group),
+            executable: Some(record.executable),
+            command: Some(record.command),
+            pid: None,
+            syscall: Some(record.syscall),
+            key: Some(record.key),
+            success: None,
+            path: record.path,
+        };
+    }
+}
+
+impl AccessRow {
+    /// The operation with one of its files, or with `None` for an operation which touched no file.
+    pub(crate) fn of_operation(operation: &Operation, path: Option<String>) -> Self {
+        return Self {
+            audit_time: operation.audit_time,
+            serial: operation.serial,
+            user: Some(operation.user.clone()),
+            group: Some(operation.group.clone()),
+            executable: Some(operation.executable.clone()),
+            command: Some(operation.command.clone()),
+            pid: operation.pid,
+            syscall: Some(operation.syscall.clone()),
+            key: Some(operation.key.to_string()),
+            success: Some(operation.success),
+            path,
+        };
+    }
+
+    /// A file reported without its operation.
+    pub(crate) fn of_file(file: &FileOperatedOn) -> Self {
+        return Self {
+            audit_time: file.audit_time,
+            serial: file.serial,
+            user: None,
+            group: None,
+            executable: None,
+            command: None,
+            pid: None,
+            syscall: None,
+            key: None,
+            success: None,
+            path: Some(file.name.clone()),
+        };
+    }
+
+    fn partition(&self) -> Partition {
+        let date = DateTime::from_timestamp_millis(self.audit_time as i64)
+            .unwrap_or_default()
+            .format("%Y-%m-%d")
+            .to_string();
+        let key = self.key.as_deref().unwrap_or(UNKNOWN_KEY).to_string();
+        return Partition { date, key };
+    }
+}
+
+/// The columns of every part. Date and key are left to the partition directories, the way Spark and
+/// DuckDB read them back.
+pub(crate) fn schema() -> SchemaRef {
+    return Arc::new(Schema::new(vec![
+        Field::new(
+            "audit_time",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("serial", DataType::UInt64, false),
+        Field::new("user", DataType::Utf8, true),
+        Field::new("group", DataType::Utf8, true),
+        Field::new("executable", DataType::Utf8, true),
+        Field::new("command", DataType::Utf8, true),
+        Field::new("pid", DataType::UInt32, true),
+        Field::new("syscall", DataType::Utf8, true),
+        Field::new("success", DataType::Boolean, true),
+        Field::new("path", DataType::Utf8, true),
+    ]));
+}
+
+/// Writes the accesses matching the filter below `directory`, returning the parts written.
+pub(crate) fn export_accesses(
+    db_path: &str,
+    filter: &QueryFilter,
+    directory: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut parts = Vec::new();
+    let mut rows = Vec::with_capacity(ROWS_PER_PART);
+    for_each_access(db_path, filter, |record| {
+        rows.push(AccessRow::from(record));
+        if rows.len() >= ROWS_PER_PART {
+            parts.extend(write_parts(directory, &rows)?);
+            rows.clear();
+        }
+        return Ok(());
+    })?;
+    parts.extend(write_parts(directory, &rows)?);
+    return Ok(parts);
+}
+
+/// Writes the rows into a new part of each partition they fall into, returning the parts written. Parts are
+/// written under a hidden name first, so that readers never see one half written.
+pub(crate) fn write_parts(
+    directory: &Path,
+    rows: &[AccessRow],
+) -> Result<Vec<PathBuf>, ParquetError> {
+    let mut partitions: BTreeMap<Partition, Vec<&AccessRow>> = BTreeMap::new();
+    for row in rows {
+        partitions.entry(row.partition()).or_default().push(row);
+    }
+    let mut parts = Vec::with_capacity(partitions.len());
+    for (partition, rows) in partitions {
+        let partition_directory = directory
+            .join(format!("date={}", partition.date))
+            .join(format!("key={}", partition.key));
+        std::fs::create_dir_all(&partition_directory)?;
+        let name = format!(
+            "part-{}-{:06}.parquet",
+            chrono::Utc::now().timestamp_millis(),
+            PART_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        );
+        let hidden = partition_directory.join(format!(".{}.tmp", name));
+        let part = partition_directory.join(name);
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = ArrowWriter::try_new(File::create(&hidden)?, schema(), Some(properties))?;
+        writer.write(&record_batch(&rows)?)?;
+        writer.close()?;
+        std::fs::rename(&hidden, &part)?;
+        parts.push(part);
+    }
+    return Ok(parts);
+}
+
+fn record_batch(rows: &[&AccessRow]) -> Result<RecordBatch, ParquetError> {
+    let text = |column: fn(&AccessRow) -> &Option<String>| -> ArrayRef {
+        return Arc::new(StringArray::from_iter(
+            rows.iter().map(|x| column(x).as_deref()),
+        ));
+    };
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(rows.iter().map(|x| x.audit_time as i64))
+                .with_timezone("UTC"),
+        ),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|x| x.serial))),
+        text(|x| &x.user),
+        text(|x| &x.group),
+        text(|x| &x.executable),
+        text(|x| &x.command),
+        Arc::new(UInt32Array::from_iter(rows.iter().map(|x| x.pid))),
+        text(|x| &x.syscall),
+        Arc::new(BooleanArray::from_iter(rows.iter().map(|x| x.success))),
+        text(|x| &x.path),
+    ];
+    return Ok(RecordBatch::try_new(schema(), columns)?);
+}
+
+/// Reads a part back, taking the key from its partition directory.
+#[cfg(test)]
+pub(crate) fn read_part(part: &Path) -> Vec<AccessRow> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{TimestampMillisecondType, UInt32Type, UInt64Type};
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let key = part
+        .parent()
+        .and_then(|x| x.file_name())
+        .and_then(|x| x.to_str())
+        .and_then(|x| x.strip_prefix("key="))
+        .filter(|x| *x != UNKNOWN_KEY)
+        .map(String::from);
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(part).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch = batch.unwrap();
+        let text = |name: &str, i: usize| {
+            let column = batch.column_by_name(name).unwrap().as_string::<i32>();
+            return column.is_valid(i).then(|| column.value(i).to_string());
+        };
+        let pid = batch
+            .column_by_name("pid")
+            .unwrap()
+            .as_primitive::<UInt32Type>();
+        let success = batch.column_by_name("success").unwrap().as_boolean();
+        for i in 0..batch.num_rows() {
+            rows.push(AccessRow {
+                audit_time: batch
+                    .column_by_name("audit_time")
+                    .unwrap()
+                    .as_primitive::<TimestampMillisecondType>()
+                    .value(i) as u64,
+                serial: batch
+                    .column_by_name("serial")
+                    .unwrap()
+                    .as_primitive::<UInt64Type>()
+                    .value(i),
+                user: text("user", i),
+                group: text("group", i),
+                executable: text("executable", i),
+                command: text("command", i),
+                pid: pid.is_valid(i).then(|| pid.value(i)),
+                syscall: text("syscall", i),
+                key: key.clone(),
+                success: success.is_valid(i).then(|| success.value(i)),
+                path: text("path", i),
+            });
+        }
+    }
+    return rows;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::export::{export_accesses, read_part, schema, write_parts, AccessRow};
+    use crate::query::QueryFilter;
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::settings::SqliteSettings;
+    use crate::view::{SqliteView, View, ViewContext};
+    use tempfile::tempdir;
+
+    const SYSCALL_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 comm=\"cat\" exe=\"/usr/bin/cat\" key=\"READ\"ARCH=x86_64 UID=\"maciek\" GID=\"maciek\"";
+
+    fn row(audit_time: u64, key: Option<&str>, path: &str) -> AccessRow {
+        return AccessRow {
+            audit_time,
+            serial: 570,
+            user: key.map(|_| "maciek".to_string()),
+            group: None,
+            executable: key.map(|_| "/usr/bin/cat".to_string()),
+            command: None,
+            pid: key.map(|_| 20680),
+            syscall: None,
+            key: key.map(String::from),
+            success: key.map(|_| true),
+            path: Some(path.to_string()),
+        };
+    }
+
+    #[test]
+    fn rows_should_be_partitioned_by_date_and_key() {
+        let directory = tempdir().unwrap();
+        let rows = [
+            row(1698576562955, Some("READ"), "/etc/hosts"),
+            row(1698576563120, Some("WRITE"), "/etc/shadow"),
+            row(1698662962955, Some("READ"), "/etc/passwd"),
+            row(1698576564000, Some("READ"), "/etc/group"),
+            row(1698576565000, None, "/tmp/orphan"),
+        ];
+
+        let parts = write_parts(directory.path(), &rows).unwrap();
+
+        let mut partitions: Vec<String> = parts
+            .iter()
+            .map(|x| {
+                let partition = x.parent().unwrap().strip_prefix(directory.path());
+                partition.unwrap().to_str().unwrap().to_string()
+            })
+            .collect();
+        partitions.sort();
+        assert_eq!(
+            partitions,
+            [
+                "date=2023-10-29/key=READ",
+                "date=2023-10-29/key=WRITE",
+                "date=2023-10-29/key=unknown",
+                "date=2023-10-30/key=READ"
+            ]
+        );
+        let read = parts
+            .iter()
+            .find(|x| x.to_str().unwrap().contains("2023-10-29/key=READ"))
+            .unwrap();
+        assert_eq!(read_part(read), [rows[0].clone(), rows[3].clone()]);
+        let orphan = parts
+            .iter()
+            .find(|x| x.to_str().unwrap().contains("key=unknown"))
+            .unwrap();
+        assert_eq!(read_part(orphan), [rows[4].clone()]);
+        let hidden = walk(directory.path())
+            .into_iter()
+            .filter(|x| x.ends_with(".tmp"))
+            .count();
+        assert_eq!(hidden, 0);
+    }
+
+    #[test]
+    fn parts_should_have_typed_schema() {
+        let directory = tempdir().unwrap();
+        let parts =
+            write_parts(directory.path(), &[row(1698576562955, Some("READ"), "/a")]).unwrap();
+        let file = std::fs::File::open(&parts[0]).unwrap();
+        let builder =
+            parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.schema().fields(), schema().fields());
+    }
+
+    #[tokio::test]
+    async fn export_should_write_accesses_of_sqlite_store() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        let sqlite_view = SqliteView::new(&SqliteSettings::new(db_path), &ViewContext::default());
+        sqlite_view
+            .update(Operation::new(SYSCALL_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        sqlite_view
+            .report(FileOperatedOn {
+                name: "/etc/shadow".to_string(),
+                timestamp: "123".to_string(),
+                serial: 570,
+                audit_time: 1698576562955,
+            })
+            .await
+            .unwrap();
+        sqlite_view.flush().await.unwrap();
+        let export = directory.path().join("export");
+
+        let parts = export_accesses(db_path, &QueryFilter::default(), &export).unwrap();
+
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].starts_with(export.join("date=2023-10-29").join("key=READ")));
+        let rows = read_part(&parts[0]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].audit_time, 1698576562955);
+        assert_eq!(rows[0].user.as_deref(), Some("maciek"));
+        assert_eq!(rows[0].key.as_deref(), Some("READ"));
+        assert_eq!(rows[0].path.as_deref(), Some("/etc/shadow"));
+        assert_eq!(rows[0].pid, None);
+    }
+
+    fn walk(directory: &std::path::Path) -> Vec<String> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => files.extend(walk(&path)),
+                false => files.push(path.to_str().unwrap().to_string()),
+            }
+        }
+        return files;
+    }
+}
@@ -0,0 +1,174 @@
+use crate::filter::{Comparison, Expression, Field, Filter};
+use crate::serializer::{FileOperatedOn, Operation};
+use glob::MatchOptions;
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl Filter {
+    /// Evaluates the filter against an operation, or against a file together with the operation
+    /// it was touched by. A comparison on a field the event does not carry never matches,
+    /// so `path` comparisons only ever match file events.
+    pub(crate) fn matches(
+        &self,
+        operation: Option<&Operation>,
+        file: Option<&FileOperatedOn>,
+    ) -> bool {
+        return self.expression.evaluate(operation, file);
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, operation: Option<&Operation>, file: Option<&FileOperatedOn>) -> bool {
+        return match self {
+            Expression::And(left, right) => {
+                left.evaluate(operation, file) && right.evaluate(operation, file)
+            }
+            Expression::Or(left, right) => {
+                left.evaluate(operation, file) || right.evaluate(operation, file)
+            }
+            Expression::Not(inner) => !inner.evaluate(operation, file),
+            Expression::Comparison(field, comparison) => match field.value_of(operation, file) {
+                Some(value) => comparison.holds_for(*field, &value),
+                None => false,
+            },
+        };
+    }
+}
+
+impl Field {
+    fn value_of(
+        &self,
+        operation: Option<&Operation>,
+        file: Option<&FileOperatedOn>,
+    ) -> Option<String> {
+        return match self {
+            Field::Key => operation.map(|x| x.key.to_string()),
+            Field::User => operation.map(|x| x.user.clone()),
+            Field::Group => operation.map(|x| x.group.clone()),
+            Field::Executable => operation.map(|x| x.executable.clone()),
+            Field::Syscall => operation.map(|x| x.syscall.clone()),
+            Field::Success => operation.map(|x| x.success.to_string()),
+            Field::Path => file.map(|x| x.name.clone()),
+        };
+    }
+}
+
+impl Comparison {
+    fn holds_for(&self, field: Field, value: &str) -> bool {
+        return match self {
+            Comparison::Equals(expected) => equal(field, value, expected),
+            Comparison::NotEquals(expected) => !equal(field, value, expected),
+            Comparison::Matches(pattern) => pattern.matches_with(value, GLOB_OPTIONS),
+        };
+    }
+}
+
+/// `success` is compared to `yes` and `no` the way audit logs spell it, every other field literally.
+fn equal(field: Field, value: &str, expected: &str) -> bool {
+    return match (field, expected) {
+        (Field::Success, "yes") => value == "true",
+        (Field::Success, "no") => value == "false",
+        _ => value == expected,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::Filter;
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::test_fixtures::{read_file, read_operation, READ_LOG_LINE};
+
+    fn matches(filter: &str, operation: Option<&Operation>, file: Option<&FileOperatedOn>) -> bool {
+        return Filter::parse(filter).unwrap().matches(operation, file);
+    }
+
+    #[test]
+    fn should_match_operation_fields() {
+        let operation = read_operation();
+        assert!(matches("key == READ", Some(&operation), None));
+        assert!(matches(
+            "user == maciek and executable == /usr/bin/ls",
+            Some(&operation),
+            None
+        ));
+        assert!(matches("success == yes", Some(&operation), None));
+        assert!(matches(
+            "executable ~ \"/usr/bin/*\"",
+            Some(&operation),
+            None
+        ));
+        assert!(!matches("key == WRITE", Some(&operation), None));
+        assert!(!matches("key != READ", Some(&operation), None));
+        assert!(!matches("success == no", Some(&operation), None));
+    }
+
+    #[test]
+    fn yes_and_no_should_be_literal_outside_success() {
+        let operation = Operation::new(READ_LOG_LINE.replace("\"maciek\"", "\"yes\"")).unwrap();
+        assert!(matches("user == yes", Some(&operation), None));
+        assert!(!matches("user == true", Some(&operation), None));
+        assert!(!matches("group != yes", Some(&operation), None));
+    }
+
+    #[test]
+    fn should_match_paths_by_glob() {
+        let operation = read_operation();
+        let shadow = read_file("/etc/shadow");
+        let sshd_config = read_file("/etc/ssh/sshd_config");
+        let home = read_file("/home/maciek/notes");
+        assert!(matches(
+            "path ~ \"/etc/*\"",
+            Some(&operation),
+            Some(&shadow)
+        ));
+        assert!(!matches(
+            "path ~ \"/etc/*\"",
+            Some(&operation),
+            Some(&sshd_config)
+        ));
+        assert!(matches(
+            "path ~ \"/etc/**\"",
+            Some(&operation),
+            Some(&sshd_config)
+        ));
+        assert!(!matches(
+            "key == READ and path ~ \"/etc/**\"",
+            Some(&operation),
+            Some(&home)
+        ));
+        assert!(matches("path == /home/maciek/notes", None, Some(&home)));
+    }
+
+    #[test]
+    fn should_combine_with_precedence() {
+        let operation = read_operation();
+        assert!(matches(
+            "key == WRITE or user == maciek and success == yes",
+            Some(&operation),
+            None
+        ));
+        assert!(!matches(
+            "(key == WRITE or user == maciek) and success == no",
+            Some(&operation),
+            None
+        ));
+        assert!(matches("not key == WRITE", Some(&operation), None));
+        assert!(!matches("not (key == READ)", Some(&operation), None));
+    }
+
+    #[test]
+    fn should_not_match_fields_missing_from_event() {
+        let operation = read_operation();
+        assert!(!matches("path ~ \"/**\"", Some(&operation), None));
+        assert!(!matches("path != /etc/shadow", Some(&operation), None));
+        assert!(!matches(
+            "user == maciek",
+            None,
+            Some(&read_file("/etc/shadow"))
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::filter::{Comparison, Expression, Field, Filter, FilterParseError};
+use glob::Pattern;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    Matches,
+    OpenParen,
+    CloseParen,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    current: usize,
+    length: usize,
+}
+
+impl Filter {
+    pub(crate) fn parse(source: &str) -> Result<Self, FilterParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            current: 0,
+            length: source.len(),
+        };
+        let expression = parser.parse_or()?;
+        if let Some((token, position)) = parser.tokens.get(parser.current) {
+            return Err(error(format!("unexpected {:?}", token), *position));
+        }
+        return Ok(Self { expression });
+    }
+}
+
+impl Parser {
+    fn parse_or(&mut self) -> Result<Expression, FilterParseError> {
+        let mut expression = self.parse_and()?;
+        while self.next_is_keyword("or") {
+            self.current += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        return Ok(expression);
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, FilterParseError> {
+        let mut expression = self.parse_unary()?;
+        while self.next_is_keyword("and") {
+            self.current += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.parse_unary()?));
+        }
+        return Ok(expression);
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, FilterParseError> {
+        if self.next_is_keyword("not") {
+            self.current += 1;
+            return Ok(Expression::Not(Box::new(self.parse_unary()?)));
+        }
+        let (token, position) = self.advance()?;
+        match token {
+            Token::OpenParen => {
+                let expression = self.parse_or()?;
+                match self.advance()? {
+                    (Token::CloseParen, _) => Ok(expression),
+                    (token, position) => {
+                        Err(error(format!("expected ')', found {:?}", token), position))
+                    }
+                }
+            }
+            Token::Word(name) => {
+                let field = parse_field(&name, position)?;
+                let (operator, operator_position) = self.advance()?;
+                let value = match self.advance()? {
+                    (Token::Word(value), _) | (Token::Quoted(value), _) => value,
+                    (token, position) => {
+                        return Err(error(
+                            format!("expected a value, found {:?}", token),
+                            position,
+                        ))
+                    }
+                };
+                let comparison = match operator {
+                    Token::Equals => Comparison::Equals(value),
+                    Token::NotEquals => Comparison::NotEquals(value),
+                    Token::Matches => Comparison::Matches(
+                        Pattern::new(&value)
+                            .map_err(|e| error(e.msg.to_string(), operator_position))?,
+                    ),
+                    token => {
+                        return Err(error(
+                            format!("expected '==', '!=' or '~', found {:?}", token),
+                            operator_position,
+                        ))
+                    }
+                };
+                Ok(Expression::Comparison(field, comparison))
+            }
+            token => Err(error(
+                format!("expected a field, found {:?}", token),
+                position,
+            )),
+        }
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        return matches!(self.tokens.get(self.current), Some((Token::Word(word), _)) if word == keyword);
+    }
+
+    fn advance(&mut self) -> Result<(Token, usize), FilterParseError> {
+        let token = self
+            .tokens
+            .get(self.current)
+            .cloned()
+            .ok_or_else(|| error("unexpected end of filter".to_string(), self.length))?;
+        self.current += 1;
+        return Ok(token);
+    }
+}
+
+fn parse_field(name: &str, position: usize) -> Result<Field, FilterParseError> {
+    return match name {
+        "key" => Ok(Field::Key),
+        "user" => Ok(Field::User),
+        "group" => Ok(Field::Group),
+        "executable" => Ok(Field::Executable),
+        "syscall" => Ok(Field::Syscall),
+        "success" => Ok(Field::Success),
+        "path" => Ok(Field::Path),
+        _ => Err(error(format!("unknown field '{}'", name), position)),
+    };
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, FilterParseError> {
+    let mut tokens = Vec::new();
+    let mut characters = source.char_indices().peekable();
+    while let Some((position, character)) = characters.next() {
+        match character {
+            _ if character.is_whitespace() => {}
+            '(' => tokens.push((Token::OpenParen, position)),
+            ')' => tokens.push((Token::CloseParen, position)),
+            '~' => tokens.push((Token::Matches, position)),
+            '=' | '!' => match characters.next() {
+                Some((_, '=')) if character == '=' => tokens.push((Token::Equals, position)),
+                Some((_, '=')) => tokens.push((Token::NotEquals, position)),
+                _ => {
+                    return Err(error(
+                        format!("expected '=' after '{}'", character),
+                        position,
+                    ))
+                }
+            },
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match characters.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match characters.next() {
+                            Some((_, escaped)) => value.push(escaped),
+                            None => return Err(error("unterminated string".to_string(), position)),
+                        },
+                        Some((_, other)) => value.push(other),
+                        None => return Err(error("unterminated string".to_string(), position)),
+                    }
+                }
+                tokens.push((Token::Quoted(value), position));
+            }
+            _ => {
+                let mut value = String::from(character);
+                while let Some((_, next)) = characters.peek() {
+                    if next.is_whitespace() || "()~=!\"".contains(*next) {
+                        break;
+                    }
+                    value.push(*next);
+                    characters.next();
+                }
+                tokens.push((Token::Word(value), position));
+            }
+        }
+    }
+    return Ok(tokens);
+}
+
+fn error(message: String, position: usize) -> FilterParseError {
+    return FilterParseError { message, position };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{Filter, FilterParseError};
+
+    #[test]
+    fn should_parse_nested_expression() {
+        let filter =
+            Filter::parse(r#"not (user == root or user == "daemon") and path ~ "/etc/**""#);
+        assert!(filter.is_ok());
+    }
+
+    #[test]
+    fn should_reject_unknown_field() {
+        let filter = Filter::parse("owner == root");
+        assert_eq!(
+            filter.unwrap_err(),
+            FilterParseError {
+                message: "unknown field 'owner'".to_string(),
+                position: 0
+            }
+        );
+    }
+
+    #[test]
+    fn should_reject_incomplete_expression() {
+        assert!(Filter::parse("key == WRITE and").is_err());
+        assert!(Filter::parse("(key == WRITE").is_err());
+        assert!(Filter::parse("key = WRITE").is_err());
+        assert!(Filter::parse("key == WRITE user == root").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+use glob::Pattern;
+use std::error::Error;
+use std::fmt;
+
+mod evaluate;
+mod filter_parse;
+
+/// A parsed filter expression, e.g. `key == WRITE and path ~ "/etc/**"`.
+///
+/// Comparisons are `==`, `!=` and `~` (glob match), combined with `and`, `or`, `not`
+/// and parentheses. The fields are `key`, `user`, `group`, `executable`, `syscall`,
+/// `success` and `path`.
+#[derive(Debug, Clone)]
+pub(crate) struct Filter {
+    expression: Expression,
+}
+
+#[derive(Debug, Clone)]
+enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Comparison(Field, Comparison),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Key,
+    User,
+    Group,
+    Executable,
+    Syscall,
+    Success,
+    Path,
+}
+
+#[derive(Debug, Clone)]
+enum Comparison {
+    Equals(String),
+    NotEquals(String),
+    Matches(Pattern),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct FilterParseError {
+    message: String,
+    position: usize,
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Error: invalid filter at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl Error for FilterParseError {}
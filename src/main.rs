@@ -1,17 +1,80 @@
+mod admin;
+mod cli;
 mod controller;
+mod export;
+mod filter;
+mod query;
 mod serializer;
 mod settings;
+#[cfg(test)]
+mod test_fixtures;
 mod view;
+use crate::cli::{Command, ExportCommand, QueryCommand, USAGE};
+use crate::controller::dead_letter::FailedEvents;
 use crate::controller::unix_port::{UnixSocketPort, UnixSocketSettings};
 use crate::controller::InputPort;
-use crate::settings::{configure, LogSettings, ViewMode};
-use crate::view::{HttpView, MockView, SqliteView, View};
+use crate::filter::Filter;
+use crate::settings::{configure, LogSettings, ViewDefinition, ViewMode};
+use crate::view::{
+    CompositeView, ElasticsearchView, FileView, FluentView, GelfView, HttpView, KafkaView,
+    LokiView, MockView, OtlpView, ParquetView, PostgresView, SinkDefinition, SplunkView,
+    SqliteView, StdoutView, SyslogView, View, ViewContext,
+};
 use colored::Colorize;
 use log::Level;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::signal::unix::{signal, SignalKind};
 
 static SETTINGS_ADDRESS: &str = "./resources/settings.json";
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    return match Command::parse(&arguments, now) {
+        Ok(Command::Run) => run().await,
+        Ok(Command::Query(command)) => query(*command),
+        Ok(Command::Export(command)) => export(*command),
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+}
+
+fn query(command: QueryCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = match command.db_path {
+        Some(db_path) => db_path,
+        None => configure(SETTINGS_ADDRESS)?.sqlite_db_path().to_string(),
+    };
+    let records = query::find_accesses(&db_path, &command.filter)?;
+    query::write_records(&records, command.format, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+fn export(command: ExportCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let db_path = match command.db_path {
+        Some(db_path) => db_path,
+        None => configure(SETTINGS_ADDRESS)?.sqlite_db_path().to_string(),
+    };
+    let parts = export::export_accesses(
+        &db_path,
+        &command.filter,
+        std::path::Path::new(&command.directory),
+    )?;
+    println!(
+        "Wrote {} Parquet files to {}",
+        parts.len(),
+        command.directory
+    );
+    Ok(())
+}
+
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let configs = configure(SETTINGS_ADDRESS)?;
     simple_logger::init_with_level(match configs.log_level {
         LogSettings::Debug => Level::Debug,
@@ -19,26 +82,120 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     })?;
     log::debug!("Loaded settings from: {}", SETTINGS_ADDRESS.cyan());
 
-    log::info!(
-        "Dispatcher path chosen was: {} ; Chosen view method was: {}.",
-        configs.dispatcher_directory.red(),
-        configs.view_mode.to_string().green(),
-    );
-
-    let view: Box<dyn View> = match configs.view_mode {
-        ViewMode::Http => Box::new(HttpView::new(
-            configs.http_settings.http_destination.as_str(),
-        )),
-        ViewMode::Mock => Box::new(MockView {}),
-        ViewMode::Sqlite => Box::new(SqliteView::new(configs.sqlite_settings.db_path.as_str())),
+    if let Some(admin_address) = &configs.admin_address {
+        match admin::spawn(admin_address) {
+            Ok(bound) => log::info!(
+                "Serving /metrics, /healthz and /readyz on {}",
+                format!("http://{}", bound).cyan()
+            ),
+            Err(e) => log::error!(
+                "{} {}, running without it: {}",
+                "Error: admin server cannot listen on".red(),
+                admin_address,
+                e
+            ),
+        }
+    }
+    let failed_events = Arc::new(FailedEvents::new(configs.dead_letter_path.as_deref()));
+    let context = ViewContext {
+        failed_events: failed_events.clone(),
+        ..Default::default()
+    };
+    let view_name = configs
+        .views
+        .is_empty()
+        .then(|| configs.view.display_name());
+    let view: Box<dyn View> = match configs.views.is_empty() {
+        true => {
+            log::info!(
+                "Dispatcher path chosen was: {} ; Chosen view method was: {}.",
+                configs.dispatcher_directory.red(),
+                configs.view.view_mode.to_string().green(),
+            );
+            admin::health().register_view(&configs.view.display_name(), configs.view.error_budget);
+            create_view(&configs.view, &context).await
+        }
+        false => {
+            log::info!(
+                "Dispatcher path chosen was: {} ; Chosen views were: {}.",
+                configs.dispatcher_directory.red(),
+                configs
+                    .views
+                    .iter()
+                    .map(|definition| definition.display_name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .green(),
+            );
+            let mut sinks = Vec::new();
+            for definition in configs.views.iter() {
+                admin::health().register_view(&definition.display_name(), definition.error_budget);
+                sinks.push(SinkDefinition {
+                    name: definition.display_name(),
+                    view: create_view(definition, &context).await,
+                    queue_capacity: definition.queue_capacity,
+                    filter: definition
+                        .filter
+                        .as_deref()
+                        .map(Filter::parse)
+                        .transpose()?,
+                });
+            }
+            Box::new(CompositeView::new(sinks, failed_events.clone()))
+        }
     };
 
     let port_settings = UnixSocketSettings {
         socket_path: configs.dispatcher_directory,
+        failed_events,
+        view_name,
     };
     let port = UnixSocketPort::new(port_settings, view);
-    port.receive()
-        .await
-        .expect("Fatal: Port cannot receive inputs.");
+    let received = tokio::select! {
+        received = port.receive() => received,
+        _ = shutdown_requested() => {
+            log::info!("{}", "Shutting down.".yellow());
+            Ok(())
+        }
+    };
+    port.flush().await;
+    received.expect("Fatal: Port cannot receive inputs.");
     Ok(())
 }
+
+async fn shutdown_requested() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Fatal: cannot listen for SIGTERM.");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+async fn create_view(definition: &ViewDefinition, context: &ViewContext) -> Box<dyn View> {
+    let context = &context.of(definition);
+    return match definition.view_mode {
+        ViewMode::Http => Box::new(HttpView::new(
+            definition.http_settings.http_destination.as_str(),
+            definition.http_settings.event_format,
+        )),
+        ViewMode::Mock => Box::new(MockView {}),
+        ViewMode::Sqlite => Box::new(SqliteView::new(&definition.sqlite_settings, context)),
+        ViewMode::Postgres => {
+            Box::new(PostgresView::new(&definition.postgres_settings, context).await)
+        }
+        ViewMode::Elasticsearch => Box::new(ElasticsearchView::new(
+            &definition.elasticsearch_settings,
+            context,
+        )),
+        ViewMode::Loki => Box::new(LokiView::new(&definition.loki_settings, context)),
+        ViewMode::Splunk => Box::new(SplunkView::new(&definition.splunk_settings, context)),
+        ViewMode::Kafka => Box::new(KafkaView::new(&definition.kafka_settings, context)),
+        ViewMode::Otlp => Box::new(OtlpView::new(&definition.otlp_settings, context)),
+        ViewMode::File => Box::new(FileView::new(&definition.file_settings)),
+        ViewMode::Syslog => Box::new(SyslogView::new(&definition.syslog_settings)),
+        ViewMode::Gelf => Box::new(GelfView::new(&definition.gelf_settings)),
+        ViewMode::Fluent => Box::new(FluentView::new(&definition.fluent_settings, context)),
+        ViewMode::Parquet => Box::new(ParquetView::new(&definition.parquet_settings, context)),
+        ViewMode::Stdout => Box::new(StdoutView::new(&definition.stdout_settings)),
+    };
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+mod output;
+mod sqlite_query;
+
+/// Which file accesses to look up in the Sqlite store. Every criterion set has to hold,
+/// times are milliseconds since the epoch. Events match the `search` terms when any of them
+/// is part of one of their paths, executables or command lines.
+#[derive(Debug, PartialEq, Default)]
+pub(crate) struct QueryFilter {
+    pub(crate) path: Option<PathMatch>,
+    pub(crate) user: Option<String>,
+    pub(crate) executable: Option<String>,
+    pub(crate) key: Option<String>,
+    pub(crate) syscall: Option<String>,
+    pub(crate) search: Vec<String>,
+    pub(crate) since: Option<u64>,
+    pub(crate) until: Option<u64>,
+    pub(crate) limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum PathMatch {
+    Exact(String),
+    Prefix(String),
+    Glob(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// An operation together with one of the files it touched, if it touched any.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct AccessRecord {
+    pub(crate) audit_time: u64,
+    pub(crate) serial: u64,
+    pub(crate) user: String,
+    pub(crate) group: String,
+    pub(crate) executable: String,
+    pub(crate) command: String,
+    pub(crate) syscall: String,
+    pub(crate) key: String,
+    pub(crate) path: Option<String>,
+}
+
+pub(crate) use output::write_records;
+pub(crate) use sqlite_query::{find_accesses, for_each_access};
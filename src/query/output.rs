@@ -0,0 +1,152 @@
+use crate::query::{AccessRecord, OutputFormat};
+use chrono::{DateTime, SecondsFormat};
+use std::io;
+use std::io::Write;
+
+const HEADER: [&str; 9] = [
+    "time",
+    "serial",
+    "user",
+    "group",
+    "executable",
+    "command",
+    "syscall",
+    "key",
+    "path",
+];
+
+pub(crate) fn write_records(
+    records: &[AccessRecord],
+    format: OutputFormat,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    return match format {
+        OutputFormat::Table => write_table(records, out),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)
+        }
+        OutputFormat::Csv => write_csv(records, out),
+    };
+}
+
+fn columns_of(record: &AccessRecord) -> [String; 9] {
+    return [
+        format_time(record.audit_time),
+        record.serial.to_string(),
+        record.user.clone(),
+        record.group.clone(),
+        record.executable.clone(),
+        record.command.clone(),
+        record.syscall.clone(),
+        record.key.clone(),
+        record.path.clone().unwrap_or_default(),
+    ];
+}
+
+fn format_time(audit_time: u64) -> String {
+    return DateTime::from_timestamp_millis(audit_time as i64)
+        .map(|x| x.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| audit_time.to_string());
+}
+
+fn write_table(records: &[AccessRecord], out: &mut impl Write) -> io::Result<()> {
+    let rows: Vec<[String; 9]> = records.iter().map(columns_of).collect();
+    let mut widths = HEADER.map(|x| x.chars().count());
+    for row in rows.iter() {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+    let header = HEADER.map(|x| x.to_string());
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{:<width$}", column, width = width))
+            .collect();
+        writeln!(out, "{}", line.join("  ").trim_end())?;
+    }
+    return Ok(());
+}
+
+fn write_csv(records: &[AccessRecord], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "{}", HEADER.join(","))?;
+    for record in records {
+        let line: Vec<String> = columns_of(record).iter().map(|x| escape_csv(x)).collect();
+        writeln!(out, "{}", line.join(","))?;
+    }
+    return Ok(());
+}
+
+fn escape_csv(value: &str) -> String {
+    return match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::query::{write_records, AccessRecord, OutputFormat};
+
+    fn record(path: Option<&str>) -> AccessRecord {
+        return AccessRecord {
+            audit_time: 1698576562955,
+            serial: 570,
+            user: "maciek".to_string(),
+            group: "maciek".to_string(),
+            executable: "/usr/bin/cat".to_string(),
+            command: "cat /etc/shadow".to_string(),
+            syscall: "unknown".to_string(),
+            key: "READ".to_string(),
+            path: path.map(|x| x.to_string()),
+        };
+    }
+
+    fn written(records: &[AccessRecord], format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        write_records(records, format, &mut out).unwrap();
+        return String::from_utf8(out).unwrap();
+    }
+
+    #[test]
+    fn table_should_align_columns() {
+        let output = written(
+            &[record(Some("/etc/shadow")), record(None)],
+            OutputFormat::Table,
+        );
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "time                      serial  user    group   executable    command          syscall  key   path"
+        );
+        assert_eq!(
+            lines[1],
+            "2023-10-29T10:49:22.955Z  570     maciek  maciek  /usr/bin/cat  cat /etc/shadow  unknown  READ  /etc/shadow"
+        );
+        assert_eq!(
+            lines[2],
+            "2023-10-29T10:49:22.955Z  570     maciek  maciek  /usr/bin/cat  cat /etc/shadow  unknown  READ"
+        );
+    }
+
+    #[test]
+    fn csv_should_quote_special_characters() {
+        let output = written(&[record(Some("/tmp/a,\"b\""))], OutputFormat::Csv);
+        assert_eq!(
+            output,
+            "time,serial,user,group,executable,command,syscall,key,path\n\
+             2023-10-29T10:49:22.955Z,570,maciek,maciek,/usr/bin/cat,cat /etc/shadow,unknown,READ,\"/tmp/a,\"\"b\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn json_should_hold_every_field() {
+        let output = written(&[record(Some("/etc/shadow"))], OutputFormat::Json);
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed[0]["audit_time"], 1698576562955u64);
+        assert_eq!(parsed[0]["path"], "/etc/shadow");
+        assert_eq!(parsed[0]["user"], "maciek");
+    }
+}
@@ -0,0 +1,298 @@
+use crate::query::{AccessRecord, PathMatch, QueryFilter};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::error::Error;
+
+const SEARCHED_EVENTS: &str = r#"e.id IN (
+    SELECT f.event_id FROM file_search s JOIN operated_on_files f ON f.id = s.rowid WHERE file_search MATCH ?
+    UNION
+    SELECT o.event_id FROM operation_search s JOIN operations o ON o.id = s.rowid WHERE operation_search MATCH ?
+)"#;
+
+const SELECT_ACCESSES: &str = r#"
+SELECT e.audit_time, e.serial, o.user, o.users_group, o.executable, o.command, o.syscall, o.operation_key, f.absolute_path
+FROM events e
+JOIN operations o ON o.event_id = e.id
+LEFT JOIN operated_on_files f ON f.event_id = e.id
+"#;
+
+/// Looks up the newest accesses matching the filter, newest first. The database is opened read only,
+/// so it can be queried while the agent keeps writing to it.
+pub(crate) fn find_accesses(
+    db_path: &str,
+    filter: &QueryFilter,
+) -> Result<Vec<AccessRecord>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    for_each_access(db_path, filter, |record| {
+        records.push(record);
+        return Ok(());
+    })?;
+    return Ok(records);
+}
+
+/// Hands the accesses matching the filter over one by one, in the order of [`find_accesses`],
+/// without keeping them all in memory.
+pub(crate) fn for_each_access(
+    db_path: &str,
+    filter: &QueryFilter,
+    mut consume: impl FnMut(AccessRecord) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let connection = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let (conditions, parameters) = conditions_of(filter);
+    let mut query = String::from(SELECT_ACCESSES);
+    if !conditions.is_empty() {
+        query.push_str("WHERE ");
+        query.push_str(&conditions.join(" AND "));
+    }
+    query.push_str(" ORDER BY e.audit_time DESC, e.serial DESC, f.absolute_path");
+    if let Some(limit) = filter.limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+    let mut statement = connection.prepare(&query)?;
+    let records = statement.query_map(params_from_iter(parameters), |row| {
+        Ok(AccessRecord {
+            audit_time: row.get(0)?,
+            serial: row.get(1)?,
+            user: row.get(2)?,
+            group: row.get(3)?,
+            executable: row.get(4)?,
+            command: row.get(5)?,
+            syscall: row.get(6)?,
+            key: row.get(7)?,
+            path: row.get(8)?,
+        })
+    })?;
+    for record in records {
+        consume(record?)?;
+    }
+    return Ok(());
+}
+
+fn conditions_of(filter: &QueryFilter) -> (Vec<String>, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut parameters: Vec<Value> = Vec::new();
+    let mut condition = |sql: &str, value: Value| {
+        parameters.push(value);
+        conditions.push(sql.replace('?', &format!("?{}", parameters.len())));
+    };
+    match &filter.path {
+        Some(PathMatch::Exact(path)) => condition("f.absolute_path = ?", Value::Text(path.clone())),
+        Some(PathMatch::Prefix(prefix)) => condition(
+            "f.absolute_path GLOB ?",
+            Value::Text(format!("{}*", escape_glob(prefix))),
+        ),
+        Some(PathMatch::Glob(pattern)) => {
+            condition("f.absolute_path GLOB ?", Value::Text(pattern.clone()))
+        }
+        None => {}
+    }
+    let columns = [
+        ("o.user", &filter.user),
+        ("o.executable", &filter.executable),
+        ("o.operation_key", &filter.key),
+        ("o.syscall", &filter.syscall),
+    ];
+    for (column, value) in columns {
+        if let Some(value) = value {
+            condition(&format!("{} = ?", column), Value::Text(value.clone()));
+        }
+    }
+    if !filter.search.is_empty() {
+        condition(
+            SEARCHED_EVENTS,
+            Value::Text(search_expression(&filter.search)),
+        );
+    }
+    if let Some(since) = filter.since {
+        condition("e.audit_time >= ?", Value::Integer(since as i64));
+    }
+    if let Some(until) = filter.until {
+        condition("e.audit_time < ?", Value::Integer(until as i64));
+    }
+    return (conditions, parameters);
+}
+
+/// Any of the terms, each quoted so it is matched literally rather than read as query syntax.
+fn search_expression(terms: &[String]) -> String {
+    return terms
+        .iter()
+        .map(|x| format!("\"{}\"", x.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+}
+
+/// Puts the glob metacharacters of a literal path into brackets, so they only match themselves.
+fn escape_glob(literal: &str) -> String {
+    return literal
+        .chars()
+        .map(|x| match x {
+            '*' | '?' | '[' => format!("[{}]", x),
+            _ => x.to_string(),
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::query::{find_accesses, PathMatch, QueryFilter};
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::settings::SqliteSettings;
+    use crate::view::{SqliteView, View, ViewContext};
+    use tempfile::tempdir;
+
+    const SYSCALL_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 comm=\"cat\" exe=\"/usr/bin/cat\" key=\"READ\"ARCH=x86_64 UID=\"maciek\" GID=\"maciek\"";
+    const PATH_LINE: &str =
+        "type=PATH msg=audit(1698576562.955:570): item=0 name=\"/etc/shadow\" inode=409248";
+
+    /// Stores `events` of the form (user, key, path, serial, time in seconds).
+    async fn store(db_path: &str, events: &[(&str, &str, &str, u64, u64)]) {
+        let sqlite_view = SqliteView::new(&SqliteSettings::new(db_path), &ViewContext::default());
+        for (user, key, path, serial, time) in events {
+            let stamp = format!("{}.000:{}", time, serial);
+            let operation = SYSCALL_LINE
+                .replace("maciek", user)
+                .replace("READ", key)
+                .replace("1698576562.955:570", &stamp);
+            let file = PATH_LINE
+                .replace("/etc/shadow", path)
+                .replace("1698576562.955:570", &stamp);
+            sqlite_view
+                .update(Operation::new(operation).unwrap())
+                .await
+                .unwrap();
+            for file in FileOperatedOn::new(file, time.to_string()).unwrap() {
+                sqlite_view.report(file).await.unwrap();
+            }
+        }
+        sqlite_view.flush().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_find_who_touched_file_in_time_range() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        store(
+            db_path,
+            &[
+                ("maciek", "READ", "/etc/shadow", 1, 1_000),
+                ("root", "WRITE", "/etc/shadow", 2, 90_000),
+                ("daemon", "READ", "/etc/passwd", 3, 95_000),
+                ("maciek", "READ", "/etc/shadow", 4, 99_000),
+            ],
+        )
+        .await;
+        let filter = QueryFilter {
+            path: Some(PathMatch::Exact("/etc/shadow".to_string())),
+            since: Some(10_000_000),
+            ..Default::default()
+        };
+
+        let records = find_accesses(db_path, &filter).unwrap();
+
+        let users: Vec<_> = records
+            .iter()
+            .map(|x| (x.user.as_str(), x.serial))
+            .collect();
+        assert_eq!(users, vec![("maciek", 4), ("root", 2)]);
+        assert_eq!(records[0].executable, "/usr/bin/cat");
+        assert_eq!(records[0].audit_time, 99_000_000);
+    }
+
+    #[tokio::test]
+    async fn should_search_paths_and_command_lines() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        store(
+            db_path,
+            &[
+                ("maciek", "READ", "/home/maciek/.ssh/id_rsa.pub", 1, 1_000),
+                ("maciek", "READ", "/home/maciek/notes.txt", 2, 2_000),
+                ("root", "READ", "/root/.ssh/ID_RSA", 3, 3_000),
+            ],
+        )
+        .await;
+        let sqlite_view = SqliteView::new(&SqliteSettings::new(db_path), &ViewContext::default());
+        let curl = SYSCALL_LINE
+            .replace(
+                "\"cat\" exe=\"/usr/bin/cat\"",
+                "\"curl\" exe=\"/usr/bin/curl\"",
+            )
+            .replace("1698576562.955:570", "4000.000:4");
+        sqlite_view
+            .update(Operation::new(curl).unwrap())
+            .await
+            .unwrap();
+        sqlite_view.flush().await.unwrap();
+        let search = |terms: &[&str], user: Option<&str>| -> Vec<u64> {
+            let filter = QueryFilter {
+                search: terms.iter().map(|x| x.to_string()).collect(),
+                user: user.map(|x| x.to_string()),
+                ..Default::default()
+            };
+            return find_accesses(db_path, &filter)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.serial)
+                .collect();
+        };
+
+        assert_eq!(search(&["id_rsa", "curl"], None), vec![4, 3, 1]);
+        assert_eq!(search(&["id_rsa"], Some("maciek")), vec![1]);
+        assert_eq!(search(&[".ssh/\"x"], None), Vec::<u64>::new());
+        assert_eq!(search(&["cat"], None), vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn should_find_what_user_modified_by_prefix_and_glob() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        store(
+            db_path,
+            &[
+                ("root", "WRITE", "/etc/ssh/sshd_config", 1, 1_000),
+                ("root", "WRITE", "/etc/hosts", 2, 2_000),
+                ("root", "READ", "/etc/ssh/ssh_config", 3, 3_000),
+                ("maciek", "WRITE", "/etc/ssh/moduli", 4, 4_000),
+                ("root", "WRITE", "/etc/s*h/literal", 5, 5_000),
+            ],
+        )
+        .await;
+        let by_prefix = QueryFilter {
+            path: Some(PathMatch::Prefix("/etc/ssh/".to_string())),
+            user: Some("root".to_string()),
+            key: Some("WRITE".to_string()),
+            ..Default::default()
+        };
+        let by_glob = QueryFilter {
+            path: Some(PathMatch::Glob("/etc/s*h/*".to_string())),
+            user: Some("root".to_string()),
+            ..Default::default()
+        };
+        let literal_prefix = QueryFilter {
+            path: Some(PathMatch::Prefix("/etc/s*h".to_string())),
+            limit: Some(10),
+            ..Default::default()
+        };
+
+        let paths = |filter: &QueryFilter| -> Vec<String> {
+            return find_accesses(db_path, filter)
+                .unwrap()
+                .into_iter()
+                .map(|x| x.path.unwrap())
+                .collect();
+        };
+        assert_eq!(paths(&by_prefix), vec!["/etc/ssh/sshd_config"]);
+        assert_eq!(
+            paths(&by_glob),
+            vec![
+                "/etc/s*h/literal",
+                "/etc/ssh/ssh_config",
+                "/etc/ssh/sshd_config"
+            ]
+        );
+        assert_eq!(paths(&literal_prefix), vec!["/etc/s*h/literal"]);
+    }
+}
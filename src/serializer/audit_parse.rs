@@ -6,25 +6,37 @@ use std::error::Error;
 use std::string::ToString;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-const UNKNOWN_FIELD: &'static str = "unknown";
+const UNKNOWN_FIELD: &str = "unknown";
 
-const USERNAME_KEY: &'static str = "UID";
+const USERNAME_KEY: &str = "UID";
 
-const GROUP_KEY: &'static str = "GID";
+const GROUP_KEY: &str = "GID";
 
-const EXECUTABLE_KEY: &'static str = "exe";
+const EXECUTABLE_KEY: &str = "exe";
 
-const SYSCALL_KEY: &'static str = "SYSCALL";
+const COMMAND_KEY: &str = "comm";
 
-const OPERATION_KEY: &'static str = "key";
-const NAME_KEY: &'static str = "name";
-const PATH_DELIMITER: &'static str = "/";
-const PATH_KEY: &'static str = "PATH";
+const PROCTITLE_KEY: &str = "proctitle";
+
+const PID_KEY: &str = "pid";
+
+const SYSCALL_KEY: &str = "SYSCALL";
+
+const OPERATION_KEY: &str = "key";
+const SUCCESS_KEY: &str = "success";
+const SUCCESS_VALUE: &str = "yes";
+const NAME_KEY: &str = "name";
+const PATH_DELIMITER: &str = "/";
+const PATH_KEY: &str = "PATH";
+const MESSAGE_KEY: &str = "msg";
+const MESSAGE_PREFIX: &str = "audit(";
 
 impl Operation {
+    #[allow(clippy::or_then_unwrap)]
     pub(crate) fn new(log_output: String) -> Option<Self> {
         let values_map = map_of_values!(log_output);
         if values_map.contains_key(OPERATION_KEY) {
+            let (audit_time, serial) = LogParsingUtils::get_event_id(&values_map)?;
             return Some(Self {
                 user: unescape(
                     values_map
@@ -50,6 +62,8 @@ impl Operation {
                 )
                 .unwrap()
                 .to_string(),
+                command: LogParsingUtils::get_command(&values_map),
+                pid: values_map.get(PID_KEY).and_then(|x| x.parse().ok()),
                 syscall: unescape(
                     values_map
                         .get(SYSCALL_KEY)
@@ -66,6 +80,11 @@ impl Operation {
                         .unwrap()
                         .to_string(),
                 ),
+                success: values_map
+                    .get(SUCCESS_KEY)
+                    .is_some_and(|x| x == SUCCESS_VALUE),
+                serial,
+                audit_time,
             });
         }
         return None;
@@ -82,12 +101,19 @@ impl FileOperatedOn {
             .map(|x| x.to_string())
             .map(|x| map_of_values!(x))
             .filter(|x| x.contains_key(NAME_KEY))
-            .map(|x| x.get(NAME_KEY).unwrap().clone())
-            .map(|x| String::from(&x[1..x.len() - 1]))
-            .filter(|x| !x.ends_with(PATH_DELIMITER))
-            .map(|x| FileOperatedOn {
-                name: x,
+            .filter_map(|x| {
+                let name = x.get(NAME_KEY).unwrap();
+                Some((
+                    String::from(&name[1..name.len() - 1]),
+                    LogParsingUtils::get_event_id(&x)?,
+                ))
+            })
+            .filter(|x| !x.0.ends_with(PATH_DELIMITER))
+            .map(|(name, (audit_time, serial))| FileOperatedOn {
+                name,
                 timestamp: previous_timestamp.clone(),
+                serial,
+                audit_time,
             })
             .collect();
         log::debug!("Lines unfiltered {:?}", lines);
@@ -98,6 +124,7 @@ impl FileOperatedOn {
 struct LogParsingUtils {}
 
 impl LogParsingUtils {
+    #[allow(clippy::useless_conversion)]
     fn create_a_map_of_values(coded_data: String) -> HashMap<String, String> {
         return coded_data
             .split(" ")
@@ -122,6 +149,56 @@ impl LogParsingUtils {
             false => OperationKey::WRITE,
         }
     }
+    /// Reads the time in milliseconds and the serial out of `msg=audit(1698576562.955:570):`.
+    /// Records without them, or with a time too large to count in milliseconds, cannot be told
+    /// apart from other events, so they are left out.
+    fn get_event_id(values_map: &HashMap<String, String>) -> Option<(u64, u64)> {
+        let stamp = values_map
+            .get(MESSAGE_KEY)?
+            .strip_prefix(MESSAGE_PREFIX)?
+            .trim_end_matches(['(', ')', ':']);
+        let (time, serial) = stamp.split_once(':')?;
+        let (seconds, millis) = time.split_once('.').unwrap_or((time, "0"));
+        let audit_time = seconds
+            .parse::<u64>()
+            .ok()?
+            .checked_mul(1000)?
+            .checked_add(millis.parse::<u64>().ok()?)?;
+        return Some((audit_time, serial.parse().ok()?));
+    }
+    /// The command line of a PROCTITLE record, when one came along, or else the process name.
+    /// Command lines are either quoted or hex encoded with arguments separated by NUL bytes.
+    fn get_command(values_map: &HashMap<String, String>) -> String {
+        let proctitle = values_map
+            .get(PROCTITLE_KEY)
+            .and_then(|x| x.split_whitespace().next());
+        if let Some(proctitle) = proctitle {
+            if let Some(decoded) = LogParsingUtils::decode_hex_arguments(proctitle) {
+                return decoded;
+            }
+            return unescape(proctitle).unwrap_or_else(|_| proctitle.to_string());
+        }
+        return values_map
+            .get(COMMAND_KEY)
+            .map(|x| unescape(x).unwrap_or_else(|_| x.clone()))
+            .unwrap_or_else(|| UNKNOWN_FIELD.to_string());
+    }
+    fn decode_hex_arguments(encoded: &str) -> Option<String> {
+        if !encoded.len().is_multiple_of(2) || !encoded.chars().all(|x| x.is_ascii_hexdigit()) {
+            return None;
+        }
+        let bytes = (0..encoded.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .ok()?;
+        let arguments: Vec<_> = bytes
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect();
+        return Some(arguments.join(" "));
+    }
     fn get_unix_time_from_timestamp() -> Result<String, Box<dyn Error>> {
         let start = SystemTime::now();
         let since_the_epoch = start.duration_since(UNIX_EPOCH)?;
@@ -226,6 +303,52 @@ mod test {
     }
 
     #[test]
+    fn should_read_success_flag() {
+        //given
+        let input = String::from(COMPLIANT_LOG_LINE);
+        let failed_input = COMPLIANT_LOG_LINE.replace("success=yes", "success=no");
+        //when
+        let operation = Operation::new(input);
+        let failed_operation = Operation::new(failed_input);
+        //then
+        assert!(operation.unwrap().success);
+        assert!(!failed_operation.unwrap().success);
+    }
+
+    #[test]
+    fn should_read_process_id_if_present() {
+        //given
+        let input = String::from(COMPLIANT_LOG_LINE);
+        let without_pid = COMPLIANT_LOG_LINE.replace(" pid=20680", "");
+        //when
+        let operation = Operation::new(input).unwrap();
+        let without_pid = Operation::new(without_pid).unwrap();
+        //then
+        assert_eq!(operation.pid, Some(20680));
+        assert_eq!(without_pid.pid, None);
+    }
+
+    #[test]
+    fn should_read_command_line_or_process_name() {
+        //given
+        let input = String::from(COMPLIANT_LOG_LINE);
+        let with_proctitle = format!(
+            "{} type=PROCTITLE msg=audit(1698576562.955:570): proctitle=6C73002D6C61002F726F6F74",
+            COMPLIANT_LOG_LINE
+        );
+        let with_quoted_proctitle = format!("{} proctitle=\"top\"", COMPLIANT_LOG_LINE);
+        //when
+        let operation = Operation::new(input).unwrap();
+        let with_proctitle = Operation::new(with_proctitle).unwrap();
+        let with_quoted_proctitle = Operation::new(with_quoted_proctitle).unwrap();
+        //then
+        assert_eq!(operation.command, "ls");
+        assert_eq!(with_proctitle.command, "ls -la /root");
+        assert_eq!(with_quoted_proctitle.command, "top");
+    }
+
+    #[test]
+    #[allow(clippy::get_first)]
     fn should_create_files_operated_on_from_compliant_line() {
         //given
         let input = String::from(FILE_LOG_LINE);
@@ -237,5 +360,41 @@ mod test {
         assert_eq!(unwraped_files.len(), 1);
         assert_eq!(unwraped_files.get(0).unwrap().name, "/etc/ssh/sshd_config");
         assert_eq!(unwraped_files.get(0).unwrap().timestamp, "123");
+        assert_eq!(unwraped_files.get(0).unwrap().serial, 24287);
+        assert_eq!(unwraped_files.get(0).unwrap().audit_time, 1364481363243);
+    }
+
+    #[test]
+    fn should_read_serial_and_time_of_event() {
+        //given
+        let input = String::from(COMPLIANT_LOG_LINE);
+        //when
+        let operation = Operation::new(input).unwrap();
+        //then
+        assert_eq!(operation.serial, 570);
+        assert_eq!(operation.audit_time, 1698576562955);
+    }
+
+    #[test]
+    fn should_leave_out_records_without_serial_and_time() {
+        //given
+        let unstamped_operation = COMPLIANT_LOG_LINE.replace("msg=audit(1698576562.955:570): ", "");
+        let unstamped_file = FILE_LOG_LINE.replace("msg=audit(1364481363.243:24287): ", "");
+        //when
+        let operation = Operation::new(unstamped_operation);
+        let files = FileOperatedOn::new(unstamped_file, "123".to_string());
+        //then
+        assert!(operation.is_none());
+        assert!(files.unwrap().is_empty());
+    }
+    #[test]
+    fn should_leave_out_records_with_overflowing_time() {
+        //given
+        let stamp = format!("msg=audit({}.955:570):", u64::MAX / 1000);
+        let overflowing = COMPLIANT_LOG_LINE.replace("msg=audit(1698576562.955:570):", &stamp);
+        //when
+        let operation = Operation::new(overflowing);
+        //then
+        assert!(operation.is_none());
     }
 }
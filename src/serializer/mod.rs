@@ -3,22 +3,34 @@ use std::fmt;
 
 pub mod audit_parse;
 
-#[derive(Debug, Serialize)]
+/// An audited syscall. Records of one audit event share `serial` and `audit_time`,
+/// the latter in milliseconds since the epoch, as stamped by the kernel.
+#[derive(Debug, Serialize, Clone)]
 pub(crate) struct Operation {
     pub(crate) user: String,
     pub(crate) group: String,
     pub(crate) executable: String,
+    /// The command line when audit recorded it, otherwise the process name.
+    pub(crate) command: String,
+    /// The process id, unless audit left it out.
+    pub(crate) pid: Option<u32>,
     pub(crate) syscall: String,
     pub(crate) timestamp: String,
     pub(crate) key: OperationKey,
+    pub(crate) success: bool,
+    pub(crate) serial: u64,
+    pub(crate) audit_time: u64,
 }
 #[derive(Debug, Serialize, Clone)]
 pub(crate) struct FileOperatedOn {
     pub(crate) name: String,
     pub(crate) timestamp: String,
+    pub(crate) serial: u64,
+    pub(crate) audit_time: u64,
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, PartialEq, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub(crate) enum OperationKey {
     READ,
     WRITE,
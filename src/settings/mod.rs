@@ -1,19 +1,206 @@
 use serde::de::Error;
 use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 
 static SOCKET_ADDRESS: &str = "/var/run/dispatcher";
+static ADMIN_ADDRESS_DEFAULT: &str = "127.0.0.1:9477";
 static HTTP_VIEW_DESTINATION_DEFAULT: &str = "localhost:8080";
 static SQLITE_VIEW_DB_PATH_DEFAULT: &str = "reports.sqlite";
+const VIEW_QUEUE_CAPACITY_DEFAULT: usize = 1024;
+const VIEW_ERROR_BUDGET_DEFAULT: u64 = 5;
+const SQLITE_JOURNAL_MODE_DEFAULT: &str = "WAL";
+const SQLITE_BATCH_SIZE_DEFAULT: usize = 500;
+const SQLITE_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+static POSTGRES_VIEW_URL_DEFAULT: &str = "host=localhost user=postgres dbname=linux_fs_audit";
+static POSTGRES_SCHEMA_DEFAULT: &str = "public";
+const POSTGRES_POOL_SIZE_DEFAULT: usize = 2;
+const POSTGRES_BATCH_SIZE_DEFAULT: usize = 500;
+const POSTGRES_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+static ELASTICSEARCH_URL_DEFAULT: &str = "http://localhost:9200";
+static ELASTICSEARCH_INDEX_DEFAULT: &str = "linux-fs-audit-%Y.%m.%d";
+const ELASTICSEARCH_BATCH_SIZE_DEFAULT: usize = 500;
+const ELASTICSEARCH_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+const ELASTICSEARCH_MAX_RETRIES_DEFAULT: usize = 3;
+static LOKI_URL_DEFAULT: &str = "http://localhost:3100";
+static LOKI_JOB_DEFAULT: &str = "linux-fs-audit";
+const LOKI_BATCH_SIZE_DEFAULT: usize = 500;
+const LOKI_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+static SPLUNK_URL_DEFAULT: &str = "https://localhost:8088";
+static SPLUNK_SOURCE_DEFAULT: &str = "linux-fs-audit";
+static SPLUNK_SOURCETYPE_DEFAULT: &str = "linux-fs-audit:json";
+const SPLUNK_BATCH_SIZE_DEFAULT: usize = 500;
+const SPLUNK_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+const SPLUNK_ACK_TIMEOUT_MS_DEFAULT: u64 = 30000;
+static KAFKA_BROKER_DEFAULT: &str = "localhost:9092";
+static KAFKA_TOPIC_DEFAULT: &str = "linux-fs-audit-{key}";
+const KAFKA_ACK_TIMEOUT_MS_DEFAULT: u64 = 30000;
+const KAFKA_BATCH_SIZE_DEFAULT: usize = 500;
+const KAFKA_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+static OTLP_GRPC_ENDPOINT_DEFAULT: &str = "http://localhost:4317";
+static OTLP_HTTP_ENDPOINT_DEFAULT: &str = "http://localhost:4318";
+static OTLP_SERVICE_NAME_DEFAULT: &str = "linux-fs-audit";
+const OTLP_TIMEOUT_MS_DEFAULT: u64 = 10000;
+const OTLP_BATCH_SIZE_DEFAULT: usize = 500;
+const OTLP_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+const OTLP_MAX_RETRIES_DEFAULT: usize = 3;
+static FLUENT_ADDRESS_DEFAULT: &str = "localhost:24224";
+static FLUENT_TAG_DEFAULT: &str = "linux_fs_audit.{key}";
+const FLUENT_ACK_TIMEOUT_MS_DEFAULT: u64 = 30000;
+const FLUENT_BATCH_SIZE_DEFAULT: usize = 500;
+const FLUENT_FLUSH_INTERVAL_MS_DEFAULT: u64 = 1000;
+static PARQUET_DIRECTORY_DEFAULT: &str = "parquet";
+const PARQUET_BATCH_SIZE_DEFAULT: usize = 10000;
+const PARQUET_FLUSH_INTERVAL_MS_DEFAULT: u64 = 60000;
+static FILE_VIEW_PATH_DEFAULT: &str = "events.jsonl";
+const FILE_FSYNC_INTERVAL_MS_DEFAULT: u64 = 1000;
+const FILE_KEEP_DEFAULT: usize = 7;
+static SYSLOG_LOCAL_ADDRESS_DEFAULT: &str = "/dev/log";
+static SYSLOG_APP_NAME_DEFAULT: &str = "linux-fs-audit";
+static GELF_ADDRESS_DEFAULT: &str = "localhost:12201";
+/// Fits a datagram into the smallest MTU likely on the way to a remote Graylog.
+const GELF_MAX_CHUNK_SIZE_DEFAULT: usize = 1420;
+const RETENTION_BATCH_SIZE_DEFAULT: usize = 1000;
+const RETENTION_INTERVAL_SECONDS_DEFAULT: u64 = 300;
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub(crate) enum ViewMode {
     Http,
     Sqlite,
+    Postgres,
+    Elasticsearch,
+    Loki,
+    Splunk,
+    Kafka,
+    Otlp,
+    File,
+    Syslog,
+    Gelf,
+    Fluent,
+    Parquet,
+    Stdout,
     Mock,
 }
+/// Layouts SIEMs read natively, which any text view can write instead of its own.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum EventFormat {
+    Cef,
+    Leef,
+    /// Json nested the way Elastic Common Schema names the fields.
+    Ecs,
+}
+/// What a Kafka record is keyed by, so that the records sharing it land in the same partition.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum KafkaPartitionKey {
+    Host,
+    User,
+    /// The path of a file, the executable of an operation.
+    Path,
+}
+/// How many replicas a record is written to before the broker acknowledges it.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum KafkaAcks {
+    None,
+    One,
+    All,
+}
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum KafkaCompression {
+    None,
+    Gzip,
+    Snappy,
+}
+/// How log records are exported to an OpenTelemetry collector.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum OtlpProtocol {
+    Grpc,
+    /// Protobuf encoded requests posted to `/v1/logs`.
+    HttpProtobuf,
+}
+/// Where syslog messages go: a local socket like `/dev/log`, or a collector over UDP or TCP.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum SyslogTransport {
+    Local,
+    Udp,
+    Tcp,
+}
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum SyslogFormat {
+    Rfc5424,
+    Rfc3164,
+}
+/// Syslog facilities, numbered in the order they are listed.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum SyslogFacility {
+    Kern,
+    User,
+    Mail,
+    Daemon,
+    Auth,
+    Syslog,
+    Lpr,
+    News,
+    Uucp,
+    Cron,
+    Authpriv,
+    Ftp,
+    Ntp,
+    Security,
+    Console,
+    SolarisCron,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
+}
+/// Syslog severities, numbered in the order they are listed.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum SyslogSeverity {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+/// How GELF messages reach Graylog: UDP datagrams, chunked when too large, null terminated over TCP, or posted
+/// over HTTP.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum GelfTransport {
+    Udp,
+    Tcp,
+    Http,
+}
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum GelfCompression {
+    None,
+    Gzip,
+    Zlib,
+}
+/// When the file view forces written lines to disk: after every line, at most once per
+/// `fsync_interval_ms`, or never, leaving it to the operating system.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum FileFsync {
+    Always,
+    Interval,
+    Never,
+}
+/// Value of `PRAGMA synchronous`, trading durability of the last transactions for write speed.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+pub(crate) enum SqliteSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
 #[derive(Debug, Deserialize, PartialEq)]
 pub(crate) enum LogSettings {
     Debug,
@@ -30,24 +217,341 @@ impl fmt::Display for ViewMode {
 pub(crate) struct StartupSettings {
     #[serde(default = "default_dispatcher_directory")]
     pub(super) dispatcher_directory: String,
+    #[serde(flatten)]
+    pub(super) view: ViewDefinition,
+    #[serde(default)]
+    pub(super) views: Vec<ViewDefinition>,
+    #[serde(default)]
+    pub(super) dead_letter_path: Option<String>,
+    #[serde(default = "default_log_level")]
+    pub(super) log_level: LogSettings,
+    /// Where `/metrics`, `/healthz` and `/readyz` are served, `null` turns the admin server off.
+    #[serde(default = "default_admin_address")]
+    pub(super) admin_address: Option<String>,
+}
+
+/// Settings of a single view. The top level of the settings file is one of these,
+/// each entry of `views` is another one, which is used to fan out to several views at once.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct ViewDefinition {
+    #[serde(default)]
+    pub(super) name: Option<String>,
     #[serde(default = "default_view_mode")]
     pub(super) view_mode: ViewMode,
     #[serde(default = "default_http_settings")]
     pub(super) http_settings: HttpSettings,
     #[serde(default = "default_sqlite_settings")]
     pub(super) sqlite_settings: SqliteSettings,
-    #[serde(default = "default_log_level")]
-    pub(super) log_level: LogSettings,
+    #[serde(default = "default_postgres_settings")]
+    pub(super) postgres_settings: PostgresSettings,
+    #[serde(default = "default_elasticsearch_settings")]
+    pub(super) elasticsearch_settings: ElasticsearchSettings,
+    #[serde(default = "default_loki_settings")]
+    pub(super) loki_settings: LokiSettings,
+    #[serde(default = "default_splunk_settings")]
+    pub(super) splunk_settings: SplunkSettings,
+    #[serde(default = "default_kafka_settings")]
+    pub(super) kafka_settings: KafkaSettings,
+    #[serde(default = "default_otlp_settings")]
+    pub(super) otlp_settings: OtlpSettings,
+    #[serde(default = "default_file_settings")]
+    pub(super) file_settings: FileSettings,
+    #[serde(default = "default_syslog_settings")]
+    pub(super) syslog_settings: SyslogSettings,
+    #[serde(default = "default_gelf_settings")]
+    pub(super) gelf_settings: GelfSettings,
+    #[serde(default = "default_fluent_settings")]
+    pub(super) fluent_settings: FluentSettings,
+    #[serde(default = "default_parquet_settings")]
+    pub(super) parquet_settings: ParquetSettings,
+    #[serde(default)]
+    pub(super) stdout_settings: StdoutSettings,
+    #[serde(default = "default_queue_capacity")]
+    pub(super) queue_capacity: usize,
+    /// Events in a row the view may fail to render before it is no longer ready.
+    #[serde(default = "default_error_budget")]
+    pub(super) error_budget: u64,
+    #[serde(default)]
+    pub(super) filter: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub(crate) struct HttpSettings {
     pub(super) http_destination: String,
+    #[serde(default)]
+    pub(super) event_format: Option<EventFormat>,
 }
 
+/// Inserts are grouped into one transaction of up to `batch_size` events,
+/// committed at the latest `flush_interval_ms` after the first event of the batch.
 #[derive(Debug, Deserialize, PartialEq)]
 pub(crate) struct SqliteSettings {
-    pub(super) db_path: String,
+    pub(crate) db_path: String,
+    #[serde(default = "default_sqlite_journal_mode")]
+    pub(crate) journal_mode: String,
+    #[serde(default = "default_sqlite_synchronous")]
+    pub(crate) synchronous: SqliteSynchronous,
+    #[serde(default = "default_sqlite_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_sqlite_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+    #[serde(default)]
+    pub(crate) retention: Option<RetentionSettings>,
+}
+
+/// Connection to PostgreSQL, `url` being either a `postgresql://` URL or `key=value` pairs, and the schema
+/// the tables are kept in. Inserts are batched like the Sqlite ones, each batch taking a connection of the pool.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct PostgresSettings {
+    pub(crate) url: String,
+    #[serde(default = "default_postgres_schema")]
+    pub(crate) schema: String,
+    #[serde(default = "default_postgres_pool_size")]
+    pub(crate) pool_size: usize,
+    #[serde(default = "default_postgres_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_postgres_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// Elasticsearch or OpenSearch at `url`, written to through the `_bulk` API in batches like the PostgreSQL ones.
+/// `index` is a strftime template filled in with the day of each event (UTC). Items the cluster was too busy
+/// for are sent again up to `max_retries` times.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct ElasticsearchSettings {
+    pub(crate) url: String,
+    #[serde(default = "default_elasticsearch_index")]
+    pub(crate) index: String,
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    #[serde(default = "default_elasticsearch_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_elasticsearch_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+    #[serde(default = "default_elasticsearch_max_retries")]
+    pub(crate) max_retries: usize,
+}
+
+/// Grafana Loki at `url`, pushed to in batches, every stream labelled with `job`, the host, the key and the user.
+/// `tenant_id` is sent as `X-Scope-OrgID` to a multi-tenant Loki.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct LokiSettings {
+    pub(crate) url: String,
+    #[serde(default = "default_loki_job")]
+    pub(crate) job: String,
+    #[serde(default)]
+    pub(crate) tenant_id: Option<String>,
+    #[serde(default = "default_loki_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_loki_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// Splunk HTTP Event Collector at `url`, sent batches of events authenticated with `token`. With a `channel`
+/// (a GUID) every batch waits for indexer acknowledgement, for at most `ack_timeout_ms`, before it counts as written.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct SplunkSettings {
+    pub(crate) url: String,
+    #[serde(default)]
+    pub(crate) token: String,
+    #[serde(default)]
+    pub(crate) index: Option<String>,
+    #[serde(default = "default_splunk_source")]
+    pub(crate) source: String,
+    #[serde(default = "default_splunk_sourcetype")]
+    pub(crate) sourcetype: String,
+    #[serde(default)]
+    pub(crate) channel: Option<String>,
+    #[serde(default = "default_splunk_ack_timeout_ms")]
+    pub(crate) ack_timeout_ms: u64,
+    #[serde(default = "default_splunk_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_splunk_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// Kafka `brokers` the events are published to in batches. `topic` is a template, `{key}` in it standing for
+/// the key of the event in lower case, `unknown` for a file without its operation.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct KafkaSettings {
+    #[serde(default = "default_kafka_brokers")]
+    pub(crate) brokers: Vec<String>,
+    #[serde(default = "default_kafka_topic")]
+    pub(crate) topic: String,
+    #[serde(default = "default_kafka_partition_key")]
+    pub(crate) partition_key: KafkaPartitionKey,
+    #[serde(default = "default_kafka_acks")]
+    pub(crate) acks: KafkaAcks,
+    #[serde(default = "default_kafka_ack_timeout_ms")]
+    pub(crate) ack_timeout_ms: u64,
+    #[serde(default = "default_kafka_compression")]
+    pub(crate) compression: KafkaCompression,
+    #[serde(default = "default_kafka_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_kafka_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// An OpenTelemetry collector the events are exported to in batches as OTLP log records. `endpoint` defaults to
+/// the standard port of the `protocol`, `headers` are sent along with every export, and batches the collector
+/// could not take are exported again up to `max_retries` times. `service_name` names the resource.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct OtlpSettings {
+    #[serde(default = "default_otlp_protocol")]
+    pub(crate) protocol: OtlpProtocol,
+    #[serde(default)]
+    pub(crate) endpoint: Option<String>,
+    #[serde(default)]
+    pub(crate) headers: BTreeMap<String, String>,
+    #[serde(default = "default_otlp_service_name")]
+    pub(crate) service_name: String,
+    #[serde(default = "default_otlp_timeout_ms")]
+    pub(crate) timeout_ms: u64,
+    #[serde(default = "default_otlp_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_otlp_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+    #[serde(default = "default_otlp_max_retries")]
+    pub(crate) max_retries: usize,
+}
+
+/// An append-only json lines file. It is rotated once it would grow over `max_size_bytes` and, with `rotate_daily`,
+/// on the first write of a new day, keeping the newest `keep` rotated files, compressed unless `compress` is off.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub(crate) struct FileSettings {
+    pub(crate) path: String,
+    #[serde(default = "default_file_fsync")]
+    pub(crate) fsync: FileFsync,
+    #[serde(default = "default_file_fsync_interval_ms")]
+    pub(crate) fsync_interval_ms: u64,
+    #[serde(default)]
+    pub(crate) max_size_bytes: Option<u64>,
+    #[serde(default)]
+    pub(crate) rotate_daily: bool,
+    #[serde(default = "default_file_compress")]
+    pub(crate) compress: bool,
+    #[serde(default = "default_file_keep")]
+    pub(crate) keep: usize,
+    #[serde(default)]
+    pub(crate) event_format: Option<EventFormat>,
+}
+
+/// Syslog messages sent to `address`, a socket path for the `Local` transport and `host:port` otherwise.
+/// Operations are logged with the severity of their key, files with the one of the operation that touched them.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub(crate) struct SyslogSettings {
+    #[serde(default = "default_syslog_transport")]
+    pub(crate) transport: SyslogTransport,
+    #[serde(default = "default_syslog_address")]
+    pub(crate) address: String,
+    #[serde(default = "default_syslog_format")]
+    pub(crate) format: SyslogFormat,
+    #[serde(default = "default_syslog_facility")]
+    pub(crate) facility: SyslogFacility,
+    #[serde(default = "default_syslog_read_severity")]
+    pub(crate) read_severity: SyslogSeverity,
+    #[serde(default = "default_syslog_write_severity")]
+    pub(crate) write_severity: SyslogSeverity,
+    #[serde(default = "default_syslog_app_name")]
+    pub(crate) app_name: String,
+    /// Replaces the message and its structured data.
+    #[serde(default)]
+    pub(crate) event_format: Option<EventFormat>,
+}
+
+/// GELF messages sent to `address`, `host:port` for UDP and TCP, the URL of the input for HTTP. Only UDP
+/// messages are compressed, and split into chunks of at most `max_chunk_size` bytes. Levels are syslog severities,
+/// taken from the key like those of the syslog view.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub(crate) struct GelfSettings {
+    #[serde(default = "default_gelf_transport")]
+    pub(crate) transport: GelfTransport,
+    #[serde(default = "default_gelf_address")]
+    pub(crate) address: String,
+    #[serde(default = "default_gelf_compression")]
+    pub(crate) compression: GelfCompression,
+    #[serde(default = "default_gelf_max_chunk_size")]
+    pub(crate) max_chunk_size: usize,
+    #[serde(default = "default_syslog_read_severity")]
+    pub(crate) read_level: SyslogSeverity,
+    #[serde(default = "default_syslog_write_severity")]
+    pub(crate) write_level: SyslogSeverity,
+}
+
+/// A Fluentd or Fluent Bit forward input at `address`, sent batches of events in forward mode. `tag` is a template
+/// like the Kafka topic. With `require_ack` every batch waits at most `ack_timeout_ms` for the server to acknowledge
+/// it, and a `shared_key` authenticates both ends of the connection to an input with security enabled.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct FluentSettings {
+    #[serde(default = "default_fluent_address")]
+    pub(crate) address: String,
+    #[serde(default = "default_fluent_tag")]
+    pub(crate) tag: String,
+    #[serde(default)]
+    pub(crate) require_ack: bool,
+    #[serde(default = "default_fluent_ack_timeout_ms")]
+    pub(crate) ack_timeout_ms: u64,
+    #[serde(default)]
+    pub(crate) shared_key: Option<String>,
+    /// The name this host gives itself in the handshake, its hostname unless set.
+    #[serde(default)]
+    pub(crate) self_hostname: Option<String>,
+    #[serde(default = "default_fluent_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_fluent_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// Parquet files below `directory`, partitioned by date and key like `date=2024-05-01/key=WRITE`. Every batch of
+/// up to `batch_size` events, or whatever arrived within `flush_interval_ms`, becomes a new file in each partition
+/// it touches, so larger batches make fewer and bigger files.
+#[derive(Debug, Deserialize, PartialEq)]
+pub(crate) struct ParquetSettings {
+    #[serde(default = "default_parquet_directory")]
+    pub(crate) directory: String,
+    #[serde(default = "default_parquet_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_parquet_flush_interval_ms")]
+    pub(crate) flush_interval_ms: u64,
+}
+
+/// Every event printed as one line, json unless `event_format` says otherwise.
+#[derive(Debug, Deserialize, PartialEq, Clone, Default)]
+pub(crate) struct StdoutSettings {
+    #[serde(default)]
+    pub(crate) event_format: Option<EventFormat>,
+}
+
+/// Limits of the Sqlite store, every one of them optional. Every `interval_seconds` the oldest events
+/// over any limit are deleted, `batch_size` at a time, and archived first when `archive_directory` is set.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub(crate) struct RetentionSettings {
+    #[serde(default)]
+    pub(crate) max_age_seconds: Option<u64>,
+    #[serde(default)]
+    pub(crate) max_rows: Option<u64>,
+    #[serde(default)]
+    pub(crate) max_file_size_bytes: Option<u64>,
+    #[serde(default = "default_retention_batch_size")]
+    pub(crate) batch_size: usize,
+    #[serde(default = "default_retention_interval_seconds")]
+    pub(crate) interval_seconds: u64,
+    #[serde(default)]
+    pub(crate) archive_directory: Option<String>,
+    #[serde(default)]
+    pub(crate) rollups: Option<RollupRetentionSettings>,
+}
+
+/// Limits of the per-minute and per-hour rollups, which are kept independently of the events
+/// they were counted from, so they can outlive them.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub(crate) struct RollupRetentionSettings {
+    #[serde(default)]
+    pub(crate) minute_max_age_seconds: Option<u64>,
+    #[serde(default)]
+    pub(crate) hour_max_age_seconds: Option<u64>,
 }
 
 fn default_dispatcher_directory() -> String {
@@ -57,21 +561,442 @@ fn default_dispatcher_directory() -> String {
 fn default_view_mode() -> ViewMode {
     return ViewMode::Mock;
 }
+fn default_queue_capacity() -> usize {
+    return VIEW_QUEUE_CAPACITY_DEFAULT;
+}
+fn default_error_budget() -> u64 {
+    return VIEW_ERROR_BUDGET_DEFAULT;
+}
 fn default_log_level() -> LogSettings {
     return LogSettings::Info;
 }
+fn default_admin_address() -> Option<String> {
+    return Some(String::from(ADMIN_ADDRESS_DEFAULT));
+}
 fn default_sqlite_settings() -> SqliteSettings {
-    return SqliteSettings {
-        db_path: SQLITE_VIEW_DB_PATH_DEFAULT.to_string(),
-    };
+    return SqliteSettings::new(SQLITE_VIEW_DB_PATH_DEFAULT);
+}
+fn default_sqlite_journal_mode() -> String {
+    return SQLITE_JOURNAL_MODE_DEFAULT.to_string();
+}
+fn default_sqlite_synchronous() -> SqliteSynchronous {
+    return SqliteSynchronous::Normal;
+}
+fn default_sqlite_batch_size() -> usize {
+    return SQLITE_BATCH_SIZE_DEFAULT;
+}
+fn default_sqlite_flush_interval_ms() -> u64 {
+    return SQLITE_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_postgres_settings() -> PostgresSettings {
+    return PostgresSettings::new(POSTGRES_VIEW_URL_DEFAULT);
+}
+fn default_postgres_schema() -> String {
+    return String::from(POSTGRES_SCHEMA_DEFAULT);
+}
+fn default_postgres_pool_size() -> usize {
+    return POSTGRES_POOL_SIZE_DEFAULT;
+}
+fn default_postgres_batch_size() -> usize {
+    return POSTGRES_BATCH_SIZE_DEFAULT;
+}
+fn default_postgres_flush_interval_ms() -> u64 {
+    return POSTGRES_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_elasticsearch_settings() -> ElasticsearchSettings {
+    return ElasticsearchSettings::new(ELASTICSEARCH_URL_DEFAULT);
+}
+fn default_elasticsearch_index() -> String {
+    return String::from(ELASTICSEARCH_INDEX_DEFAULT);
+}
+fn default_elasticsearch_batch_size() -> usize {
+    return ELASTICSEARCH_BATCH_SIZE_DEFAULT;
+}
+fn default_elasticsearch_flush_interval_ms() -> u64 {
+    return ELASTICSEARCH_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_elasticsearch_max_retries() -> usize {
+    return ELASTICSEARCH_MAX_RETRIES_DEFAULT;
+}
+fn default_loki_settings() -> LokiSettings {
+    return LokiSettings::new(LOKI_URL_DEFAULT);
+}
+fn default_loki_job() -> String {
+    return String::from(LOKI_JOB_DEFAULT);
+}
+fn default_loki_batch_size() -> usize {
+    return LOKI_BATCH_SIZE_DEFAULT;
+}
+fn default_loki_flush_interval_ms() -> u64 {
+    return LOKI_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_splunk_settings() -> SplunkSettings {
+    return SplunkSettings::new(SPLUNK_URL_DEFAULT, "");
+}
+fn default_splunk_source() -> String {
+    return String::from(SPLUNK_SOURCE_DEFAULT);
+}
+fn default_splunk_sourcetype() -> String {
+    return String::from(SPLUNK_SOURCETYPE_DEFAULT);
+}
+fn default_splunk_ack_timeout_ms() -> u64 {
+    return SPLUNK_ACK_TIMEOUT_MS_DEFAULT;
+}
+fn default_splunk_batch_size() -> usize {
+    return SPLUNK_BATCH_SIZE_DEFAULT;
+}
+fn default_splunk_flush_interval_ms() -> u64 {
+    return SPLUNK_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_kafka_settings() -> KafkaSettings {
+    return KafkaSettings::new(default_kafka_brokers());
+}
+fn default_kafka_brokers() -> Vec<String> {
+    return vec![String::from(KAFKA_BROKER_DEFAULT)];
+}
+fn default_kafka_topic() -> String {
+    return String::from(KAFKA_TOPIC_DEFAULT);
+}
+fn default_kafka_partition_key() -> KafkaPartitionKey {
+    return KafkaPartitionKey::Host;
+}
+fn default_kafka_acks() -> KafkaAcks {
+    return KafkaAcks::All;
+}
+fn default_kafka_ack_timeout_ms() -> u64 {
+    return KAFKA_ACK_TIMEOUT_MS_DEFAULT;
+}
+fn default_kafka_compression() -> KafkaCompression {
+    return KafkaCompression::None;
+}
+fn default_otlp_settings() -> OtlpSettings {
+    return OtlpSettings::new(default_otlp_protocol());
+}
+fn default_otlp_protocol() -> OtlpProtocol {
+    return OtlpProtocol::Grpc;
+}
+fn default_otlp_service_name() -> String {
+    return OTLP_SERVICE_NAME_DEFAULT.to_string();
+}
+fn default_otlp_timeout_ms() -> u64 {
+    return OTLP_TIMEOUT_MS_DEFAULT;
+}
+fn default_otlp_batch_size() -> usize {
+    return OTLP_BATCH_SIZE_DEFAULT;
+}
+fn default_otlp_flush_interval_ms() -> u64 {
+    return OTLP_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_otlp_max_retries() -> usize {
+    return OTLP_MAX_RETRIES_DEFAULT;
+}
+fn default_kafka_batch_size() -> usize {
+    return KAFKA_BATCH_SIZE_DEFAULT;
+}
+fn default_kafka_flush_interval_ms() -> u64 {
+    return KAFKA_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_file_settings() -> FileSettings {
+    return FileSettings::new(FILE_VIEW_PATH_DEFAULT);
+}
+fn default_file_fsync() -> FileFsync {
+    return FileFsync::Interval;
+}
+fn default_file_fsync_interval_ms() -> u64 {
+    return FILE_FSYNC_INTERVAL_MS_DEFAULT;
+}
+fn default_file_compress() -> bool {
+    return true;
+}
+fn default_file_keep() -> usize {
+    return FILE_KEEP_DEFAULT;
+}
+fn default_syslog_settings() -> SyslogSettings {
+    return SyslogSettings::new(SyslogTransport::Local, SYSLOG_LOCAL_ADDRESS_DEFAULT);
+}
+fn default_syslog_transport() -> SyslogTransport {
+    return SyslogTransport::Local;
+}
+fn default_syslog_address() -> String {
+    return String::from(SYSLOG_LOCAL_ADDRESS_DEFAULT);
+}
+fn default_syslog_format() -> SyslogFormat {
+    return SyslogFormat::Rfc5424;
+}
+fn default_syslog_facility() -> SyslogFacility {
+    return SyslogFacility::Auth;
+}
+fn default_syslog_read_severity() -> SyslogSeverity {
+    return SyslogSeverity::Info;
+}
+fn default_syslog_write_severity() -> SyslogSeverity {
+    return SyslogSeverity::Notice;
+}
+fn default_syslog_app_name() -> String {
+    return String::from(SYSLOG_APP_NAME_DEFAULT);
+}
+fn default_gelf_settings() -> GelfSettings {
+    return GelfSettings::new(default_gelf_transport(), GELF_ADDRESS_DEFAULT);
+}
+fn default_gelf_transport() -> GelfTransport {
+    return GelfTransport::Udp;
+}
+fn default_gelf_address() -> String {
+    return String::from(GELF_ADDRESS_DEFAULT);
+}
+fn default_gelf_compression() -> GelfCompression {
+    return GelfCompression::Gzip;
+}
+fn default_gelf_max_chunk_size() -> usize {
+    return GELF_MAX_CHUNK_SIZE_DEFAULT;
+}
+fn default_fluent_settings() -> FluentSettings {
+    return FluentSettings::new(FLUENT_ADDRESS_DEFAULT);
+}
+fn default_fluent_address() -> String {
+    return String::from(FLUENT_ADDRESS_DEFAULT);
+}
+fn default_fluent_tag() -> String {
+    return String::from(FLUENT_TAG_DEFAULT);
+}
+fn default_fluent_ack_timeout_ms() -> u64 {
+    return FLUENT_ACK_TIMEOUT_MS_DEFAULT;
+}
+fn default_fluent_batch_size() -> usize {
+    return FLUENT_BATCH_SIZE_DEFAULT;
+}
+fn default_fluent_flush_interval_ms() -> u64 {
+    return FLUENT_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_parquet_settings() -> ParquetSettings {
+    return ParquetSettings::new(PARQUET_DIRECTORY_DEFAULT);
+}
+fn default_parquet_directory() -> String {
+    return String::from(PARQUET_DIRECTORY_DEFAULT);
+}
+fn default_parquet_batch_size() -> usize {
+    return PARQUET_BATCH_SIZE_DEFAULT;
+}
+fn default_parquet_flush_interval_ms() -> u64 {
+    return PARQUET_FLUSH_INTERVAL_MS_DEFAULT;
+}
+fn default_retention_batch_size() -> usize {
+    return RETENTION_BATCH_SIZE_DEFAULT;
+}
+fn default_retention_interval_seconds() -> u64 {
+    return RETENTION_INTERVAL_SECONDS_DEFAULT;
 }
 
 fn default_http_settings() -> HttpSettings {
     return HttpSettings {
         http_destination: HTTP_VIEW_DESTINATION_DEFAULT.to_string(),
+        event_format: None,
     };
 }
 
+impl StartupSettings {
+    /// The database of the first Sqlite view, or of the top level settings when none is listed.
+    pub(crate) fn sqlite_db_path(&self) -> &str {
+        return self
+            .views
+            .iter()
+            .find(|x| x.view_mode == ViewMode::Sqlite)
+            .unwrap_or(&self.view)
+            .sqlite_settings
+            .db_path
+            .as_str();
+    }
+}
+
+impl SqliteSettings {
+    pub(crate) fn new(db_path: &str) -> Self {
+        return Self {
+            db_path: db_path.to_string(),
+            journal_mode: default_sqlite_journal_mode(),
+            synchronous: default_sqlite_synchronous(),
+            batch_size: default_sqlite_batch_size(),
+            flush_interval_ms: default_sqlite_flush_interval_ms(),
+            retention: None,
+        };
+    }
+}
+
+impl PostgresSettings {
+    pub(crate) fn new(url: &str) -> Self {
+        return Self {
+            url: url.to_string(),
+            schema: default_postgres_schema(),
+            pool_size: default_postgres_pool_size(),
+            batch_size: default_postgres_batch_size(),
+            flush_interval_ms: default_postgres_flush_interval_ms(),
+        };
+    }
+}
+
+impl FileSettings {
+    pub(crate) fn new(path: &str) -> Self {
+        return Self {
+            path: path.to_string(),
+            fsync: default_file_fsync(),
+            fsync_interval_ms: default_file_fsync_interval_ms(),
+            max_size_bytes: None,
+            rotate_daily: false,
+            compress: default_file_compress(),
+            keep: default_file_keep(),
+            event_format: None,
+        };
+    }
+}
+
+impl ElasticsearchSettings {
+    pub(crate) fn new(url: &str) -> Self {
+        return Self {
+            url: url.to_string(),
+            index: default_elasticsearch_index(),
+            username: None,
+            password: None,
+            batch_size: default_elasticsearch_batch_size(),
+            flush_interval_ms: default_elasticsearch_flush_interval_ms(),
+            max_retries: default_elasticsearch_max_retries(),
+        };
+    }
+}
+
+impl LokiSettings {
+    pub(crate) fn new(url: &str) -> Self {
+        return Self {
+            url: url.to_string(),
+            job: default_loki_job(),
+            tenant_id: None,
+            batch_size: default_loki_batch_size(),
+            flush_interval_ms: default_loki_flush_interval_ms(),
+        };
+    }
+}
+
+impl SplunkSettings {
+    pub(crate) fn new(url: &str, token: &str) -> Self {
+        return Self {
+            url: url.to_string(),
+            token: token.to_string(),
+            index: None,
+            source: default_splunk_source(),
+            sourcetype: default_splunk_sourcetype(),
+            channel: None,
+            ack_timeout_ms: default_splunk_ack_timeout_ms(),
+            batch_size: default_splunk_batch_size(),
+            flush_interval_ms: default_splunk_flush_interval_ms(),
+        };
+    }
+}
+
+impl KafkaSettings {
+    pub(crate) fn new(brokers: Vec<String>) -> Self {
+        return Self {
+            brokers,
+            topic: default_kafka_topic(),
+            partition_key: default_kafka_partition_key(),
+            acks: default_kafka_acks(),
+            ack_timeout_ms: default_kafka_ack_timeout_ms(),
+            compression: default_kafka_compression(),
+            batch_size: default_kafka_batch_size(),
+            flush_interval_ms: default_kafka_flush_interval_ms(),
+        };
+    }
+}
+
+impl OtlpSettings {
+    pub(crate) fn new(protocol: OtlpProtocol) -> Self {
+        return Self {
+            protocol,
+            endpoint: None,
+            headers: BTreeMap::new(),
+            service_name: default_otlp_service_name(),
+            timeout_ms: default_otlp_timeout_ms(),
+            batch_size: default_otlp_batch_size(),
+            flush_interval_ms: default_otlp_flush_interval_ms(),
+            max_retries: default_otlp_max_retries(),
+        };
+    }
+
+    /// The configured endpoint, otherwise the collector on this host at the standard port of the protocol.
+    pub(crate) fn endpoint(&self) -> &str {
+        return match (&self.endpoint, self.protocol) {
+            (Some(endpoint), _) => endpoint,
+            (None, OtlpProtocol::Grpc) => OTLP_GRPC_ENDPOINT_DEFAULT,
+            (None, OtlpProtocol::HttpProtobuf) => OTLP_HTTP_ENDPOINT_DEFAULT,
+        };
+    }
+}
+
+impl SyslogSettings {
+    pub(crate) fn new(transport: SyslogTransport, address: &str) -> Self {
+        return Self {
+            transport,
+            address: address.to_string(),
+            format: default_syslog_format(),
+            facility: default_syslog_facility(),
+            read_severity: default_syslog_read_severity(),
+            write_severity: default_syslog_write_severity(),
+            app_name: default_syslog_app_name(),
+            event_format: None,
+        };
+    }
+}
+
+impl GelfSettings {
+    pub(crate) fn new(transport: GelfTransport, address: &str) -> Self {
+        return Self {
+            transport,
+            address: address.to_string(),
+            compression: default_gelf_compression(),
+            max_chunk_size: default_gelf_max_chunk_size(),
+            read_level: default_syslog_read_severity(),
+            write_level: default_syslog_write_severity(),
+        };
+    }
+}
+
+impl FluentSettings {
+    pub(crate) fn new(address: &str) -> Self {
+        return Self {
+            address: address.to_string(),
+            tag: default_fluent_tag(),
+            require_ack: false,
+            ack_timeout_ms: default_fluent_ack_timeout_ms(),
+            shared_key: None,
+            self_hostname: None,
+            batch_size: default_fluent_batch_size(),
+            flush_interval_ms: default_fluent_flush_interval_ms(),
+        };
+    }
+}
+
+impl ParquetSettings {
+    pub(crate) fn new(directory: &str) -> Self {
+        return Self {
+            directory: directory.to_string(),
+            batch_size: default_parquet_batch_size(),
+            flush_interval_ms: default_parquet_flush_interval_ms(),
+        };
+    }
+}
+
+impl fmt::Display for SqliteSynchronous {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_uppercase())
+    }
+}
+
+impl ViewDefinition {
+    pub(crate) fn display_name(&self) -> String {
+        return self
+            .name
+            .clone()
+            .unwrap_or_else(|| self.view_mode.to_string());
+    }
+}
+
 pub(crate) fn configure(config_file_path: &str) -> Result<StartupSettings, serde_json::Error> {
     let file = match File::open(config_file_path) {
         Ok(f) => f,
@@ -81,23 +1006,69 @@ pub(crate) fn configure(config_file_path: &str) -> Result<StartupSettings, serde
             ));
         }
     };
-    serde_json::from_reader(BufReader::new(file))
+    let settings: StartupSettings = serde_json::from_reader(BufReader::new(file))?;
+    if settings.view.filter.is_some() {
+        return Err(serde_json::Error::custom(
+            "Error: filter only applies to entries of views, list the view under views to filter it.",
+        ));
+    }
+    let mut names = BTreeSet::new();
+    for definition in settings.views.iter() {
+        if !names.insert(definition.display_name()) {
+            return Err(serde_json::Error::custom(format!(
+                "Error: more than one view is called {}, give them different names.",
+                definition.display_name()
+            )));
+        }
+    }
+    return Ok(settings);
 }
 
 #[cfg(test)]
 mod test {
-    use crate::settings::{configure, LogSettings, ViewMode};
+    use crate::settings::{
+        configure, EventFormat, FileFsync, GelfCompression, GelfTransport, KafkaAcks,
+        KafkaCompression, KafkaPartitionKey, LogSettings, OtlpProtocol, SqliteSynchronous,
+        SyslogFacility, SyslogFormat, SyslogSeverity, SyslogTransport, ViewMode,
+    };
 
     #[test]
     fn if_file_present_should_have_correct_settings_set() {
         let read_configs = configure("test_resources/all_present.json").unwrap();
         assert_eq!(
-            read_configs.http_settings.http_destination,
+            read_configs.view.http_settings.http_destination,
             "localhost:9000"
         );
-        assert_eq!(read_configs.view_mode, ViewMode::Mock);
+        assert_eq!(
+            read_configs.view.http_settings.event_format,
+            Some(EventFormat::Ecs)
+        );
+        assert_eq!(read_configs.view.view_mode, ViewMode::Mock);
         assert_eq!(read_configs.log_level, LogSettings::Info);
         assert_eq!(read_configs.dispatcher_directory, "/var/run/disp");
+        assert_eq!(
+            read_configs.dead_letter_path.as_deref(),
+            Some("dead_letters.jsonl")
+        );
+        assert_eq!(read_configs.admin_address.as_deref(), Some("0.0.0.0:9100"));
+    }
+
+    #[test]
+    fn if_filter_set_outside_views_should_be_error() {
+        let read_configs = configure("test_resources/filter_outside_views.json");
+        assert!(read_configs
+            .unwrap_err()
+            .to_string()
+            .contains("filter only applies to entries of views"));
+    }
+
+    #[test]
+    fn if_views_share_name_should_be_error() {
+        let read_configs = configure("test_resources/views_sharing_name.json");
+        assert!(read_configs
+            .unwrap_err()
+            .to_string()
+            .contains("more than one view is called Kafka"));
     }
 
     #[test]
@@ -110,39 +1081,202 @@ mod test {
     fn if_file_present_should_have_dispatcher_present_others_on_default() {
         let read_configs = configure("test_resources/dispatcher_present.json").unwrap();
         assert_eq!(
-            read_configs.http_settings.http_destination,
+            read_configs.view.http_settings.http_destination,
             "localhost:8080"
         );
-        assert_eq!(read_configs.view_mode, ViewMode::Mock);
+        assert_eq!(read_configs.view.view_mode, ViewMode::Mock);
         assert_eq!(read_configs.dispatcher_directory, "/var/run/disp");
+        assert_eq!(read_configs.dead_letter_path, None);
+        assert_eq!(
+            read_configs.admin_address.as_deref(),
+            Some("127.0.0.1:9477")
+        );
     }
 
     #[test]
     fn if_file_present_should_have_http_present_others_on_default() {
         let read_configs = configure("test_resources/http_present.json").unwrap();
         assert_eq!(
-            read_configs.http_settings.http_destination,
+            read_configs.view.http_settings.http_destination,
             "localhost:9000"
         );
-        assert_eq!(read_configs.view_mode, ViewMode::Mock);
+        assert_eq!(read_configs.view.http_settings.event_format, None);
+        assert_eq!(read_configs.view.view_mode, ViewMode::Mock);
         assert_eq!(read_configs.dispatcher_directory, "/var/run/dispatcher");
     }
     #[test]
     fn if_file_present_should_have_sqlite_present_others_on_default() {
         let read_configs = configure("test_resources/sqlite_present.json").unwrap();
-        assert_eq!(read_configs.sqlite_settings.db_path, "db.sqlite");
-        assert_eq!(read_configs.view_mode, ViewMode::Mock);
+        assert_eq!(read_configs.view.sqlite_settings.db_path, "db.sqlite");
+        assert_eq!(read_configs.view.sqlite_settings.journal_mode, "WAL");
+        assert_eq!(
+            read_configs.view.sqlite_settings.synchronous,
+            SqliteSynchronous::Normal
+        );
+        assert_eq!(read_configs.view.sqlite_settings.batch_size, 500);
+        assert_eq!(read_configs.view.sqlite_settings.retention, None);
+        assert_eq!(read_configs.view.view_mode, ViewMode::Mock);
         assert_eq!(read_configs.dispatcher_directory, "/var/run/dispatcher");
     }
 
+    #[test]
+    fn if_file_present_should_have_postgres_present_others_on_default() {
+        let read_configs = configure("test_resources/postgres_present.json").unwrap();
+        let postgres = &read_configs.view.postgres_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Postgres);
+        assert_eq!(postgres.url, "postgresql://audit@db.example.com/audit");
+        assert_eq!(postgres.schema, "fs_audit");
+        assert_eq!(postgres.pool_size, 2);
+        assert_eq!(postgres.batch_size, 2000);
+        assert_eq!(postgres.flush_interval_ms, 1000);
+    }
+
+    #[test]
+    fn if_file_present_should_have_file_view_present_others_on_default() {
+        let read_configs = configure("test_resources/file_present.json").unwrap();
+        let file = &read_configs.view.file_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::File);
+        assert_eq!(file.path, "/var/log/linux-fs-audit/events.jsonl");
+        assert_eq!(file.fsync, FileFsync::Always);
+        assert_eq!(file.max_size_bytes, Some(104857600));
+        assert!(file.rotate_daily);
+        assert!(file.compress);
+        assert_eq!(file.keep, 7);
+        assert_eq!(file.event_format, None);
+    }
+
+    #[test]
+    fn if_file_present_should_have_elasticsearch_present_others_on_default() {
+        let read_configs = configure("test_resources/elasticsearch_present.json").unwrap();
+        let elasticsearch = &read_configs.view.elasticsearch_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Elasticsearch);
+        assert_eq!(elasticsearch.url, "https://opensearch.example.com:9200");
+        assert_eq!(elasticsearch.index, "audit-%Y.%m.%d");
+        assert_eq!(elasticsearch.username.as_deref(), Some("audit"));
+        assert_eq!(elasticsearch.password.as_deref(), Some("secret"));
+        assert_eq!(elasticsearch.batch_size, 500);
+        assert_eq!(elasticsearch.flush_interval_ms, 1000);
+        assert_eq!(elasticsearch.max_retries, 5);
+    }
+
+    #[test]
+    fn if_file_present_should_have_loki_present_others_on_default() {
+        let read_configs = configure("test_resources/loki_present.json").unwrap();
+        let loki = &read_configs.view.loki_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Loki);
+        assert_eq!(loki.url, "http://loki.example.com:3100");
+        assert_eq!(loki.job, "linux-fs-audit");
+        assert_eq!(loki.tenant_id.as_deref(), Some("ops"));
+        assert_eq!(loki.batch_size, 1000);
+        assert_eq!(loki.flush_interval_ms, 1000);
+    }
+
+    #[test]
+    fn if_file_present_should_have_splunk_present_others_on_default() {
+        let read_configs = configure("test_resources/splunk_present.json").unwrap();
+        let splunk = &read_configs.view.splunk_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Splunk);
+        assert_eq!(splunk.url, "https://splunk.example.com:8088");
+        assert_eq!(splunk.token, "8a1f6c2e-3f4b-4c55-9a0e-2d7b8c9e1f00");
+        assert_eq!(splunk.index.as_deref(), Some("security"));
+        assert_eq!(splunk.source, "linux-fs-audit");
+        assert_eq!(splunk.sourcetype, "linux-fs-audit:json");
+        assert_eq!(
+            splunk.channel.as_deref(),
+            Some("0aeeac95-ac74-4aa9-b30d-6c4c0ac581ba")
+        );
+        assert_eq!(splunk.ack_timeout_ms, 30000);
+        assert_eq!(splunk.batch_size, 500);
+    }
+
+    #[test]
+    fn if_file_present_should_have_kafka_present_others_on_default() {
+        let read_configs = configure("test_resources/kafka_present.json").unwrap();
+        let kafka = &read_configs.view.kafka_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Kafka);
+        assert_eq!(kafka.brokers, vec!["kafka-1:9092", "kafka-2:9092"]);
+        assert_eq!(kafka.topic, "audit.{key}");
+        assert_eq!(kafka.partition_key, KafkaPartitionKey::Path);
+        assert_eq!(kafka.acks, KafkaAcks::One);
+        assert_eq!(kafka.ack_timeout_ms, 30000);
+        assert_eq!(kafka.compression, KafkaCompression::Gzip);
+        assert_eq!(kafka.batch_size, 500);
+    }
+
+    #[test]
+    fn if_file_present_should_have_otlp_present_others_on_default() {
+        let read_configs = configure("test_resources/otlp_present.json").unwrap();
+        let otlp = &read_configs.view.otlp_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Otlp);
+        assert_eq!(otlp.protocol, OtlpProtocol::HttpProtobuf);
+        assert_eq!(otlp.endpoint(), "http://localhost:4318");
+        assert_eq!(otlp.headers["Authorization"], "Bearer 8a1f6c2e");
+        assert_eq!(otlp.service_name, "fs-audit-web-01");
+        assert_eq!(otlp.timeout_ms, 10000);
+        assert_eq!(otlp.batch_size, 500);
+        assert_eq!(otlp.max_retries, 5);
+    }
+
+    #[test]
+    fn if_file_present_should_have_gelf_present_others_on_default() {
+        let read_configs = configure("test_resources/gelf_present.json").unwrap();
+        let gelf = &read_configs.view.gelf_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Gelf);
+        assert_eq!(gelf.transport, GelfTransport::Udp);
+        assert_eq!(gelf.address, "graylog.example.com:12201");
+        assert_eq!(gelf.compression, GelfCompression::Zlib);
+        assert_eq!(gelf.max_chunk_size, 8154);
+        assert_eq!(gelf.read_level, SyslogSeverity::Info);
+        assert_eq!(gelf.write_level, SyslogSeverity::Warning);
+    }
+
+    #[test]
+    fn if_file_present_should_have_fluent_present_others_on_default() {
+        let read_configs = configure("test_resources/fluent_present.json").unwrap();
+        let fluent = &read_configs.view.fluent_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Fluent);
+        assert_eq!(fluent.address, "fluent-bit:24224");
+        assert_eq!(fluent.tag, "audit.{key}");
+        assert!(fluent.require_ack);
+        assert_eq!(fluent.ack_timeout_ms, 30000);
+        assert_eq!(fluent.shared_key.as_deref(), Some("secret"));
+        assert_eq!(fluent.self_hostname, None);
+        assert_eq!(fluent.batch_size, 500);
+    }
+
+    #[test]
+    fn if_file_present_should_have_parquet_present_others_on_default() {
+        let read_configs = configure("test_resources/parquet_present.json").unwrap();
+        let parquet = &read_configs.view.parquet_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Parquet);
+        assert_eq!(parquet.directory, "/var/lib/linux-fs-audit/parquet");
+        assert_eq!(parquet.batch_size, 50000);
+        assert_eq!(parquet.flush_interval_ms, 60000);
+    }
+
+    #[test]
+    fn if_file_present_should_have_syslog_present_others_on_default() {
+        let read_configs = configure("test_resources/syslog_present.json").unwrap();
+        let syslog = &read_configs.view.syslog_settings;
+        assert_eq!(read_configs.view.view_mode, ViewMode::Syslog);
+        assert_eq!(syslog.transport, SyslogTransport::Tcp);
+        assert_eq!(syslog.address, "siem.example.com:601");
+        assert_eq!(syslog.format, SyslogFormat::Rfc3164);
+        assert_eq!(syslog.facility, SyslogFacility::Local4);
+        assert_eq!(syslog.read_severity, SyslogSeverity::Info);
+        assert_eq!(syslog.write_severity, SyslogSeverity::Warning);
+        assert_eq!(syslog.app_name, "linux-fs-audit");
+        assert_eq!(syslog.event_format, Some(EventFormat::Cef));
+    }
+
     #[test]
     fn if_file_present_should_have_view_present_others_on_default() {
         let read_configs = configure("test_resources/view_present.json").unwrap();
         assert_eq!(
-            read_configs.http_settings.http_destination,
+            read_configs.view.http_settings.http_destination,
             "localhost:8080"
         );
-        assert_eq!(read_configs.view_mode, ViewMode::Http);
+        assert_eq!(read_configs.view.view_mode, ViewMode::Http);
         assert_eq!(read_configs.dispatcher_directory, "/var/run/dispatcher");
     }
     #[test]
@@ -150,4 +1284,49 @@ mod test {
         let read_configs = configure("test_resources/log_present.json").unwrap();
         assert_eq!(read_configs.log_level, LogSettings::Debug);
     }
+
+    #[test]
+    fn if_file_present_should_have_every_listed_view_with_own_settings() {
+        let read_configs = configure("test_resources/views_present.json").unwrap();
+        assert_eq!(read_configs.views.len(), 2);
+        let sqlite = read_configs.views.first().unwrap();
+        assert_eq!(sqlite.view_mode, ViewMode::Sqlite);
+        assert_eq!(sqlite.sqlite_settings.db_path, "db.sqlite");
+        assert_eq!(sqlite.sqlite_settings.synchronous, SqliteSynchronous::Off);
+        assert_eq!(sqlite.sqlite_settings.flush_interval_ms, 250);
+        let retention = sqlite.sqlite_settings.retention.as_ref().unwrap();
+        assert_eq!(retention.max_age_seconds, Some(2592000));
+        assert_eq!(retention.max_rows, None);
+        assert_eq!(retention.batch_size, 1000);
+        assert_eq!(retention.archive_directory.as_deref(), Some("archive"));
+        let rollups = retention.rollups.as_ref().unwrap();
+        assert_eq!(rollups.minute_max_age_seconds, Some(604800));
+        assert_eq!(rollups.hour_max_age_seconds, None);
+        assert_eq!(sqlite.display_name(), "Sqlite");
+        let http = read_configs.views.get(1).unwrap();
+        assert_eq!(http.view_mode, ViewMode::Http);
+        assert_eq!(http.http_settings.http_destination, "localhost:9000");
+        assert_eq!(http.queue_capacity, 16);
+        assert_eq!(http.error_budget, 20);
+        assert_eq!(sqlite.error_budget, 5);
+        assert_eq!(
+            http.filter.as_deref(),
+            Some("key == WRITE and path ~ \"/etc/**\"")
+        );
+        assert_eq!(http.display_name(), "collector");
+    }
+
+    #[test]
+    fn if_views_not_present_should_have_no_views_listed() {
+        let read_configs = configure("test_resources/all_present.json").unwrap();
+        assert!(read_configs.views.is_empty());
+        assert_eq!(read_configs.view.queue_capacity, 1024);
+        assert_eq!(read_configs.sqlite_db_path(), "reports.sqlite");
+    }
+
+    #[test]
+    fn sqlite_db_path_should_be_taken_from_listed_sqlite_view() {
+        let read_configs = configure("test_resources/views_present.json").unwrap();
+        assert_eq!(read_configs.sqlite_db_path(), "db.sqlite");
+    }
 }
@@ -0,0 +1,55 @@
+//! Audit records and the events parsed from them, shared by the tests of the filters and views.
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::view::ViewEvent;
+
+/// `ls` reading as maciek, audit event 570.
+pub(crate) const READ_LOG_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 pid=20680 comm=\"ls\" exe=\"/usr/bin/ls\" key=\"READ\"ARCH=x86_64 UID=\"maciek\" GID=\"maciek\"";
+/// `vi` writing as root, audit event 571.
+pub(crate) const WRITE_LOG_LINE: &str = "type=SYSCALL msg=audit(1698576563.120:571): arch=c000003e syscall=257 success=yes exit=3 pid=20681 comm=\"vi\" exe=\"/usr/bin/vi\" key=\"WRITE\"ARCH=x86_64 UID=\"root\" GID=\"root\"";
+pub(crate) const READ_SERIAL: u64 = 570;
+pub(crate) const READ_AUDIT_TIME: u64 = 1698576562955;
+pub(crate) const WRITE_SERIAL: u64 = 571;
+pub(crate) const WRITE_AUDIT_TIME: u64 = 1698576563120;
+
+pub(crate) fn read_operation() -> Operation {
+    return Operation::new(READ_LOG_LINE.to_string()).unwrap();
+}
+
+pub(crate) fn write_operation() -> Operation {
+    return Operation::new(WRITE_LOG_LINE.to_string()).unwrap();
+}
+
+pub(crate) fn file(name: &str, serial: u64, audit_time: u64) -> FileOperatedOn {
+    return FileOperatedOn {
+        name: name.to_string(),
+        timestamp: "123".to_string(),
+        serial,
+        audit_time,
+    };
+}
+
+/// A file touched by the read of [`READ_LOG_LINE`].
+pub(crate) fn read_file(name: &str) -> FileOperatedOn {
+    return file(name, READ_SERIAL, READ_AUDIT_TIME);
+}
+
+/// A file touched by the write of [`WRITE_LOG_LINE`].
+pub(crate) fn write_file(name: &str) -> FileOperatedOn {
+    return file(name, WRITE_SERIAL, WRITE_AUDIT_TIME);
+}
+
+/// The read of `/etc/shadow`, its operation followed by the file.
+pub(crate) fn read_events() -> Vec<ViewEvent> {
+    return vec![
+        ViewEvent::Operation(read_operation()),
+        ViewEvent::File(read_file("/etc/shadow")),
+    ];
+}
+
+/// The write of `/etc/shadow`, its operation followed by the file.
+pub(crate) fn write_events() -> Vec<ViewEvent> {
+    return vec![
+        ViewEvent::Operation(write_operation()),
+        ViewEvent::File(write_file("/etc/shadow")),
+    ];
+}
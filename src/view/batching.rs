@@ -0,0 +1,391 @@
+use crate::admin::{health, metrics, BATCH_STAGE};
+use crate::controller::dead_letter::FailedEvents;
+use crate::view::{ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use colored::Colorize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+/// How many batches may wait for the writer before new events are refused.
+const QUEUED_BATCHES: usize = 8;
+/// Pause before writing a batch again after a failure, doubled on every failure in a row.
+const RETRY_BACKOFF_MIN: Duration = Duration::from_millis(500);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Writes a whole batch of events to a backend at once.
+#[async_trait]
+pub(super) trait BatchWriter: Send + 'static {
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError>;
+
+    /// Writes what the writer held back from the batches written so far, on a flush, when the batcher stops
+    /// and a flush interval after the last write when no other batch came first.
+    async fn flush(&mut self) -> Result<(), ViewError> {
+        return Ok(());
+    }
+
+    /// Whether the writer holds back anything of the batches written so far.
+    fn holds_back(&self) -> bool {
+        return false;
+    }
+}
+
+enum BatchCommand {
+    Insert(ViewEvent),
+    Flush(oneshot::Sender<Result<(), ViewError>>),
+}
+
+/// Queues events for a [`BatchWriter`] running in its own task, which writes them once `batch_size`
+/// of them are pending, or at the latest `flush_interval` after the first one of the batch.
+/// Events the writer gives up on are handed to the failed events of the [`ViewContext`].
+pub(super) struct Batcher {
+    name: String,
+    commands: mpsc::Sender<BatchCommand>,
+}
+
+struct BatchTask<W: BatchWriter> {
+    name: String,
+    writer: W,
+    batch_size: usize,
+    flush_interval: Duration,
+    pending: Vec<ViewEvent>,
+    failed_events: Arc<FailedEvents>,
+    /// Pause after the last write, `None` when it succeeded.
+    backoff: Option<Duration>,
+}
+
+impl Batcher {
+    pub(super) fn spawn(
+        context: &ViewContext,
+        writer: impl BatchWriter,
+        batch_size: usize,
+        flush_interval: Duration,
+    ) -> Self {
+        let batch_size = batch_size.max(1);
+        let (commands, received) = mpsc::channel(batch_size * QUEUED_BATCHES);
+        let name = context.view_name.clone();
+        let task = BatchTask {
+            name: name.clone(),
+            writer,
+            batch_size,
+            flush_interval,
+            pending: Vec::with_capacity(batch_size),
+            failed_events: context.failed_events.clone(),
+            backoff: None,
+        };
+        health().register_writer(&name, context.error_budget);
+        tokio::spawn(task.run(received));
+        return Self { name, commands };
+    }
+
+    pub(super) fn enqueue(&self, event: ViewEvent) -> Result<(), ViewError> {
+        return match self.commands.try_send(BatchCommand::Insert(event)) {
+            Ok(_) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(ViewError::retryable(format!(
+                "{} writer queue is full",
+                self.name
+            ))),
+            Err(TrySendError::Closed(_)) => Err(self.stopped()),
+        };
+    }
+
+    /// Writes every event queued so far, waiting for the writer to finish.
+    pub(super) async fn flush(&self) -> Result<(), ViewError> {
+        let (acknowledge, acknowledged) = oneshot::channel();
+        self.commands
+            .send(BatchCommand::Flush(acknowledge))
+            .await
+            .map_err(|_| self.stopped())?;
+        return acknowledged.await.map_err(|_| self.stopped())?;
+    }
+
+    fn stopped(&self) -> ViewError {
+        return ViewError::permanent(format!("{} writer is no longer running", self.name));
+    }
+}
+
+impl<W: BatchWriter> BatchTask<W> {
+    async fn run(mut self, mut commands: mpsc::Receiver<BatchCommand>) {
+        let mut deadline: Option<Instant> = None;
+        loop {
+            let command = match deadline {
+                Some(at) => tokio::select! {
+                    command = commands.recv() => command,
+                    _ = tokio::time::sleep_until(at) => {
+                        deadline = match self.pending.is_empty() {
+                            true => self.flush_logging_errors().await,
+                            false => self.write_logging_errors().await,
+                        };
+                        continue;
+                    }
+                },
+                None => commands.recv().await,
+            };
+            match command {
+                Some(BatchCommand::Insert(event)) => {
+                    self.pending.push(event);
+                    metrics().set_queue_depth(&self.name, BATCH_STAGE, self.pending.len());
+                    if self.is_due() {
+                        deadline = self.write_logging_errors().await;
+                    } else if deadline.is_none() {
+                        deadline = Some(Instant::now() + self.flush_interval);
+                    }
+                }
+                Some(BatchCommand::Flush(acknowledge)) => {
+                    let result = match self.write().await {
+                        Ok(_) => self.writer.flush().await,
+                        Err(e) => Err(e),
+                    };
+                    let _ = acknowledge.send(result);
+                    deadline = self.next_deadline();
+                }
+                None => {
+                    self.write_logging_errors().await;
+                    self.flush_logging_errors().await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// A full batch is written at once, but after a failed write only when the writer's queue is full too,
+    /// otherwise it waits for the backoff to pass.
+    fn is_due(&self) -> bool {
+        return match self.backoff {
+            None => self.pending.len() >= self.batch_size,
+            Some(_) => self.pending.len() >= self.batch_size * QUEUED_BATCHES,
+        };
+    }
+
+    /// When to write the events a failed write left pending, or else to flush what the writer held back.
+    fn next_deadline(&self) -> Option<Instant> {
+        return match (self.pending.is_empty(), self.backoff) {
+            (false, Some(backoff)) => Some(Instant::now() + backoff),
+            (true, _) if self.writer.holds_back() => Some(Instant::now() + self.flush_interval),
+            _ => None,
+        };
+    }
+
+    /// Writes the pending events, returning when to try again if they are still pending.
+    async fn write_logging_errors(&mut self) -> Option<Instant> {
+        if let Err(e) = self.write().await {
+            log::error!(
+                "{} {}: {}",
+                "Error: writing batch failed".red(),
+                self.name,
+                e
+            );
+        }
+        return self.next_deadline();
+    }
+
+    /// Writes what the writer held back, returning when to try again if it still holds it back.
+    async fn flush_logging_errors(&mut self) -> Option<Instant> {
+        if let Err(e) = self.writer.flush().await {
+            log::error!(
+                "{} {}: {}",
+                "Error: flushing writer failed".red(),
+                self.name,
+                e
+            );
+        }
+        return self.next_deadline();
+    }
+
+    /// Writes the pending events, counting them as delivered. When the failure is retryable the events stay pending
+    /// for the next write, as long as they fit into the writer's queue, otherwise they are given up.
+    async fn write(&mut self) -> Result<(), ViewError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        log::info!(
+            "Writing {} events to {}",
+            self.pending.len().to_string().green(),
+            self.name
+        );
+        let started = Instant::now();
+        let result = self.writer.write(&self.pending).await;
+        metrics().record_latency(&self.name, BATCH_STAGE, started.elapsed());
+        health().record_write(&self.name, result.is_ok());
+        match &result {
+            Ok(_) => {
+                metrics().record_batch(&self.name, true, self.pending.len());
+                self.pending.clear();
+                self.backoff = None;
+            }
+            Err(e) => {
+                self.backoff = Some(match self.backoff {
+                    Some(backoff) => (backoff * 2).min(RETRY_BACKOFF_MAX),
+                    None => RETRY_BACKOFF_MIN,
+                });
+                if !e.is_retryable() || self.pending.len() >= self.batch_size * QUEUED_BATCHES {
+                    self.give_up(e).await;
+                }
+            }
+        }
+        metrics().set_queue_depth(&self.name, BATCH_STAGE, self.pending.len());
+        return result;
+    }
+
+    async fn give_up(&mut self, error: &ViewError) {
+        log::error!(
+            "{} {} events of {}",
+            "Error: giving up on".red(),
+            self.pending.len(),
+            self.name
+        );
+        metrics().record_batch(&self.name, false, self.pending.len());
+        for event in self.pending.drain(..) {
+            self.failed_events.record(&event, error).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::admin::metrics;
+    use crate::controller::dead_letter::FailedEvents;
+    use crate::serializer::FileOperatedOn;
+    use crate::view::batching::{BatchWriter, Batcher};
+    use crate::view::{ViewContext, ViewError, ViewEvent};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Records the size of every batch, failing the first `failures` writes, with a permanent error if `permanent`.
+    struct RecordingWriter {
+        batches: Arc<Mutex<Vec<usize>>>,
+        failed_writes: Arc<AtomicUsize>,
+        failures: usize,
+        permanent: bool,
+    }
+
+    /// What happened to the events handed to a test batcher.
+    struct Recorded {
+        batches: Arc<Mutex<Vec<usize>>>,
+        failed_writes: Arc<AtomicUsize>,
+        failed_events: Arc<FailedEvents>,
+    }
+
+    #[async_trait]
+    impl BatchWriter for RecordingWriter {
+        async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                self.failed_writes.fetch_add(1, Ordering::SeqCst);
+                return match self.permanent {
+                    true => Err(ViewError::permanent("Backend rejected batch")),
+                    false => Err(ViewError::retryable("Backend unavailable")),
+                };
+            }
+            self.batches.lock().unwrap().push(events.len());
+            return Ok(());
+        }
+    }
+
+    fn event() -> ViewEvent {
+        return ViewEvent::File(FileOperatedOn {
+            name: "/etc/shadow".to_string(),
+            timestamp: "123".to_string(),
+            serial: 570,
+            audit_time: 1698576562955,
+        });
+    }
+
+    fn batcher(
+        name: &str,
+        failures: usize,
+        permanent: bool,
+        flush_interval: Duration,
+    ) -> (Batcher, Recorded) {
+        let recorded = Recorded {
+            batches: Arc::new(Mutex::new(Vec::new())),
+            failed_writes: Arc::new(AtomicUsize::new(0)),
+            failed_events: Arc::new(FailedEvents::default()),
+        };
+        let writer = RecordingWriter {
+            batches: recorded.batches.clone(),
+            failed_writes: recorded.failed_writes.clone(),
+            failures,
+            permanent,
+        };
+        let context = ViewContext {
+            failed_events: recorded.failed_events.clone(),
+            view_name: name.to_string(),
+            ..Default::default()
+        };
+        let batcher = Batcher::spawn(&context, writer, 2, flush_interval);
+        return (batcher, recorded);
+    }
+
+    #[tokio::test]
+    async fn full_batches_should_be_written_at_once_and_the_rest_on_interval() {
+        let (batcher, recorded) =
+            batcher("batch-interval-test", 0, false, Duration::from_millis(100));
+
+        for _ in 0..5 {
+            batcher.enqueue(event()).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(*recorded.batches.lock().unwrap(), vec![2, 2]);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*recorded.batches.lock().unwrap(), vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn events_should_stay_pending_after_retryable_failure() {
+        let (batcher, recorded) = batcher("batch-retry-test", 1, false, Duration::from_secs(3600));
+
+        batcher.enqueue(event()).unwrap();
+        assert!(batcher.flush().await.unwrap_err().is_retryable());
+        batcher.enqueue(event()).unwrap();
+        batcher.flush().await.unwrap();
+
+        assert_eq!(*recorded.batches.lock().unwrap(), vec![2]);
+        assert_eq!(recorded.failed_events.count(), 0);
+        assert_eq!(metrics().view_events("batch-retry-test", "delivered"), 2);
+        assert_eq!(metrics().view_events("batch-retry-test", "failed"), 0);
+    }
+
+    #[tokio::test]
+    async fn events_should_be_given_up_after_permanent_failure() {
+        let (batcher, recorded) = batcher("batch-give-up-test", 1, true, Duration::from_secs(3600));
+
+        batcher.enqueue(event()).unwrap();
+        assert!(!batcher.flush().await.unwrap_err().is_retryable());
+        batcher.flush().await.unwrap();
+
+        assert!(recorded.batches.lock().unwrap().is_empty());
+        assert_eq!(recorded.failed_events.count(), 1);
+        assert_eq!(metrics().view_events("batch-give-up-test", "delivered"), 0);
+        assert_eq!(metrics().view_events("batch-give-up-test", "failed"), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_writer_should_back_off_until_its_queue_is_full() {
+        let (batcher, recorded) = batcher(
+            "batch-backoff-test",
+            usize::MAX,
+            false,
+            Duration::from_secs(3600),
+        );
+
+        for _ in 0..15 {
+            batcher.enqueue(event()).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(recorded.failed_writes.load(Ordering::SeqCst), 1);
+        assert_eq!(recorded.failed_events.count(), 0);
+
+        batcher.enqueue(event()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(recorded.failed_writes.load(Ordering::SeqCst), 2);
+        assert_eq!(recorded.failed_events.count(), 16);
+
+        tokio::time::sleep(Duration::from_millis(900)).await;
+        assert_eq!(recorded.failed_writes.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,470 @@
+use crate::admin::{self, metrics, DISPATCH_STAGE};
+use crate::controller::dead_letter::FailedEvents;
+use crate::filter::Filter;
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::view::event_format::LastOperation;
+use crate::view::{render, CompositeView, SinkDefinition, View, ViewError, ViewEvent};
+use async_trait::async_trait;
+use colored::Colorize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::oneshot;
+
+const HEALTH_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(super) struct Sink {
+    name: String,
+    queue: mpsc::Sender<SinkMessage>,
+    health: Arc<SinkHealth>,
+    filter: Option<Filter>,
+}
+
+enum SinkMessage {
+    Render(ViewEvent),
+    Flush(oneshot::Sender<Result<(), ViewError>>),
+}
+
+#[derive(Default)]
+struct SinkHealth {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    consecutive_failures: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SinkHealthReport {
+    name: String,
+    delivered: u64,
+    failed: u64,
+    dropped: u64,
+    consecutive_failures: u64,
+    queue_depth: usize,
+}
+
+impl CompositeView {
+    /// Every view gets a bounded queue of `queue_capacity` events and a task of its own,
+    /// so a slow or failing view never holds back the others. Events a view fails to render are
+    /// counted and dead lettered in `failed_events`.
+    pub(crate) fn new(definitions: Vec<SinkDefinition>, failed_events: Arc<FailedEvents>) -> Self {
+        let sinks: Arc<Vec<Sink>> = Arc::new(
+            definitions
+                .into_iter()
+                .map(|definition| Sink::spawn(definition, failed_events.clone()))
+                .collect(),
+        );
+        let reported = sinks.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_REPORT_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                health_of(&reported).iter().for_each(log_health);
+            }
+        });
+        return Self {
+            sinks,
+            last_operation: LastOperation::default(),
+        };
+    }
+
+    /// File events are filtered together with the operation of their audit event, which is the one
+    /// observed right before them; a file of any other event is filtered without an operation.
+    /// Views which could not take the event in are named in the returned error; it is permanent,
+    /// as retrying would duplicate it in the other views.
+    fn dispatch(&self, event: ViewEvent) -> Result<(), ViewError> {
+        let last_operation = match &event {
+            ViewEvent::Operation(operation) => {
+                self.last_operation.remember(operation);
+                None
+            }
+            ViewEvent::File(file) => self.last_operation.of(file),
+        };
+        let (operation, file) = match &event {
+            ViewEvent::Operation(operation) => (Some(operation), None),
+            ViewEvent::File(file) => (last_operation.as_ref(), Some(file)),
+        };
+        let mut dropped_by = Vec::new();
+        for sink in self.sinks.iter() {
+            if let Some(filter) = &sink.filter {
+                if !filter.matches(operation, file) {
+                    continue;
+                }
+            }
+            let sent = sink.queue.try_send(SinkMessage::Render(event.clone()));
+            metrics().set_queue_depth(&sink.name, DISPATCH_STAGE, queue_depth(&sink.queue));
+            match sent {
+                Ok(_) => {}
+                Err(TrySendError::Full(_)) => {
+                    sink.health.dropped.fetch_add(1, Ordering::Relaxed);
+                    metrics().record_drop(&sink.name);
+                    log::warn!(
+                        "{} queue of view {} is full, dropping event.",
+                        "Warning:".yellow(),
+                        sink.name.cyan()
+                    );
+                    dropped_by.push(sink.name.clone());
+                }
+                Err(TrySendError::Closed(_)) => {
+                    sink.health.dropped.fetch_add(1, Ordering::Relaxed);
+                    metrics().record_drop(&sink.name);
+                    log::error!("View {} is no longer running.", sink.name.red());
+                    dropped_by.push(sink.name.clone());
+                }
+            }
+        }
+        return match dropped_by.is_empty() {
+            true => Ok(()),
+            false => Err(ViewError::permanent(format!(
+                "Event dropped by views: {}",
+                dropped_by.join(", ")
+            ))),
+        };
+    }
+}
+
+impl Sink {
+    fn spawn(definition: SinkDefinition, failed_events: Arc<FailedEvents>) -> Self {
+        let SinkDefinition {
+            name,
+            view,
+            queue_capacity,
+            filter,
+        } = definition;
+        let (queue, mut receiver) = mpsc::channel::<SinkMessage>(queue_capacity.max(1));
+        let health = Arc::new(SinkHealth::default());
+        let worker_health = health.clone();
+        let worker_name = name.clone();
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                metrics().set_queue_depth(&worker_name, DISPATCH_STAGE, receiver.len());
+                let event = match message {
+                    SinkMessage::Render(event) => event,
+                    SinkMessage::Flush(acknowledge) => {
+                        let _ = acknowledge.send(view.flush().await);
+                        continue;
+                    }
+                };
+                let started = Instant::now();
+                let rendered = render(view.as_ref(), &event).await;
+                match view.renders_in_background() {
+                    true => metrics().record_handover(&worker_name, &rendered, started.elapsed()),
+                    false => metrics().record_render(&worker_name, &rendered, started.elapsed()),
+                }
+                admin::health().record_view(&worker_name, rendered.is_ok());
+                match rendered {
+                    Ok(_) => worker_health.record_delivery(),
+                    Err(e) => {
+                        worker_health.record_failure();
+                        let failed = failed_events.record(&event, &e).await;
+                        log::error!(
+                            "View {} failed to render an event: {} ({} events failed so far)",
+                            worker_name.red(),
+                            e,
+                            failed
+                        );
+                    }
+                }
+            }
+        });
+        return Self {
+            name,
+            queue,
+            health,
+            filter,
+        };
+    }
+}
+
+impl SinkHealth {
+    fn record_delivery(&self) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn report(&self, name: &str, queue: &mpsc::Sender<SinkMessage>) -> SinkHealthReport {
+        return SinkHealthReport {
+            name: name.to_string(),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            queue_depth: queue_depth(queue),
+        };
+    }
+}
+
+fn queue_depth(queue: &mpsc::Sender<SinkMessage>) -> usize {
+    return queue.max_capacity() - queue.capacity();
+}
+
+fn health_of(sinks: &[Sink]) -> Vec<SinkHealthReport> {
+    return sinks
+        .iter()
+        .map(|sink| sink.health.report(&sink.name, &sink.queue))
+        .collect();
+}
+
+fn log_health(report: &SinkHealthReport) {
+    let status = match report.consecutive_failures {
+        0 => "healthy".green(),
+        _ => "failing".red(),
+    };
+    log::info!(
+        "View {} is {}: delivered {}, failed {}, dropped {}, queued {}.",
+        report.name.cyan(),
+        status,
+        report.delivered,
+        report.failed,
+        report.dropped,
+        report.queue_depth
+    );
+}
+
+#[async_trait]
+impl View for CompositeView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.dispatch(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.dispatch(ViewEvent::File(files));
+    }
+
+    /// Waits for every view to drain its queue and flush, reporting the first failure.
+    async fn flush(&self) -> Result<(), ViewError> {
+        let mut result = Ok(());
+        for sink in self.sinks.iter() {
+            let (acknowledge, acknowledged) = oneshot::channel();
+            let flushed = match sink.queue.send(SinkMessage::Flush(acknowledge)).await {
+                Ok(_) => acknowledged.await.unwrap_or_else(|_| {
+                    Err(ViewError::permanent(format!("View {} stopped", sink.name)))
+                }),
+                Err(_) => Err(ViewError::permanent(format!("View {} stopped", sink.name))),
+            };
+            if let Err(e) = flushed {
+                log::error!("View {} failed to flush: {}", sink.name.red(), e);
+                result = result.and(Err(e));
+            }
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::admin::metrics;
+    use crate::controller::dead_letter::FailedEvents;
+    use crate::filter::Filter;
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::settings::SqliteSettings;
+    use crate::test_fixtures::{file, read_operation, WRITE_AUDIT_TIME, WRITE_SERIAL};
+    use crate::view::composite_view::health_of;
+    use crate::view::{CompositeView, SinkDefinition, SqliteView, View, ViewContext, ViewError};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    struct CountingView {
+        rendered: Arc<AtomicUsize>,
+        delay: Duration,
+        fails: bool,
+    }
+
+    #[async_trait]
+    impl View for CountingView {
+        async fn update(&self, _operation: Operation) -> Result<(), ViewError> {
+            tokio::time::sleep(self.delay).await;
+            self.rendered.fetch_add(1, Ordering::SeqCst);
+            return match self.fails {
+                true => Err(ViewError::permanent("Rendering")),
+                false => Ok(()),
+            };
+        }
+
+        async fn report(&self, _files: FileOperatedOn) -> Result<(), ViewError> {
+            self.rendered.fetch_add(1, Ordering::SeqCst);
+            return Ok(());
+        }
+    }
+
+    fn sink(
+        name: &str,
+        view: Box<dyn View>,
+        queue_capacity: usize,
+        filter: Option<&str>,
+    ) -> SinkDefinition {
+        return SinkDefinition {
+            name: name.to_string(),
+            view,
+            queue_capacity,
+            filter: filter.map(|x| Filter::parse(x).unwrap()),
+        };
+    }
+
+    fn counting_view(delay: Duration, fails: bool) -> (Box<dyn View>, Arc<AtomicUsize>) {
+        let rendered = Arc::new(AtomicUsize::new(0));
+        let view = CountingView {
+            rendered: rendered.clone(),
+            delay,
+            fails,
+        };
+        return (Box::new(view), rendered);
+    }
+
+    fn composite(sinks: Vec<SinkDefinition>) -> CompositeView {
+        return CompositeView::new(sinks, Arc::new(FailedEvents::new(None)));
+    }
+
+    #[tokio::test]
+    async fn slow_view_should_not_stall_other_views() {
+        let (slow, slow_rendered) = counting_view(Duration::from_secs(60), false);
+        let (fast, fast_rendered) = counting_view(Duration::ZERO, false);
+        let composite = composite(vec![
+            sink("slow", slow, 1, None),
+            sink("fast", fast, 16, None),
+        ]);
+
+        let mut results = Vec::new();
+        for _ in 0..5 {
+            results.push(composite.update(read_operation()).await);
+        }
+        let rejected = results.iter().filter(|x| x.is_err()).count();
+        assert_eq!(rejected, 4);
+        assert!(!results.last().unwrap().as_ref().unwrap_err().is_retryable());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(fast_rendered.load(Ordering::SeqCst), 5);
+        assert_eq!(slow_rendered.load(Ordering::SeqCst), 0);
+        let health = health_of(&composite.sinks);
+        assert_eq!(health.first().unwrap().dropped, 4);
+        assert_eq!(health.get(1).unwrap().delivered, 5);
+        assert_eq!(health.get(1).unwrap().dropped, 0);
+        assert_eq!(metrics().view_events("slow", "dropped"), 4);
+        assert_eq!(metrics().view_events("fast", "delivered"), 5);
+    }
+
+    #[tokio::test]
+    async fn batched_view_should_count_events_once_written() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("batched.db");
+        let context = ViewContext {
+            view_name: "batched".to_string(),
+            ..Default::default()
+        };
+        let batched = SqliteView::new(&SqliteSettings::new(db_path.to_str().unwrap()), &context);
+        let composite = composite(vec![sink("batched", Box::new(batched), 16, None)]);
+
+        composite.update(read_operation()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(metrics().view_events("batched", "delivered"), 0);
+        composite.flush().await.unwrap();
+
+        assert_eq!(metrics().view_events("batched", "delivered"), 1);
+    }
+
+    #[tokio::test]
+    async fn failing_view_should_be_reported_per_sink() {
+        let (failing, _) = counting_view(Duration::ZERO, true);
+        let (working, _) = counting_view(Duration::ZERO, false);
+        let composite = composite(vec![
+            sink("failing", failing, 16, None),
+            sink("working", working, 16, None),
+        ]);
+
+        composite.update(read_operation()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let health = health_of(&composite.sinks);
+        assert_eq!(health.first().unwrap().failed, 1);
+        assert_eq!(health.first().unwrap().consecutive_failures, 1);
+        assert_eq!(health.get(1).unwrap().delivered, 1);
+        assert_eq!(health.get(1).unwrap().consecutive_failures, 0);
+        assert_eq!(metrics().view_events("failing", "failed"), 1);
+        assert_eq!(metrics().view_events("working", "delivered"), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_events_should_be_counted_and_dead_lettered() {
+        let directory = tempdir().unwrap();
+        let dead_letter_path = directory.path().join("dead_letters.jsonl");
+        let failed_events = Arc::new(FailedEvents::new(dead_letter_path.to_str()));
+        let (failing, _) = counting_view(Duration::ZERO, true);
+        let composite = CompositeView::new(
+            vec![sink("dead letters", failing, 16, None)],
+            failed_events.clone(),
+        );
+
+        composite.update(read_operation()).await.unwrap();
+        composite.flush().await.unwrap();
+
+        assert_eq!(failed_events.count(), 1);
+        let dead_letters = std::fs::read_to_string(dead_letter_path).unwrap();
+        assert_eq!(dead_letters.lines().count(), 1);
+        assert!(dead_letters.contains("/usr/bin/ls"));
+    }
+
+    #[tokio::test]
+    async fn files_should_be_routed_with_filter_of_their_operation() {
+        let (everything, everything_rendered) = counting_view(Duration::ZERO, false);
+        let (etc_reads, etc_reads_rendered) = counting_view(Duration::ZERO, false);
+        let (writes, writes_rendered) = counting_view(Duration::ZERO, false);
+        let composite = composite(vec![
+            sink("everything", everything, 16, None),
+            sink(
+                "etc reads",
+                etc_reads,
+                16,
+                Some("key == READ and path ~ \"/etc/**\""),
+            ),
+            sink("writes", writes, 16, Some("key == WRITE")),
+        ]);
+
+        composite.update(read_operation()).await.unwrap();
+        for name in ["/etc/ssh/sshd_config", "/home/maciek/notes"] {
+            composite
+                .report(FileOperatedOn {
+                    name: name.to_string(),
+                    timestamp: "123".to_string(),
+                    serial: 570,
+                    audit_time: 1698576562955,
+                })
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(everything_rendered.load(Ordering::SeqCst), 3);
+        assert_eq!(etc_reads_rendered.load(Ordering::SeqCst), 1);
+        assert_eq!(writes_rendered.load(Ordering::SeqCst), 0);
+    }
+    #[tokio::test]
+    async fn file_of_other_event_should_not_be_routed_with_filter_of_last_operation() {
+        let (everything, everything_rendered) = counting_view(Duration::ZERO, false);
+        let (reads, reads_rendered) = counting_view(Duration::ZERO, false);
+        let composite = composite(vec![
+            sink("everything", everything, 16, None),
+            sink("reads", reads, 16, Some("key == READ")),
+        ]);
+
+        composite.update(read_operation()).await.unwrap();
+        composite
+            .report(file("/etc/shadow", WRITE_SERIAL, WRITE_AUDIT_TIME))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(everything_rendered.load(Ordering::SeqCst), 2);
+        assert_eq!(reads_rendered.load(Ordering::SeqCst), 1);
+    }
+}
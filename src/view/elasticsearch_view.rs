@@ -0,0 +1,380 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::{ElasticsearchSettings, EventFormat};
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{format_file, format_operation, LastOperation};
+use crate::view::{ElasticsearchView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::time::Duration;
+
+const BULK_CONTENT_TYPE: &str = "application/x-ndjson";
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// The document already exists, which is what an idempotent id is for.
+const CONFLICT_STATUS: u64 = 409;
+const TOO_MANY_REQUESTS_STATUS: u64 = 429;
+
+/// Writes batches of events through the `_bulk` API, as ECS documents.
+pub(super) struct ElasticsearchWriter {
+    client: Client,
+    bulk_url: String,
+    index: String,
+    username: Option<String>,
+    password: Option<String>,
+    max_retries: usize,
+    retry_backoff: Duration,
+    last_operation: LastOperation,
+}
+
+/// One `create` action of a bulk request with the document that follows it.
+struct BulkItem {
+    action: String,
+    document: String,
+}
+
+/// What became of the items of a bulk request.
+#[derive(Default)]
+struct BulkOutcome {
+    retryable: Vec<usize>,
+    rejected: Vec<String>,
+}
+
+impl ElasticsearchView {
+    pub(crate) fn new(settings: &ElasticsearchSettings, context: &ViewContext) -> Self {
+        if let Err(e) = index_name(&settings.index, 0) {
+            panic!(
+                "Fatal: {} is not a valid index template: {}",
+                settings.index, e
+            );
+        }
+        let batcher = Batcher::spawn(
+            context,
+            ElasticsearchWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for ElasticsearchView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for ElasticsearchWriter {
+    /// Sends the items the cluster was too busy for again, up to `max_retries` times. Documents it rejected
+    /// fail the batch, after everything else got indexed.
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut items = Vec::with_capacity(events.len());
+        for event in events {
+            items.push(self.item(event)?);
+        }
+        let mut rejected = Vec::new();
+        for attempt in 0..=self.max_retries {
+            if attempt > 0 {
+                log::warn!(
+                    "{} {} of them, attempt {}",
+                    "Elasticsearch could not index every document, retrying".yellow(),
+                    items.len(),
+                    attempt
+                );
+                tokio::time::sleep(self.retry_backoff * attempt as u32).await;
+            }
+            let outcome = self.send(&items).await?;
+            rejected.extend(outcome.rejected);
+            items = items
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| outcome.retryable.contains(i))
+                .map(|(_, item)| item)
+                .collect();
+            if items.is_empty() {
+                break;
+            }
+        }
+        if !items.is_empty() {
+            return Err(ViewError::retryable(format!(
+                "Elasticsearch did not index {} documents after {} retries",
+                items.len(),
+                self.max_retries
+            )));
+        }
+        return match rejected.first() {
+            Some(reason) => Err(ViewError::permanent(format!(
+                "Elasticsearch rejected {} documents, the first one because of {}",
+                rejected.len(),
+                reason
+            ))),
+            None => Ok(()),
+        };
+    }
+}
+
+impl ElasticsearchWriter {
+    pub(super) fn new(settings: &ElasticsearchSettings) -> Self {
+        return Self {
+            client: Client::new(),
+            bulk_url: format!("{}/_bulk", settings.url.trim_end_matches('/')),
+            index: settings.index.clone(),
+            username: settings.username.clone(),
+            password: settings.password.clone(),
+            max_retries: settings.max_retries,
+            retry_backoff: RETRY_BACKOFF,
+            last_operation: LastOperation::default(),
+        };
+    }
+
+    /// Files are indexed together with the operation that touched them, when it came before them.
+    fn item(&self, event: &ViewEvent) -> Result<BulkItem, ViewError> {
+        let (id, audit_time, document) = match event {
+            ViewEvent::Operation(operation) => {
+                self.last_operation.remember(operation);
+                (
+                    format!("{}-{}", operation.audit_time, operation.serial),
+                    operation.audit_time,
+                    format_operation(EventFormat::Ecs, operation),
+                )
+            }
+            ViewEvent::File(file) => {
+                let operation = self.last_operation.of(file);
+                (
+                    format!(
+                        "{}-{}-{:016x}",
+                        file.audit_time,
+                        file.serial,
+                        fnv1a(&file.name)
+                    ),
+                    file.audit_time,
+                    format_file(EventFormat::Ecs, file, operation.as_ref()),
+                )
+            }
+        };
+        let index = index_name(&self.index, audit_time)
+            .map_err(|e| ViewError::permanent("Naming index").caused_by(e))?;
+        let action = json!({ "create": { "_index": index, "_id": id } }).to_string();
+        return Ok(BulkItem { action, document });
+    }
+
+    async fn send(&self, items: &[BulkItem]) -> Result<BulkOutcome, ViewError> {
+        let mut body = String::new();
+        for item in items {
+            body.push_str(&item.action);
+            body.push('\n');
+            body.push_str(&item.document);
+            body.push('\n');
+        }
+        let mut request = self
+            .client
+            .post(&self.bulk_url)
+            .header(reqwest::header::CONTENT_TYPE, BULK_CONTENT_TYPE)
+            .body(body);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let response: Value = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ViewError::from_http(format!("Posting to {}", self.bulk_url), e))?
+            .json()
+            .await
+            .map_err(|e| ViewError::from_http("Reading bulk response", e))?;
+        return Ok(bulk_outcome(&response));
+    }
+}
+
+/// Sorts the failed items of a bulk response into the ones worth another try and the rejected ones.
+fn bulk_outcome(response: &Value) -> BulkOutcome {
+    let mut outcome = BulkOutcome::default();
+    if response["errors"] != Value::Bool(true) {
+        return outcome;
+    }
+    let items = response["items"].as_array().cloned().unwrap_or_default();
+    for (i, item) in items.iter().enumerate() {
+        let result = match item.as_object().and_then(|x| x.values().next()) {
+            Some(result) => result,
+            None => continue,
+        };
+        let status = result["status"].as_u64().unwrap_or_default();
+        if status < 300 || status == CONFLICT_STATUS {
+            continue;
+        }
+        if status == TOO_MANY_REQUESTS_STATUS || status >= 500 {
+            outcome.retryable.push(i);
+        } else {
+            outcome.rejected.push(format!(
+                "{}: {}",
+                result["error"]["type"].as_str().unwrap_or("unknown"),
+                result["error"]["reason"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+    return outcome;
+}
+
+/// The index template filled in with the day of the event, failing on an invalid template.
+fn index_name(template: &str, audit_time: u64) -> Result<String, std::fmt::Error> {
+    let time = DateTime::from_timestamp_millis(audit_time as i64).unwrap_or_else(Utc::now);
+    let mut name = String::new();
+    write!(name, "{}", time.format(template))?;
+    return Ok(name);
+}
+
+/// A hash that stays the same across builds, so a path always gets the same document id.
+fn fnv1a(value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in value.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::ElasticsearchSettings;
+    use crate::test_fixtures::read_events;
+    use crate::view::batching::BatchWriter;
+    use crate::view::elasticsearch_view::ElasticsearchWriter;
+    use crate::view::{ElasticsearchView, View, ViewContext, ViewEvent};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Answers every bulk request with `statuses` for its items, until they run out and everything succeeds.
+    async fn bulk_server(
+        statuses: Vec<Vec<u64>>,
+    ) -> (mockito::ServerGuard, mockito::Mock, Arc<Mutex<Vec<String>>>) {
+        let mut server = mockito::Server::new_async().await;
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        let mock = server
+            .mock("POST", "/_bulk")
+            .match_header("content-type", "application/x-ndjson")
+            .with_body_from_request(move |request| {
+                let body = String::from_utf8_lossy(request.body().unwrap()).to_string();
+                let items = body.lines().count() / 2;
+                let mut bodies = received.lock().unwrap();
+                let statuses = statuses
+                    .get(bodies.len())
+                    .cloned()
+                    .unwrap_or_else(|| vec![201; items]);
+                bodies.push(body);
+                let items: Vec<Value> = statuses
+                    .iter()
+                    .map(|status| {
+                        json!({ "create": {
+                            "status": status,
+                            "error": { "type": "mapper_parsing_exception", "reason": "failed to parse" }
+                        }})
+                    })
+                    .collect();
+                let errors = statuses.iter().any(|x| *x >= 300);
+                return json!({ "errors": errors, "items": items })
+                    .to_string()
+                    .into_bytes();
+            })
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        return (server, mock, bodies);
+    }
+
+    fn writer(server: &mockito::ServerGuard) -> ElasticsearchWriter {
+        let mut writer = ElasticsearchWriter::new(&ElasticsearchSettings::new(&server.url()));
+        writer.retry_backoff = Duration::ZERO;
+        return writer;
+    }
+
+    #[tokio::test]
+    async fn events_should_be_created_in_daily_index_under_idempotent_ids() {
+        let (server, mock, bodies) = bulk_server(vec![]).await;
+        let elasticsearch_view = ElasticsearchView::new(
+            &ElasticsearchSettings::new(&server.url()),
+            &ViewContext::default(),
+        );
+
+        for event in read_events() {
+            match event {
+                ViewEvent::Operation(operation) => elasticsearch_view.update(operation).await,
+                ViewEvent::File(file) => elasticsearch_view.report(file).await,
+            }
+            .unwrap();
+        }
+        elasticsearch_view.flush().await.unwrap();
+
+        mock.assert_async().await;
+        let bodies = bodies.lock().unwrap();
+        let lines: Vec<Value> = bodies[0]
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            json!({ "create": { "_index": "linux-fs-audit-2023.10.29", "_id": "1698576562955-570" } })
+        );
+        assert_eq!(lines[1]["process"]["executable"], "/usr/bin/ls");
+        let file_id = lines[2]["create"]["_id"].as_str().unwrap();
+        assert!(file_id.starts_with("1698576562955-570-"));
+        assert_eq!(lines[3]["file"]["path"], "/etc/shadow");
+        assert_eq!(lines[3]["user"]["name"], "maciek");
+    }
+
+    #[tokio::test]
+    async fn only_items_rejected_as_too_many_requests_should_be_sent_again() {
+        let (server, _mock, bodies) = bulk_server(vec![vec![201, 429], vec![503]]).await;
+        let mut writer = writer(&server);
+
+        writer.write(&read_events()).await.unwrap();
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 3);
+        assert_eq!(bodies[1].lines().count(), 2);
+        assert_eq!(bodies[1], bodies[2]);
+        assert!(bodies[2].contains("/etc/shadow"));
+    }
+
+    #[tokio::test]
+    async fn rejected_items_should_fail_batch_but_conflicts_should_not() {
+        let (server, _mock, bodies) = bulk_server(vec![vec![409, 400]]).await;
+        let mut writer = writer(&server);
+
+        let error = writer.write(&read_events()).await.unwrap_err();
+
+        assert!(!error.is_retryable());
+        assert!(error.to_string().contains("mapper_parsing_exception"));
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn items_still_failing_after_retries_should_fail_batch_as_retryable() {
+        let (server, _mock, bodies) = bulk_server(vec![vec![429, 429]; 5]).await;
+        let mut writer = writer(&server);
+        writer.max_retries = 2;
+
+        let error = writer.write(&read_events()).await.unwrap_err();
+
+        assert!(error.is_retryable());
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,443 @@
+use crate::serializer::{FileOperatedOn, Operation, OperationKey};
+use crate::settings::EventFormat;
+use crate::view::ViewError;
+use chrono::{DateTime, SecondsFormat};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+const VENDOR: &str = "linux-fs-audit";
+const PRODUCT: &str = "linux-fs-audit";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const CEF_VERSION: &str = "CEF:0";
+const LEEF_VERSION: &str = "LEEF:1.0";
+/// The default `devTime` layout QRadar recognises without a `devTimeFormat`.
+const LEEF_TIME_FORMAT: &str = "%b %d %Y %H:%M:%S%.3f UTC";
+const CEF_READ_SEVERITY: u8 = 3;
+const CEF_WRITE_SEVERITY: u8 = 5;
+const ECS_VERSION: &str = "8.11.0";
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+
+/// A single json line, the fields of the event tagged with its kind.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(super) enum JsonLine<'a> {
+    Operation(&'a Operation),
+    File(&'a FileOperatedOn),
+}
+
+/// The event as a single json line.
+pub(super) fn to_json(line: JsonLine) -> Result<String, ViewError> {
+    return serde_json::to_string(&line)
+        .map_err(|e| ViewError::permanent("Serializing event").caused_by(e));
+}
+
+/// Remembers the last operation, so that the files reported after it can be described together with it.
+#[derive(Default)]
+pub(super) struct LastOperation {
+    operation: Mutex<Option<Operation>>,
+}
+
+impl LastOperation {
+    pub(super) fn remember(&self, operation: &Operation) {
+        *self.operation.lock().unwrap() = Some(operation.clone());
+    }
+
+    /// The operation of the same audit event as `file`, if it was the last one seen.
+    pub(super) fn of(&self, file: &FileOperatedOn) -> Option<Operation> {
+        return self
+            .operation
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|x| x.serial == file.serial && x.audit_time == file.audit_time)
+            .cloned();
+    }
+}
+
+/// Name of this machine, empty when it cannot be read.
+pub(super) fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    return HOSTNAME.get_or_init(|| {
+        std::fs::read_to_string(HOSTNAME_PATH)
+            .map(|x| x.trim().to_string())
+            .unwrap_or_default()
+    });
+}
+
+pub(super) fn format_operation(format: EventFormat, operation: &Operation) -> String {
+    let fields = operation_fields(operation);
+    return match format {
+        EventFormat::Ecs => ecs(&fields, Some(&operation.key), "operation").to_string(),
+        EventFormat::Cef => cef(
+            &operation.key.to_string(),
+            &format!("{} operation", operation.key),
+            cef_severity(Some(&operation.key)),
+            &fields,
+        ),
+        EventFormat::Leef => leef(&operation.key.to_string(), "operation", &fields),
+    };
+}
+
+/// Describes the file together with the operation that touched it, when it is known.
+pub(super) fn format_file(
+    format: EventFormat,
+    file: &FileOperatedOn,
+    operation: Option<&Operation>,
+) -> String {
+    let key = operation.map(|x| &x.key);
+    let event_id = match key {
+        Some(key) => key.to_string(),
+        None => String::from("FILE"),
+    };
+    let mut fields = match operation {
+        Some(operation) => operation_fields(operation),
+        None => vec![
+            (Field::Time, file.audit_time.to_string()),
+            (Field::Serial, file.serial.to_string()),
+        ],
+    };
+    fields.push((Field::Path, file.name.clone()));
+    let name = Path::new(&file.name)
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fields.push((Field::Name, name));
+    return match format {
+        EventFormat::Ecs => ecs(&fields, key, "file").to_string(),
+        EventFormat::Cef => cef(
+            &event_id,
+            &format!("{} file", event_id),
+            cef_severity(key),
+            &fields,
+        ),
+        EventFormat::Leef => leef(&event_id, "file", &fields),
+    };
+}
+
+/// The event model, each field named by the keys CEF and LEEF give it.
+#[derive(Clone, Copy)]
+enum Field {
+    Time,
+    Serial,
+    User,
+    Group,
+    Executable,
+    Command,
+    Syscall,
+    Key,
+    Success,
+    Path,
+    Name,
+}
+
+impl Field {
+    /// Fields without a key of their own go into labelled custom strings.
+    fn cef_key(&self) -> (&'static str, Option<&'static str>) {
+        return match self {
+            Field::Time => ("rt", None),
+            Field::Serial => ("externalId", None),
+            Field::User => ("suser", None),
+            Field::Group => ("cs3", Some("group")),
+            Field::Executable => ("sproc", None),
+            Field::Command => ("cs2", Some("command")),
+            Field::Syscall => ("cs1", Some("syscall")),
+            Field::Key => ("act", None),
+            Field::Success => ("outcome", None),
+            Field::Path => ("filePath", None),
+            Field::Name => ("fname", None),
+        };
+    }
+
+    fn leef_key(&self) -> &'static str {
+        return match self {
+            Field::Time => "devTime",
+            Field::Serial => "serial",
+            Field::User => "usrName",
+            Field::Group => "groupName",
+            Field::Executable => "executable",
+            Field::Command => "command",
+            Field::Syscall => "syscall",
+            Field::Key => "action",
+            Field::Success => "outcome",
+            Field::Path => "filePath",
+            Field::Name => "fileName",
+        };
+    }
+}
+
+fn operation_fields(operation: &Operation) -> Vec<(Field, String)> {
+    let outcome = match operation.success {
+        true => "success",
+        false => "failure",
+    };
+    return vec![
+        (Field::Time, operation.audit_time.to_string()),
+        (Field::Serial, operation.serial.to_string()),
+        (Field::User, operation.user.clone()),
+        (Field::Group, operation.group.clone()),
+        (Field::Executable, operation.executable.clone()),
+        (Field::Command, operation.command.clone()),
+        (Field::Syscall, operation.syscall.clone()),
+        (Field::Key, operation.key.to_string()),
+        (Field::Success, outcome.to_string()),
+    ];
+}
+
+/// Nests the fields the way Elastic Common Schema lays them out, the syscall where Auditbeat puts it.
+fn ecs(fields: &[(Field, String)], key: Option<&OperationKey>, kind: &str) -> Value {
+    let mut document = json!({
+        "ecs": { "version": ECS_VERSION },
+        "event": {
+            "kind": "event",
+            "category": ["file"],
+            "type": [match key {
+                Some(OperationKey::WRITE) => "change",
+                _ => "access",
+            }],
+            "dataset": format!("{}.{}", VENDOR, kind),
+        },
+    });
+    if !hostname().is_empty() {
+        set(&mut document, &["host", "name"], hostname().into());
+        set(&mut document, &["host", "hostname"], hostname().into());
+    }
+    for (field, value) in fields {
+        let value = value.clone();
+        match field {
+            Field::Time => {
+                let timestamp = value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_millis)
+                    .map(|x| x.to_rfc3339_opts(SecondsFormat::Millis, true));
+                if let Some(timestamp) = timestamp {
+                    set(&mut document, &["@timestamp"], timestamp.into());
+                }
+            }
+            Field::Serial => {
+                let serial = value
+                    .parse::<u64>()
+                    .map(Value::from)
+                    .unwrap_or(value.into());
+                set(&mut document, &["event", "sequence"], serial);
+            }
+            Field::User => set(&mut document, &["user", "name"], value.into()),
+            Field::Group => set(&mut document, &["user", "group", "name"], value.into()),
+            Field::Executable => {
+                let name = Path::new(&value)
+                    .file_name()
+                    .map(|x| x.to_string_lossy().to_string());
+                if let Some(name) = name {
+                    set(&mut document, &["process", "name"], name.into());
+                }
+                set(&mut document, &["process", "executable"], value.into());
+            }
+            Field::Command => set(&mut document, &["process", "command_line"], value.into()),
+            Field::Syscall => set(&mut document, &["auditd", "data", "syscall"], value.into()),
+            Field::Key => set(
+                &mut document,
+                &["event", "action"],
+                value.to_lowercase().into(),
+            ),
+            Field::Success => set(&mut document, &["event", "outcome"], value.into()),
+            Field::Path => {
+                let directory = Path::new(&value)
+                    .parent()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .filter(|x| !x.is_empty());
+                if let Some(directory) = directory {
+                    set(&mut document, &["file", "directory"], directory.into());
+                }
+                set(&mut document, &["file", "path"], value.into());
+            }
+            Field::Name => set(&mut document, &["file", "name"], value.into()),
+        }
+    }
+    return document;
+}
+
+/// Puts `value` under the nested `path`, creating the objects on the way.
+fn set(document: &mut Value, path: &[&str], value: Value) {
+    let (last, parents) = path.split_last().unwrap();
+    let mut current = document;
+    for name in parents {
+        current = current
+            .as_object_mut()
+            .unwrap()
+            .entry(*name)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    current
+        .as_object_mut()
+        .unwrap()
+        .insert(last.to_string(), value);
+}
+
+fn cef_severity(key: Option<&OperationKey>) -> u8 {
+    return match key {
+        Some(OperationKey::WRITE) => CEF_WRITE_SEVERITY,
+        _ => CEF_READ_SEVERITY,
+    };
+}
+
+/// `CEF:0|Vendor|Product|Version|Signature ID|Name|Severity|key=value ...`
+fn cef(signature: &str, name: &str, severity: u8, fields: &[(Field, String)]) -> String {
+    let mut extension = Vec::with_capacity(fields.len());
+    for (field, value) in fields {
+        let (key, label) = field.cef_key();
+        if let Some(label) = label {
+            extension.push(format!("{}Label={}", key, label));
+        }
+        extension.push(format!("{}={}", key, escape_cef_value(value)));
+    }
+    let header = [VENDOR, PRODUCT, VERSION, signature, name]
+        .map(escape_header)
+        .join("|");
+    return format!(
+        "{}|{}|{}|{}",
+        CEF_VERSION,
+        header,
+        severity,
+        extension.join(" ")
+    );
+}
+
+/// `LEEF:1.0|Vendor|Product|Version|EventID|key=value<tab>...`
+fn leef(event_id: &str, category: &str, fields: &[(Field, String)]) -> String {
+    let mut attributes = vec![format!("cat={}", category)];
+    for (field, value) in fields {
+        let value = match field {
+            Field::Time => leef_time(value),
+            _ => value.clone(),
+        };
+        attributes.push(format!(
+            "{}={}",
+            field.leef_key(),
+            escape_leef_value(&value)
+        ));
+    }
+    let header = [VENDOR, PRODUCT, VERSION, event_id]
+        .map(escape_header)
+        .join("|");
+    return format!("{}|{}|{}", LEEF_VERSION, header, attributes.join("\t"));
+}
+
+fn leef_time(audit_time: &str) -> String {
+    return audit_time
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .map(|x| x.format(LEEF_TIME_FORMAT).to_string())
+        .unwrap_or_default();
+}
+
+/// Header fields of both formats escape `\` and the `|` separating them.
+fn escape_header(value: &str) -> String {
+    return value.replace('\\', "\\\\").replace('|', "\\|");
+}
+
+/// CEF extension values escape `\` and `=`, and may not span lines.
+fn escape_cef_value(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+}
+
+/// LEEF attributes are separated by tabs, so values may contain neither tabs nor line breaks.
+fn escape_leef_value(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::EventFormat;
+    use crate::test_fixtures::{read_file, write_file, write_operation};
+    use crate::view::event_format::{format_file, format_operation, hostname, LastOperation};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn operation_should_map_to_cef_extension() {
+        let cef = format_operation(EventFormat::Cef, &write_operation());
+
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            cef,
+            format!("CEF:0|linux-fs-audit|linux-fs-audit|{}|WRITE|WRITE operation|5|rt=1698576563120 externalId=571 suser=root cs3Label=group cs3=root sproc=/usr/bin/vi cs2Label=command cs2=vi cs1Label=syscall cs1=unknown act=WRITE outcome=success", version)
+        );
+    }
+
+    #[test]
+    fn file_should_map_to_leef_attributes_with_its_operation() {
+        let last_operation = LastOperation::default();
+        last_operation.remember(&write_operation());
+        let shadow = write_file("/etc/shadow");
+
+        let leef = format_file(
+            EventFormat::Leef,
+            &shadow,
+            last_operation.of(&shadow).as_ref(),
+        );
+
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            leef,
+            format!("LEEF:1.0|linux-fs-audit|linux-fs-audit|{}|WRITE|cat=file\tdevTime=Oct 29 2023 10:49:23.120 UTC\tserial=571\tusrName=root\tgroupName=root\texecutable=/usr/bin/vi\tcommand=vi\tsyscall=unknown\taction=WRITE\toutcome=success\tfilePath=/etc/shadow\tfileName=shadow", version)
+        );
+    }
+
+    #[test]
+    fn file_should_map_to_elastic_common_schema() {
+        let last_operation = LastOperation::default();
+        last_operation.remember(&write_operation());
+        let shadow = write_file("/etc/shadow");
+
+        let ecs = format_file(
+            EventFormat::Ecs,
+            &shadow,
+            last_operation.of(&shadow).as_ref(),
+        );
+
+        let mut expected = json!({
+            "@timestamp": "2023-10-29T10:49:23.120Z",
+            "ecs": { "version": "8.11.0" },
+            "event": {
+                "kind": "event",
+                "category": ["file"],
+                "type": ["change"],
+                "dataset": "linux-fs-audit.file",
+                "action": "write",
+                "outcome": "success",
+                "sequence": 571
+            },
+            "user": { "name": "root", "group": { "name": "root" } },
+            "process": { "name": "vi", "executable": "/usr/bin/vi", "command_line": "vi" },
+            "auditd": { "data": { "syscall": "unknown" } },
+            "file": { "path": "/etc/shadow", "directory": "/etc", "name": "shadow" }
+        });
+        if !hostname().is_empty() {
+            expected["host"] = json!({ "name": hostname(), "hostname": hostname() });
+        }
+        assert_eq!(serde_json::from_str::<Value>(&ecs).unwrap(), expected);
+    }
+
+    #[test]
+    fn values_should_be_escaped_for_each_format() {
+        let odd = read_file("/tmp/a=b|c\\d\te\nf");
+
+        let cef = format_file(EventFormat::Cef, &odd, None);
+        let leef = format_file(EventFormat::Leef, &odd, None);
+
+        assert!(cef.contains("|FILE|FILE file|3|"));
+        assert!(cef.contains(" filePath=/tmp/a\\=b|c\\\\d\te\\nf "));
+        assert!(leef.contains("\tfilePath=/tmp/a=b|c\\\\d\\te\\nf\t"));
+        assert_eq!(leef.lines().count(), 1);
+    }
+}
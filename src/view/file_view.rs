@@ -0,0 +1,402 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::{FileFsync, FileSettings};
+use crate::view::event_format::{format_file, format_operation, to_json, JsonLine};
+use crate::view::{FileView, View, ViewError};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::Colorize;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, SignalKind};
+
+const ROTATED_SUFFIX_FORMAT: &str = "%Y%m%d-%H%M%S";
+const COMPRESSED_EXTENSION: &str = ".gz";
+
+/// The file currently written to, together with what is needed to decide when to sync or rotate it.
+pub(super) struct RotatingFile {
+    settings: FileSettings,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+    last_sync: Instant,
+    unsynced: bool,
+    compressing: Option<JoinHandle<()>>,
+}
+
+impl FileView {
+    pub(crate) fn new(settings: &FileSettings) -> Self {
+        let file = RotatingFile::open(settings.clone()).unwrap_or_else(|e| {
+            panic!(
+                "Fatal: could not open {}, check if its directory exists: {}",
+                settings.path, e
+            )
+        });
+        let reopen = Arc::new(AtomicBool::new(false));
+        Self::reopen_on_hangup(reopen.clone());
+        return Self {
+            file: Arc::new(Mutex::new(file)),
+            reopen,
+            event_format: settings.event_format,
+            last_operation: Default::default(),
+        };
+    }
+
+    /// Lets an external logrotate move the file away and send SIGHUP, after which the next line
+    /// goes to a new file at the configured path.
+    fn reopen_on_hangup(reopen: Arc<AtomicBool>) {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                log::warn!("{} {}", "Cannot listen for SIGHUP:".yellow(), e);
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                log::info!("Reopening the {} view file on SIGHUP", "File".yellow());
+                reopen.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+
+    async fn append(&self, line: String) -> Result<(), ViewError> {
+        let mut bytes = line.into_bytes();
+        bytes.push(b'\n');
+        let file = self.file.clone();
+        let reopen = self.reopen.swap(false, Ordering::Relaxed);
+        return tokio::task::spawn_blocking(move || {
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if reopen {
+                file.reopen()
+                    .map_err(|e| ViewError::from_io("Reopening file", e))?;
+            }
+            return file
+                .append(&bytes, Utc::now())
+                .map_err(|e| ViewError::from_io("Appending to file", e));
+        })
+        .await
+        .map_err(|e| ViewError::permanent("Appending to file").caused_by(e))?;
+    }
+}
+
+#[async_trait]
+impl View for FileView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        self.last_operation.remember(&operation);
+        let line = match self.event_format {
+            Some(format) => format_operation(format, &operation),
+            None => to_json(JsonLine::Operation(&operation))?,
+        };
+        return self.append(line).await;
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        let line = match self.event_format {
+            Some(format) => format_file(format, &files, self.last_operation.of(&files).as_ref()),
+            None => to_json(JsonLine::File(&files))?,
+        };
+        return self.append(line).await;
+    }
+
+    /// Syncs the file and waits for the last rotated file to be compressed.
+    async fn flush(&self) -> Result<(), ViewError> {
+        let file = self.file.clone();
+        return tokio::task::spawn_blocking(move || {
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            file.finish_compressing();
+            return file
+                .sync()
+                .map_err(|e| ViewError::from_io("Syncing file", e));
+        })
+        .await
+        .map_err(|e| ViewError::permanent("Syncing file").caused_by(e))?;
+    }
+}
+
+impl RotatingFile {
+    fn open(settings: FileSettings) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&settings.path)?;
+        let metadata = file.metadata()?;
+        let opened_on = match metadata.len() {
+            0 => Utc::now().date_naive(),
+            _ => DateTime::<Utc>::from(metadata.modified()?).date_naive(),
+        };
+        return Ok(Self {
+            settings,
+            file,
+            size: metadata.len(),
+            opened_on,
+            last_sync: Instant::now(),
+            unsynced: false,
+            compressing: None,
+        });
+    }
+
+    /// Rotates first when the line would not fit or a new day began, then syncs as configured.
+    fn append(&mut self, line: &[u8], now: DateTime<Utc>) -> io::Result<()> {
+        let too_large = self
+            .settings
+            .max_size_bytes
+            .is_some_and(|max_size_bytes| self.size + line.len() as u64 > max_size_bytes);
+        let new_day = self.settings.rotate_daily && now.date_naive() != self.opened_on;
+        if self.size > 0 && (too_large || new_day) {
+            self.rotate(now)?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        self.unsynced = true;
+        let interval = Duration::from_millis(self.settings.fsync_interval_ms);
+        return match self.settings.fsync {
+            FileFsync::Always => self.sync(),
+            FileFsync::Interval if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        };
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced {
+            self.file.sync_data()?;
+            self.unsynced = false;
+        }
+        self.last_sync = Instant::now();
+        return Ok(());
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.sync()?;
+        let compressing = self.compressing.take();
+        *self = Self::open(self.settings.clone())?;
+        self.compressing = compressing;
+        return Ok(());
+    }
+
+    /// Moves the file aside under the time of rotation and continues in a new one. Compressing it
+    /// and removing the oldest rotated files happens in the background, one rotation at a time.
+    fn rotate(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        self.sync()?;
+        let rotated = rotated_path(&self.settings.path, now);
+        std::fs::rename(&self.settings.path, &rotated)?;
+        self.reopen()?;
+        self.finish_compressing();
+        let settings = self.settings.clone();
+        self.compressing = Some(thread::spawn(move || {
+            if settings.compress {
+                if let Err(e) = compress(&rotated) {
+                    log::error!("{} {}", "Error: compressing rotated file failed:".red(), e);
+                }
+            }
+            if let Err(e) = remove_oldest(&settings) {
+                log::error!("{} {}", "Error: removing rotated files failed:".red(), e);
+            }
+        }));
+        return Ok(());
+    }
+
+    fn finish_compressing(&mut self) {
+        if let Some(compressing) = self.compressing.take() {
+            let _ = compressing.join();
+        }
+    }
+}
+
+/// `<path>.<time of rotation>`, with a counter appended if the file rotated more than once a second.
+fn rotated_path(path: &str, now: DateTime<Utc>) -> PathBuf {
+    let rotated = format!("{}.{}", path, now.format(ROTATED_SUFFIX_FORMAT));
+    let mut candidate = rotated.clone();
+    let mut counter = 1;
+    while Path::new(&candidate).exists()
+        || Path::new(&format!("{}{}", candidate, COMPRESSED_EXTENSION)).exists()
+    {
+        candidate = format!("{}-{}", rotated, counter);
+        counter += 1;
+    }
+    return PathBuf::from(candidate);
+}
+
+fn compress(rotated: &Path) -> io::Result<()> {
+    let mut compressed_path = rotated.as_os_str().to_owned();
+    compressed_path.push(COMPRESSED_EXTENSION);
+    let mut encoder = GzEncoder::new(File::create(&compressed_path)?, Compression::default());
+    io::copy(&mut File::open(rotated)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    return std::fs::remove_file(rotated);
+}
+
+/// Keeps the newest `keep` rotated files, which sort by the time of rotation in their names.
+fn remove_oldest(settings: &FileSettings) -> io::Result<()> {
+    let path = Path::new(&settings.path);
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let mut rotated = BTreeSet::new();
+    for entry in std::fs::read_dir(directory)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let is_rotated = name
+            .strip_prefix(&prefix)
+            .is_some_and(|suffix| suffix.starts_with(|x: char| x.is_ascii_digit()));
+        if is_rotated {
+            rotated.insert(name.trim_end_matches(COMPRESSED_EXTENSION).to_string());
+        }
+    }
+    let excess = rotated.len().saturating_sub(settings.keep);
+    for name in rotated.iter().take(excess) {
+        for name in [name.clone(), format!("{}{}", name, COMPRESSED_EXTENSION)] {
+            match std::fs::remove_file(directory.join(name)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{EventFormat, FileSettings};
+    use crate::test_fixtures::{read_file, read_operation};
+    use crate::view::file_view::RotatingFile;
+    use crate::view::{FileView, View};
+    use chrono::{DateTime, Duration, Utc};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use std::sync::atomic::Ordering;
+    use tempfile::tempdir;
+
+    fn names_in(directory: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        return names;
+    }
+
+    #[tokio::test]
+    async fn every_event_should_be_one_json_line_tagged_with_its_kind() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("events.jsonl");
+        let file_view = FileView::new(&FileSettings::new(path.to_str().unwrap()));
+
+        file_view.update(read_operation()).await.unwrap();
+        file_view.report(read_file("/etc/shadow")).await.unwrap();
+        file_view.flush().await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "operation");
+        assert_eq!(lines[0]["executable"], "/usr/bin/ls");
+        assert_eq!(lines[1]["event"], "file");
+        assert_eq!(lines[1]["name"], "/etc/shadow");
+    }
+
+    #[tokio::test]
+    async fn event_format_should_replace_json_lines() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("events.cef");
+        let mut settings = FileSettings::new(path.to_str().unwrap());
+        settings.event_format = Some(EventFormat::Cef);
+        let file_view = FileView::new(&settings);
+
+        file_view.update(read_operation()).await.unwrap();
+        file_view.report(read_file("/etc/shadow")).await.unwrap();
+        file_view.flush().await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|x| x.starts_with("CEF:0|linux-fs-audit|")));
+        assert!(lines[1].contains("|READ|READ file|3|"));
+        assert!(lines[1].contains(" suser=maciek "));
+    }
+
+    #[tokio::test]
+    async fn file_over_max_size_should_be_rotated_compressed_and_oldest_removed() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("events.jsonl");
+        let mut settings = FileSettings::new(path.to_str().unwrap());
+        settings.max_size_bytes = Some(250);
+        settings.keep = 2;
+        let file_view = FileView::new(&settings);
+
+        for _ in 0..10 {
+            file_view.report(read_file("/etc/shadow")).await.unwrap();
+        }
+        file_view.flush().await.unwrap();
+
+        let names = names_in(directory.path());
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], "events.jsonl");
+        assert!(names[1..].iter().all(|x| x.ends_with(".gz")));
+        let mut decompressed = String::new();
+        GzDecoder::new(std::fs::File::open(directory.path().join(&names[2])).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed.lines().count(), 2);
+        assert!(std::fs::metadata(&path).unwrap().len() <= 250);
+    }
+
+    #[test]
+    fn file_should_be_rotated_on_first_write_of_new_day() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("events.jsonl");
+        let mut settings = FileSettings::new(path.to_str().unwrap());
+        settings.rotate_daily = true;
+        settings.compress = false;
+        let today: DateTime<Utc> = Utc::now();
+        let mut rotating = RotatingFile::open(settings).unwrap();
+
+        rotating.append(b"{}\n", today).unwrap();
+        rotating.append(b"{}\n", today).unwrap();
+        rotating.append(b"{}\n", today + Duration::days(1)).unwrap();
+        rotating.finish_compressing();
+
+        let names = names_in(directory.path());
+        assert_eq!(names.len(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}\n");
+        let rotated = directory.path().join(&names[1]);
+        assert_eq!(std::fs::read_to_string(rotated).unwrap(), "{}\n{}\n");
+    }
+
+    #[tokio::test]
+    async fn file_moved_away_should_be_recreated_after_hangup() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("events.jsonl");
+        let moved = directory.path().join("events.jsonl.1");
+        let file_view = FileView::new(&FileSettings::new(path.to_str().unwrap()));
+
+        file_view.report(read_file("/etc/shadow")).await.unwrap();
+        std::fs::rename(&path, &moved).unwrap();
+        file_view.report(read_file("/etc/passwd")).await.unwrap();
+        file_view.reopen.store(true, Ordering::Relaxed);
+        file_view.report(read_file("/etc/hosts")).await.unwrap();
+        file_view.flush().await.unwrap();
+
+        let moved = std::fs::read_to_string(moved).unwrap();
+        let recreated = std::fs::read_to_string(path).unwrap();
+        assert_eq!(moved.lines().count(), 2);
+        assert_eq!(recreated.lines().count(), 1);
+        assert!(recreated.contains("/etc/hosts"));
+    }
+}
@@ -0,0 +1,513 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::FluentSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{hostname, JsonLine, LastOperation};
+use crate::view::socket::connect_tcp;
+use crate::view::{FluentView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::Utc;
+use rmpv::Value;
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const KEY_PLACEHOLDER: &str = "{key}";
+/// Stands in for the key of a file reported without its operation.
+const UNKNOWN: &str = "unknown";
+/// Extension type of the forward protocol for a time with nanoseconds.
+const EVENT_TIME_TYPE: i8 = 0;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// Forwards batches of events, one message per tag, connecting on first use and again after a failure.
+pub(super) struct FluentWriter {
+    address: String,
+    tag: String,
+    require_ack: bool,
+    ack_timeout: Duration,
+    shared_key: Option<String>,
+    self_hostname: String,
+    connection: Option<Connection>,
+    last_operation: LastOperation,
+}
+
+/// A connection to a forward input, keeping what was read past the last message.
+pub(super) struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl FluentView {
+    pub(crate) fn new(settings: &FluentSettings, context: &ViewContext) -> Self {
+        let batcher = Batcher::spawn(
+            context,
+            FluentWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for FluentView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for FluentWriter {
+    /// A failed message leaves the connection behind, the tags forwarded before it may be sent again.
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut messages: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for event in events {
+            let (tag, entry) = self.entry(event)?;
+            messages.entry(tag).or_default().push(entry);
+        }
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect().await?,
+        };
+        for (tag, entries) in messages {
+            self.forward(&mut connection, tag, entries).await?;
+        }
+        self.connection = Some(connection);
+        return Ok(());
+    }
+}
+
+impl FluentWriter {
+    pub(super) fn new(settings: &FluentSettings) -> Self {
+        return Self {
+            address: settings.address.clone(),
+            tag: settings.tag.clone(),
+            require_ack: settings.require_ack,
+            ack_timeout: Duration::from_millis(settings.ack_timeout_ms),
+            shared_key: settings.shared_key.clone(),
+            self_hostname: settings
+                .self_hostname
+                .clone()
+                .unwrap_or_else(|| hostname().to_string()),
+            connection: None,
+            last_operation: LastOperation::default(),
+        };
+    }
+
+    /// The tag and the `[time, record]` entry of the event, files taking the key of the operation that touched them.
+    fn entry(&self, event: &ViewEvent) -> Result<(String, Value), ViewError> {
+        let (key, audit_time, line) = match event {
+            ViewEvent::Operation(operation) => {
+                self.last_operation.remember(operation);
+                let key = operation.key.to_string().to_lowercase();
+                (key, operation.audit_time, JsonLine::Operation(operation))
+            }
+            ViewEvent::File(file) => {
+                let key = self
+                    .last_operation
+                    .of(file)
+                    .map_or(UNKNOWN.to_string(), |x| x.key.to_string().to_lowercase());
+                (key, file.audit_time, JsonLine::File(file))
+            }
+        };
+        let record = serde_json::to_value(&line)
+            .map_err(|e| ViewError::permanent("Serializing event").caused_by(e))?;
+        let entry = Value::Array(vec![event_time(audit_time), msgpack(&record)]);
+        return Ok((self.tag.replace(KEY_PLACEHOLDER, &key), entry));
+    }
+
+    async fn connect(&self) -> Result<Connection, ViewError> {
+        let context = format!("Connecting to {}", self.address);
+        let stream = connect_tcp(&self.address)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        let mut connection = Connection {
+            stream,
+            buffer: Vec::new(),
+        };
+        if let Some(shared_key) = &self.shared_key {
+            self.handshake(&mut connection, shared_key).await?;
+        }
+        return Ok(connection);
+    }
+
+    /// Answers the HELO of the server with a PING proving this side knows the shared key, and checks that the
+    /// PONG proves the same of the server.
+    async fn handshake(
+        &self,
+        connection: &mut Connection,
+        shared_key: &str,
+    ) -> Result<(), ViewError> {
+        let context = format!("Handshake with {}", self.address);
+        let helo = connection
+            .receive(CONNECT_TIMEOUT)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        if helo[0].as_str() != Some("HELO") {
+            return Err(ViewError::permanent(format!(
+                "{} did not start with HELO, check if the input has security enabled",
+                context
+            )));
+        }
+        let nonce = bytes(&helo[1]["nonce"]);
+        let salt: [u8; 16] = rand::random();
+        let ping = Value::Array(vec![
+            "PING".into(),
+            self.self_hostname.as_str().into(),
+            Value::Binary(salt.to_vec()),
+            digest(&[
+                &salt,
+                self.self_hostname.as_bytes(),
+                &nonce,
+                shared_key.as_bytes(),
+            ])
+            .into(),
+            "".into(),
+            "".into(),
+        ]);
+        connection
+            .send(&ping)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        let pong = connection
+            .receive(CONNECT_TIMEOUT)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        if pong[1].as_bool() != Some(true) {
+            return Err(ViewError::permanent(format!(
+                "{} failed: {}",
+                context,
+                pong[2].as_str().unwrap_or_default()
+            )));
+        }
+        let server_hostname = pong[3].as_str().unwrap_or_default();
+        let expected = digest(&[
+            &salt,
+            server_hostname.as_bytes(),
+            &nonce,
+            shared_key.as_bytes(),
+        ]);
+        if pong[4].as_str() != Some(expected.as_str()) {
+            return Err(ViewError::permanent(format!(
+                "{} failed: {} does not know the shared key",
+                context, server_hostname
+            )));
+        }
+        return Ok(());
+    }
+
+    /// Sends the entries of one tag in forward mode, waiting for the server to acknowledge them if required.
+    async fn forward(
+        &self,
+        connection: &mut Connection,
+        tag: String,
+        entries: Vec<Value>,
+    ) -> Result<(), ViewError> {
+        let context = format!("Forwarding to {}", self.address);
+        let mut option = vec![("size".into(), entries.len().into())];
+        let chunk = match self.require_ack {
+            true => {
+                let id: [u8; 16] = rand::random();
+                Some(base64::engine::general_purpose::STANDARD.encode(id))
+            }
+            false => None,
+        };
+        if let Some(chunk) = &chunk {
+            option.push(("chunk".into(), chunk.as_str().into()));
+        }
+        let message = Value::Array(vec![tag.into(), Value::Array(entries), Value::Map(option)]);
+        connection
+            .send(&message)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        let Some(chunk) = chunk else {
+            return Ok(());
+        };
+        let response = connection
+            .receive(self.ack_timeout)
+            .await
+            .map_err(|e| ViewError::from_io(&context, e))?;
+        return match response["ack"].as_str() {
+            Some(ack) if ack == chunk => Ok(()),
+            _ => Err(ViewError::retryable(format!(
+                "{} was answered with {} instead of the ack of chunk {}",
+                context, response, chunk
+            ))),
+        };
+    }
+}
+
+impl Connection {
+    pub(super) async fn send(&mut self, message: &Value) -> io::Result<()> {
+        let mut encoded = Vec::new();
+        rmpv::encode::write_value(&mut encoded, message)?;
+        return self.stream.write_all(&encoded).await;
+    }
+
+    /// Reads until a whole message arrived, for at most `timeout`.
+    pub(super) async fn receive(&mut self, timeout: Duration) -> io::Result<Value> {
+        return tokio::time::timeout(timeout, self.read_message())
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e))?;
+    }
+
+    async fn read_message(&mut self) -> io::Result<Value> {
+        loop {
+            let mut cursor = io::Cursor::new(self.buffer.as_slice());
+            match rmpv::decode::read_value(&mut cursor) {
+                Ok(value) => {
+                    let consumed = cursor.position() as usize;
+                    self.buffer.drain(..consumed);
+                    return Ok(value);
+                }
+                Err(
+                    rmpv::decode::Error::InvalidMarkerRead(e)
+                    | rmpv::decode::Error::InvalidDataRead(e),
+                ) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+            let mut read = [0u8; READ_BUFFER_SIZE];
+            let length = self.stream.read(&mut read).await?;
+            if length == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionAborted,
+                    "Connection closed by the server",
+                ));
+            }
+            self.buffer.extend_from_slice(&read[..length]);
+        }
+    }
+}
+
+/// The audit time, or now when it is not known, as seconds and nanoseconds.
+fn event_time(audit_time: u64) -> Value {
+    let millis = match audit_time {
+        0 => Utc::now().timestamp_millis() as u64,
+        _ => audit_time,
+    };
+    let seconds = (millis / 1000) as u32;
+    let nanos = (millis % 1000 * 1_000_000) as u32;
+    return Value::Ext(
+        EVENT_TIME_TYPE,
+        [seconds.to_be_bytes(), nanos.to_be_bytes()].concat(),
+    );
+}
+
+fn msgpack(value: &serde_json::Value) -> Value {
+    return match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(value) => Value::Boolean(*value),
+        serde_json::Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(value), _) => value.into(),
+            (None, Some(value)) => value.into(),
+            (None, None) => number.as_f64().unwrap_or_default().into(),
+        },
+        serde_json::Value::String(value) => value.as_str().into(),
+        serde_json::Value::Array(values) => Value::Array(values.iter().map(msgpack).collect()),
+        serde_json::Value::Object(fields) => Value::Map(
+            fields
+                .iter()
+                .map(|(key, value)| (key.as_str().into(), msgpack(value)))
+                .collect(),
+        ),
+    };
+}
+
+fn bytes(value: &Value) -> Vec<u8> {
+    return match value {
+        Value::Binary(bytes) => bytes.clone(),
+        Value::String(text) => text.as_bytes().to_vec(),
+        _ => Vec::new(),
+    };
+}
+
+/// Hex encoded SHA-512 of the parts one after another, as the handshake uses it.
+fn digest(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    return format!("{:x}", hasher.finalize());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::FluentSettings;
+    use crate::test_fixtures::{file, write_events, write_file, write_operation};
+    use crate::view::batching::BatchWriter;
+    use crate::view::fluent_view::{bytes, digest, Connection, FluentWriter};
+    use crate::view::{FluentView, View, ViewContext};
+    use rmpv::Value;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// A forward input accepting one connection, which keeps every message it receives.
+    struct FakeForwardServer {
+        address: String,
+        messages: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl FakeForwardServer {
+        async fn start(shared_key: Option<&'static str>, acknowledge: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let messages = Arc::new(Mutex::new(Vec::new()));
+            let received = messages.clone();
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut connection = Connection {
+                    stream,
+                    buffer: Vec::new(),
+                };
+                if let Some(shared_key) = shared_key {
+                    if !Self::handshake(&mut connection, shared_key).await {
+                        return;
+                    }
+                }
+                while let Ok(message) = connection.receive(WAIT).await {
+                    let chunk = message[2]["chunk"].clone();
+                    received.lock().unwrap().push(message);
+                    if acknowledge && !chunk.is_nil() {
+                        let ack = Value::Map(vec![("ack".into(), chunk)]);
+                        connection.send(&ack).await.unwrap();
+                    }
+                }
+            });
+            return Self { address, messages };
+        }
+
+        async fn handshake(connection: &mut Connection, shared_key: &str) -> bool {
+            let nonce = b"0123456789abcdef".to_vec();
+            let helo = Value::Array(vec![
+                "HELO".into(),
+                Value::Map(vec![
+                    ("nonce".into(), Value::Binary(nonce.clone())),
+                    ("auth".into(), Value::Binary(Vec::new())),
+                    ("keepalive".into(), true.into()),
+                ]),
+            ]);
+            connection.send(&helo).await.unwrap();
+            let ping = connection.receive(WAIT).await.unwrap();
+            let salt = bytes(&ping[2]);
+            let client = ping[1].as_str().unwrap().as_bytes().to_vec();
+            let expected = digest(&[&salt, &client, &nonce, shared_key.as_bytes()]);
+            let pong = match ping[3].as_str() == Some(expected.as_str()) {
+                true => Value::Array(vec![
+                    "PONG".into(),
+                    true.into(),
+                    "".into(),
+                    "aggregator".into(),
+                    digest(&[&salt, b"aggregator", &nonce, shared_key.as_bytes()]).into(),
+                ]),
+                false => Value::Array(vec![
+                    "PONG".into(),
+                    false.into(),
+                    "shared_key mismatch".into(),
+                    "".into(),
+                    "".into(),
+                ]),
+            };
+            connection.send(&pong).await.unwrap();
+            return pong[1].as_bool() == Some(true);
+        }
+
+        async fn wait_for(&self, count: usize) -> Vec<Value> {
+            let deadline = tokio::time::Instant::now() + WAIT;
+            while self.messages.lock().unwrap().len() < count {
+                assert!(tokio::time::Instant::now() < deadline);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            return self.messages.lock().unwrap().clone();
+        }
+    }
+
+    #[tokio::test]
+    async fn events_should_be_forwarded_in_one_message_per_tag() {
+        let server = FakeForwardServer::start(None, false).await;
+        let fluent_view = FluentView::new(
+            &FluentSettings::new(&server.address),
+            &ViewContext::default(),
+        );
+
+        fluent_view.update(write_operation()).await.unwrap();
+        fluent_view.report(write_file("/etc/shadow")).await.unwrap();
+        fluent_view
+            .report(file("/tmp/orphan", 999, 1698576563999))
+            .await
+            .unwrap();
+        fluent_view.flush().await.unwrap();
+
+        let messages = server.wait_for(2).await;
+        assert_eq!(messages[0][0].as_str(), Some("linux_fs_audit.unknown"));
+        assert_eq!(messages[1][0].as_str(), Some("linux_fs_audit.write"));
+        let entries = messages[1][1].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(messages[1][2]["size"].as_u64(), Some(2));
+        let seconds = 1698576563u32.to_be_bytes();
+        let nanos = 120000000u32.to_be_bytes();
+        assert_eq!(entries[0][0], Value::Ext(0, [seconds, nanos].concat()));
+        assert_eq!(entries[0][1]["event"].as_str(), Some("operation"));
+        assert_eq!(entries[0][1]["executable"].as_str(), Some("/usr/bin/vi"));
+        assert_eq!(entries[1][1]["name"].as_str(), Some("/etc/shadow"));
+        assert_eq!(entries[1][1]["serial"].as_u64(), Some(571));
+    }
+
+    #[tokio::test]
+    async fn acknowledged_batch_should_be_written_after_handshake() {
+        let server = FakeForwardServer::start(Some("secret"), true).await;
+        let mut settings = FluentSettings::new(&server.address);
+        settings.require_ack = true;
+        settings.shared_key = Some("secret".to_string());
+        let mut writer = FluentWriter::new(&settings);
+
+        writer.write(&write_events()).await.unwrap();
+
+        let messages = server.wait_for(1).await;
+        assert!(messages[0][2]["chunk"].is_str());
+    }
+
+    #[tokio::test]
+    async fn batch_not_acknowledged_in_time_should_be_retryable() {
+        let server = FakeForwardServer::start(None, false).await;
+        let mut settings = FluentSettings::new(&server.address);
+        settings.require_ack = true;
+        settings.ack_timeout_ms = 50;
+        let mut writer = FluentWriter::new(&settings);
+
+        let error = writer.write(&write_events()).await.unwrap_err();
+
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn wrong_shared_key_should_be_permanent() {
+        let server = FakeForwardServer::start(Some("secret"), true).await;
+        let mut settings = FluentSettings::new(&server.address);
+        settings.shared_key = Some("guess".to_string());
+        let mut writer = FluentWriter::new(&settings);
+
+        let error = writer.write(&write_events()).await.unwrap_err();
+
+        assert!(!error.is_retryable());
+        assert!(error.to_string().contains("shared_key mismatch"));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::serializer::{FileOperatedOn, Operation, OperationKey};
+use crate::settings::{GelfCompression, GelfSettings, GelfTransport};
+use crate::view::event_format::hostname;
+use crate::view::socket::{connect_tcp, connect_udp};
+use crate::view::{GelfView, View, ViewError};
+use async_trait::async_trait;
+use chrono::Utc;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::io;
+use std::io::Write;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+
+const GELF_VERSION: &str = "1.1";
+/// Stands in for the host when its name could not be read, GELF requires one.
+const UNKNOWN_HOST: &str = "unknown";
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+/// Magic bytes, message id, sequence number and sequence count.
+const CHUNK_HEADER_SIZE: usize = 12;
+/// Graylog drops messages split into more chunks than this.
+const MAX_CHUNKS: usize = 128;
+const MILLIS_PER_SECOND: f64 = 1000.0;
+
+/// The socket of the configured transport, connected on first use and again after it failed.
+pub(super) struct Connection {
+    transport: GelfTransport,
+    address: String,
+    compression: GelfCompression,
+    max_chunk_size: usize,
+    socket: Option<Socket>,
+    next_message_id: u64,
+}
+
+enum Socket {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl GelfView {
+    pub(crate) fn new(settings: &GelfSettings) -> Self {
+        if settings.transport == GelfTransport::Udp && settings.max_chunk_size <= CHUNK_HEADER_SIZE
+        {
+            panic!(
+                "Fatal: max_chunk_size of {} leaves no room for the {} bytes of a chunk header",
+                settings.max_chunk_size, CHUNK_HEADER_SIZE
+            );
+        }
+        return Self {
+            settings: settings.clone(),
+            connection: tokio::sync::Mutex::new(Connection {
+                transport: settings.transport,
+                address: settings.address.clone(),
+                compression: settings.compression,
+                max_chunk_size: settings.max_chunk_size,
+                socket: None,
+                next_message_id: Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64,
+            }),
+            client: Client::new(),
+            last_operation: Default::default(),
+        };
+    }
+
+    fn level_of(&self, key: &OperationKey) -> u8 {
+        return match key {
+            OperationKey::READ => self.settings.read_level as u8,
+            OperationKey::WRITE => self.settings.write_level as u8,
+        };
+    }
+
+    async fn send(&self, message: Value) -> Result<(), ViewError> {
+        let payload = message.to_string();
+        if self.settings.transport != GelfTransport::Http {
+            return self.connection.lock().await.send(payload.as_bytes()).await;
+        }
+        self.client
+            .post(&self.settings.address)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                ViewError::from_http(format!("Posting to {}", self.settings.address), e)
+            })?;
+        return Ok(());
+    }
+}
+
+#[async_trait]
+impl View for GelfView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        self.last_operation.remember(&operation);
+        let level = self.level_of(&operation.key);
+        let short_message = format!(
+            "{} {} {}",
+            operation.user, operation.key, operation.executable
+        );
+        let mut message = gelf_message(short_message, level, operation.audit_time);
+        message["_event"] = json!("operation");
+        add_operation_fields(&mut message, &operation);
+        return self.send(message).await;
+    }
+
+    /// Files are sent with the level and the fields of the operation that touched them.
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        let operation = self.last_operation.of(&files);
+        let (level, short_message) = match &operation {
+            Some(operation) => (
+                self.level_of(&operation.key),
+                format!("{} {}", operation.key, files.name),
+            ),
+            None => (self.settings.read_level as u8, files.name.clone()),
+        };
+        let mut message = gelf_message(short_message, level, files.audit_time);
+        message["_event"] = json!("file");
+        if let Some(operation) = &operation {
+            add_operation_fields(&mut message, operation);
+        }
+        message["_path"] = json!(files.name);
+        message["_serial"] = json!(files.serial);
+        return self.send(message).await;
+    }
+}
+
+impl Connection {
+    async fn send(&mut self, payload: &[u8]) -> Result<(), ViewError> {
+        let context = format!("Sending to {}", self.address);
+        let datagrams = match self.transport {
+            GelfTransport::Udp => {
+                let compressed = compress(self.compression, payload)
+                    .map_err(|e| ViewError::from_io("Compressing GELF message", e))?;
+                self.next_message_id = self.next_message_id.wrapping_add(1);
+                chunks(&compressed, self.next_message_id, self.max_chunk_size)?
+            }
+            _ => vec![[payload, b"\0"].concat()],
+        };
+        if self.socket.is_none() {
+            self.socket = Some(
+                self.connect()
+                    .await
+                    .map_err(|e| ViewError::from_io(&context, e))?,
+            );
+        }
+        let mut result = Ok(());
+        for datagram in &datagrams {
+            result = match self.socket.as_mut() {
+                Some(Socket::Udp(socket)) => socket.send(datagram).await.map(|_| ()),
+                Some(Socket::Tcp(stream)) => stream.write_all(datagram).await,
+                None => Ok(()),
+            };
+            if result.is_err() {
+                self.socket = None;
+                break;
+            }
+        }
+        return result.map_err(|e| ViewError::from_io(context, e));
+    }
+
+    async fn connect(&self) -> io::Result<Socket> {
+        return match self.transport {
+            GelfTransport::Tcp => Ok(Socket::Tcp(connect_tcp(&self.address).await?)),
+            _ => Ok(Socket::Udp(connect_udp(&self.address).await?)),
+        };
+    }
+}
+
+/// The fields GELF requires, with the audit time as `timestamp` in seconds when it is known.
+fn gelf_message(short_message: String, level: u8, audit_time: u64) -> Value {
+    let host = match hostname() {
+        "" => UNKNOWN_HOST,
+        host => host,
+    };
+    let mut message = json!({
+        "version": GELF_VERSION,
+        "host": host,
+        "short_message": short_message,
+        "level": level,
+    });
+    if audit_time > 0 {
+        message["timestamp"] = json!(audit_time as f64 / MILLIS_PER_SECOND);
+    }
+    return message;
+}
+
+/// Additional fields may only hold strings and numbers, so `success` is sent as text.
+fn add_operation_fields(message: &mut Value, operation: &Operation) {
+    message["_user"] = json!(operation.user);
+    message["_group"] = json!(operation.group);
+    message["_executable"] = json!(operation.executable);
+    message["_command"] = json!(operation.command);
+    message["_syscall"] = json!(operation.syscall);
+    message["_key"] = json!(operation.key.to_string());
+    message["_success"] = json!(operation.success.to_string());
+    message["_serial"] = json!(operation.serial);
+    if let Some(pid) = operation.pid {
+        message["_pid"] = json!(pid);
+    }
+}
+
+fn compress(compression: GelfCompression, payload: &[u8]) -> io::Result<Vec<u8>> {
+    return match compression {
+        GelfCompression::None => Ok(payload.to_vec()),
+        GelfCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload)?;
+            encoder.finish()
+        }
+        GelfCompression::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload)?;
+            encoder.finish()
+        }
+    };
+}
+
+/// The payload as one datagram when it fits, otherwise as chunks sharing `message_id`.
+fn chunks(
+    payload: &[u8],
+    message_id: u64,
+    max_chunk_size: usize,
+) -> Result<Vec<Vec<u8>>, ViewError> {
+    if payload.len() <= max_chunk_size {
+        return Ok(vec![payload.to_vec()]);
+    }
+    let parts: Vec<&[u8]> = payload.chunks(max_chunk_size - CHUNK_HEADER_SIZE).collect();
+    if parts.len() > MAX_CHUNKS {
+        return Err(ViewError::permanent(format!(
+            "GELF message of {} bytes would take {} chunks, at most {} are allowed",
+            payload.len(),
+            parts.len(),
+            MAX_CHUNKS
+        )));
+    }
+    let count = parts.len() as u8;
+    return Ok(parts
+        .into_iter()
+        .enumerate()
+        .map(|(sequence, part)| {
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_SIZE + part.len());
+            chunk.extend_from_slice(&CHUNK_MAGIC);
+            chunk.extend_from_slice(&message_id.to_be_bytes());
+            chunk.push(sequence as u8);
+            chunk.push(count);
+            chunk.extend_from_slice(part);
+            chunk
+        })
+        .collect());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{GelfCompression, GelfSettings, GelfTransport};
+    use crate::test_fixtures::{read_file, write_file, write_operation};
+    use crate::view::gelf_view::chunks;
+    use crate::view::{GelfView, View};
+    use flate2::read::GzDecoder;
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+    use std::io::Read;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, UdpSocket};
+
+    async fn send_both(gelf_view: &GelfView, name: &str) {
+        gelf_view.update(write_operation()).await.unwrap();
+        gelf_view.report(write_file(name)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn udp_messages_should_be_compressed_with_additional_fields() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let gelf_view = GelfView::new(&GelfSettings::new(GelfTransport::Udp, &address));
+
+        send_both(&gelf_view, "/etc/shadow").await;
+
+        let mut buffer = [0u8; 2048];
+        let mut messages = Vec::new();
+        for _ in 0..2 {
+            let length = collector.recv(&mut buffer).await.unwrap();
+            let mut message = String::new();
+            GzDecoder::new(&buffer[..length])
+                .read_to_string(&mut message)
+                .unwrap();
+            messages.push(serde_json::from_str::<Value>(&message).unwrap());
+        }
+        assert_eq!(messages[0]["version"], "1.1");
+        assert_eq!(messages[0]["short_message"], "root WRITE /usr/bin/vi");
+        assert_eq!(messages[0]["timestamp"], json!(1698576563.120));
+        // notice
+        assert_eq!(messages[0]["level"], 5);
+        assert_eq!(messages[0]["_pid"], 20681);
+        assert_eq!(messages[0]["_success"], "true");
+        assert_eq!(messages[1]["short_message"], "WRITE /etc/shadow");
+        assert_eq!(messages[1]["_event"], "file");
+        assert_eq!(messages[1]["_path"], "/etc/shadow");
+        assert_eq!(messages[1]["_user"], "root");
+    }
+
+    #[tokio::test]
+    async fn large_udp_messages_should_be_chunked() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut settings = GelfSettings::new(GelfTransport::Udp, &address);
+        settings.compression = GelfCompression::None;
+        settings.max_chunk_size = 100;
+        let gelf_view = GelfView::new(&settings);
+
+        gelf_view.report(read_file(&"x".repeat(300))).await.unwrap();
+
+        let mut buffer = [0u8; 2048];
+        let length = collector.recv(&mut buffer).await.unwrap();
+        let first = buffer[..length].to_vec();
+        assert!(first.len() <= 100);
+        assert_eq!(first[..2], [0x1e, 0x0f]);
+        assert_eq!(first[10], 0);
+        let count = first[11] as usize;
+        let mut message = first[12..].to_vec();
+        for sequence in 1..count {
+            let length = collector.recv(&mut buffer).await.unwrap();
+            assert_eq!(buffer[2..10], first[2..10]);
+            assert_eq!(buffer[10] as usize, sequence);
+            message.extend_from_slice(&buffer[12..length]);
+        }
+        let message: Value = serde_json::from_slice(&message).unwrap();
+        assert_eq!(message["_path"], "x".repeat(300));
+    }
+
+    #[tokio::test]
+    async fn tcp_messages_should_be_null_terminated() {
+        let collector = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let gelf_view = GelfView::new(&GelfSettings::new(GelfTransport::Tcp, &address));
+
+        send_both(&gelf_view, "/etc/shadow").await;
+        drop(gelf_view);
+
+        let (mut stream, _) = collector.accept().await.unwrap();
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        let messages: Vec<Value> = received
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| serde_json::from_slice(x).unwrap())
+            .collect();
+        assert_eq!(received.last(), Some(&0));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["_executable"], "/usr/bin/vi");
+        assert_eq!(messages[1]["_path"], "/etc/shadow");
+    }
+
+    #[tokio::test]
+    async fn http_messages_should_be_posted_to_input() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/gelf")
+            .match_body(Matcher::PartialJson(json!({
+                "version": "1.1",
+                "short_message": "/etc/shadow",
+                "level": 6,
+                "_event": "file",
+                "_serial": 570
+            })))
+            .with_status(202)
+            .create_async()
+            .await;
+        let address = format!("{}/gelf", server.url());
+        let gelf_view = GelfView::new(&GelfSettings::new(GelfTransport::Http, &address));
+
+        gelf_view.report(read_file("/etc/shadow")).await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn chunk_size_should_not_matter_for_tcp() {
+        let collector = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut settings = GelfSettings::new(GelfTransport::Tcp, &address);
+        settings.max_chunk_size = 0;
+        let gelf_view = GelfView::new(&settings);
+
+        gelf_view.report(read_file("/etc/shadow")).await.unwrap();
+    }
+
+    #[test]
+    fn message_needing_too_many_chunks_should_be_permanent() {
+        let error = chunks(&[0u8; 20000], 7, 100).unwrap_err();
+        assert!(!error.is_retryable());
+    }
+}
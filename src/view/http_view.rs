@@ -1,44 +1,69 @@
 use crate::serializer::{FileOperatedOn, Operation};
-use crate::view::{HttpView, View};
+use crate::settings::EventFormat;
+use crate::view::event_format::{format_file, format_operation};
+use crate::view::{HttpView, View, ViewError};
 use async_trait::async_trait;
 use colored::Colorize;
 
 impl HttpView {
-    pub(crate) fn new(destination_url: &str) -> Self {
+    pub(crate) fn new(destination_url: &str, event_format: Option<EventFormat>) -> Self {
         return Self {
             destination_url: destination_url.to_string(),
             client: reqwest::Client::new(),
+            event_format,
+            last_operation: Default::default(),
         };
     }
+
+    async fn post(&self, body: String) -> Result<(), ViewError> {
+        self.client
+            .post(self.destination_url.to_string())
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ViewError::from_http(format!("Posting to {}", self.destination_url), e))?;
+        return Ok(());
+    }
 }
 
 #[async_trait]
 impl View for HttpView {
-    async fn update(&self, operation: Operation) -> Result<(), ()> {
-        let jsonized_operation = serde_json::to_string(&operation).unwrap();
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        self.last_operation.remember(&operation);
+        let jsonized_operation = match self.event_format {
+            Some(format) => format_operation(format, &operation),
+            None => serde_json::to_string(&operation).unwrap(),
+        };
         log::debug!(
             "Sending {} to endpoint {}",
             jsonized_operation.blue(),
             self.destination_url.to_string().green()
         );
-        let _resp = self
-            .client
-            .post(self.destination_url.to_string())
-            .body(jsonized_operation)
-            .send()
-            .await;
-        return Ok(());
+        return self.post(jsonized_operation).await;
     }
 
-    async fn report(&self, _files: FileOperatedOn) -> Result<(), ()> {
-        todo!()
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        let jsonized_file = match self.event_format {
+            Some(format) => format_file(format, &files, self.last_operation.of(&files).as_ref()),
+            None => serde_json::to_string(&files).unwrap(),
+        };
+        log::debug!(
+            "Sending {} to endpoint {}",
+            jsonized_file.blue(),
+            self.destination_url.to_string().green()
+        );
+        return self.post(jsonized_file).await;
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::serializer::Operation;
+    use crate::settings::EventFormat;
     use crate::view::{HttpView, View};
+    use mockito::Matcher;
+    use serde_json::json;
     const COMPLIANT_LOG_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 a0=ffffff9c a1=55a917750550 a2=90800 a3=0 items=1 ppid=20120 pid=20680 auid=1000 uid=1000 gid=1000 euid=1000 suid=1000 fsuid=1000 egid=1000 sgid=1000 fsgid=1000 tty=pts2 ses=14 comm=\"ls\" exe=\"/usr/bin/ls\" subj=unconfined key=\"READ\"ARCH=x86_64 AUID=\"maciek\" UID=\"maciek\" GID=\"maciek\" EUID=\"maciek\" SUID=\"maciek\" FSUID=\"maciek\" EGID=\"maciek\" SGID=\"maciek\"";
 
     #[tokio::test]
@@ -55,11 +80,70 @@ mod test {
             .create();
 
         // Reporting an operation
-        let http_view = HttpView::new(url.as_str());
+        let http_view = HttpView::new(url.as_str(), None);
         let response = http_view
             .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
             .await;
         assert!(response.is_ok());
         mock.assert();
     }
+
+    #[tokio::test]
+    async fn when_server_fails_error_should_be_retryable_unless_request_was_rejected() {
+        let mut destination_server = mockito::Server::new_async().await;
+        let unavailable = destination_server
+            .mock("POST", "/unavailable")
+            .with_status(503)
+            .create_async()
+            .await;
+        let rejecting = destination_server
+            .mock("POST", "/rejecting")
+            .with_status(400)
+            .create_async()
+            .await;
+
+        let operation = Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap();
+        let unavailable_view =
+            HttpView::new(&format!("{}/unavailable", destination_server.url()), None);
+        let rejecting_view =
+            HttpView::new(&format!("{}/rejecting", destination_server.url()), None);
+
+        assert!(unavailable_view
+            .update(operation.clone())
+            .await
+            .unwrap_err()
+            .is_retryable());
+        assert!(!rejecting_view
+            .update(operation)
+            .await
+            .unwrap_err()
+            .is_retryable());
+        unavailable.assert_async().await;
+        rejecting.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn ecs_event_format_should_post_nested_fields() {
+        let mut destination_server = mockito::Server::new_async().await;
+        let mock = destination_server
+            .mock("POST", "/events")
+            .match_body(Matcher::PartialJson(json!({
+                "event": { "action": "read", "category": ["file"], "sequence": 570 },
+                "user": { "name": "maciek" },
+                "process": { "executable": "/usr/bin/ls", "name": "ls" }
+            })))
+            .with_status(201)
+            .create_async()
+            .await;
+
+        let http_view = HttpView::new(
+            &format!("{}/events", destination_server.url()),
+            Some(EventFormat::Ecs),
+        );
+        http_view
+            .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        mock.assert_async().await;
+    }
 }
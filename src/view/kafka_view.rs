@@ -0,0 +1,317 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::{KafkaAcks, KafkaCompression, KafkaPartitionKey, KafkaSettings};
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{hostname, to_json, JsonLine, LastOperation};
+use crate::view::{KafkaView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use kafka::client::{Compression, RequiredAcks};
+use kafka::producer::{Producer, Record};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+const CLIENT_ID: &str = "linux-fs-audit";
+const KEY_PLACEHOLDER: &str = "{key}";
+/// Stands in for what a file reported without its operation does not tell.
+const UNKNOWN: &str = "unknown";
+
+/// Publishes batches of events, connecting to the brokers on first use and again after a failure.
+pub(super) struct KafkaWriter {
+    config: ProducerConfig,
+    topic: String,
+    partition_key: KafkaPartitionKey,
+    producer: Option<Producer>,
+    last_operation: LastOperation,
+}
+
+#[derive(Clone)]
+struct ProducerConfig {
+    brokers: Vec<String>,
+    acks: KafkaAcks,
+    ack_timeout: Duration,
+    compression: KafkaCompression,
+}
+
+/// A record ready to be sent, owning what [`Record`] borrows.
+struct OutgoingRecord {
+    topic: String,
+    key: String,
+    value: String,
+}
+
+impl KafkaView {
+    pub(crate) fn new(settings: &KafkaSettings, context: &ViewContext) -> Self {
+        let batcher = Batcher::spawn(
+            context,
+            KafkaWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for KafkaView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for KafkaWriter {
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut records = Vec::with_capacity(events.len());
+        for event in events {
+            records.push(self.record(event)?);
+        }
+        let producer = self.producer.take();
+        let config = self.config.clone();
+        let (producer, result) =
+            tokio::task::spawn_blocking(move || produce(producer, &config, &records))
+                .await
+                .map_err(|e| ViewError::permanent("Producing to Kafka").caused_by(e))?;
+        self.producer = producer;
+        return result;
+    }
+}
+
+impl KafkaWriter {
+    pub(super) fn new(settings: &KafkaSettings) -> Self {
+        return Self {
+            config: ProducerConfig {
+                brokers: settings.brokers.clone(),
+                acks: settings.acks,
+                ack_timeout: Duration::from_millis(settings.ack_timeout_ms),
+                compression: settings.compression,
+            },
+            topic: settings.topic.clone(),
+            partition_key: settings.partition_key,
+            producer: None,
+            last_operation: LastOperation::default(),
+        };
+    }
+
+    /// Files take the topic and, when keyed by user, the key of the operation that touched them.
+    fn record(&self, event: &ViewEvent) -> Result<OutgoingRecord, ViewError> {
+        let (operation, path, value) = match event {
+            ViewEvent::Operation(operation) => {
+                self.last_operation.remember(operation);
+                let value = to_json(JsonLine::Operation(operation))?;
+                (Some(operation.clone()), operation.executable.clone(), value)
+            }
+            ViewEvent::File(file) => {
+                let value = to_json(JsonLine::File(file))?;
+                (self.last_operation.of(file), file.name.clone(), value)
+            }
+        };
+        let audit_key = match &operation {
+            Some(operation) => operation.key.to_string().to_lowercase(),
+            None => UNKNOWN.to_string(),
+        };
+        let key = match self.partition_key {
+            KafkaPartitionKey::Host if !hostname().is_empty() => hostname().to_string(),
+            KafkaPartitionKey::Host => UNKNOWN.to_string(),
+            KafkaPartitionKey::User => operation.map_or(UNKNOWN.to_string(), |x| x.user),
+            KafkaPartitionKey::Path => path,
+        };
+        return Ok(OutgoingRecord {
+            topic: self.topic.replace(KEY_PLACEHOLDER, &audit_key),
+            key,
+            value,
+        });
+    }
+}
+
+impl ProducerConfig {
+    fn create(&self) -> kafka::Result<Producer> {
+        let acks = match self.acks {
+            KafkaAcks::None => RequiredAcks::None,
+            KafkaAcks::One => RequiredAcks::One,
+            KafkaAcks::All => RequiredAcks::All,
+        };
+        let compression = match self.compression {
+            KafkaCompression::None => Compression::NONE,
+            KafkaCompression::Gzip => Compression::GZIP,
+            KafkaCompression::Snappy => Compression::SNAPPY,
+        };
+        return Producer::from_hosts(self.brokers.clone())
+            .with_client_id(CLIENT_ID.to_string())
+            .with_required_acks(acks)
+            .with_ack_timeout(self.ack_timeout)
+            .with_compression(compression)
+            .create();
+    }
+}
+
+/// Sends the records, handing the producer back unless it failed, so that the next batch starts afresh.
+fn produce(
+    producer: Option<Producer>,
+    config: &ProducerConfig,
+    records: &[OutgoingRecord],
+) -> (Option<Producer>, Result<(), ViewError>) {
+    let mut producer = match producer.map_or_else(|| config.create(), Ok) {
+        Ok(producer) => producer,
+        Err(e) => return (None, Err(ViewError::from_kafka("Connecting to Kafka", e))),
+    };
+    return match send(&mut producer, records) {
+        Ok(_) => (Some(producer), Ok(())),
+        Err(e) => (None, Err(ViewError::from_kafka("Producing to Kafka", e))),
+    };
+}
+
+/// Looks up the topics the producer does not know yet, which lets the brokers create them when they are allowed to.
+fn send(producer: &mut Producer, records: &[OutgoingRecord]) -> kafka::Result<()> {
+    let topics = producer.client().topics();
+    let missing: BTreeSet<&str> = records
+        .iter()
+        .map(|x| x.topic.as_str())
+        .filter(|x| !topics.contains(x))
+        .collect();
+    if !missing.is_empty() {
+        let mut known: Vec<String> = topics.names().map(String::from).collect();
+        known.extend(missing.into_iter().map(String::from));
+        producer.client_mut().load_metadata(&known)?;
+    }
+    let records: Vec<Record<&str, &str>> = records
+        .iter()
+        .map(|x| Record::from_key_value(&x.topic, x.key.as_str(), x.value.as_str()))
+        .collect();
+    for confirm in producer.send_all(&records)? {
+        for partition in confirm.partition_confirms {
+            if let Err(error_code) = partition.offset {
+                return Err(kafka::Error::TopicPartitionError {
+                    topic_name: confirm.topic,
+                    partition_id: partition.partition,
+                    error_code,
+                });
+            }
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{KafkaPartitionKey, KafkaSettings};
+    use crate::test_fixtures::{file, read_file, read_operation, write_operation};
+    use crate::view::batching::BatchWriter;
+    use crate::view::kafka_view::KafkaWriter;
+    use crate::view::ViewEvent;
+    use kafka::consumer::{Consumer, FetchOffset};
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A read and a write, each with its file, and a file of an event without an operation.
+    fn events() -> Vec<ViewEvent> {
+        return vec![
+            ViewEvent::Operation(read_operation()),
+            ViewEvent::File(read_file("/etc/hosts")),
+            ViewEvent::Operation(write_operation()),
+            ViewEvent::File(file("/etc/shadow", 571, 1698576563120)),
+            ViewEvent::File(file("/tmp/orphan", 999, 1698576563999)),
+        ];
+    }
+
+    fn brokers() -> Vec<String> {
+        let brokers = std::env::var("LINUX_FS_AUDIT_KAFKA_BROKERS")
+            .unwrap_or_else(|_| "localhost:9092".to_string());
+        return brokers.split(',').map(String::from).collect();
+    }
+
+    #[test]
+    fn records_should_take_topic_of_their_key_and_partition_key_as_configured() {
+        let mut settings = KafkaSettings::new(vec![]);
+        settings.topic = "audit.{key}".to_string();
+        settings.partition_key = KafkaPartitionKey::User;
+        let writer = KafkaWriter::new(&settings);
+
+        let records: Vec<(String, String)> = events()
+            .iter()
+            .map(|x| writer.record(x).unwrap())
+            .map(|x| (x.topic, x.key))
+            .collect();
+
+        let expected = [
+            ("audit.read", "maciek"),
+            ("audit.read", "maciek"),
+            ("audit.write", "root"),
+            ("audit.write", "root"),
+            ("audit.unknown", "unknown"),
+        ];
+        assert_eq!(
+            records,
+            expected.map(|(topic, key)| (topic.to_string(), key.to_string()))
+        );
+    }
+
+    #[test]
+    fn records_keyed_by_path_should_use_file_or_executable() {
+        let mut settings = KafkaSettings::new(vec![]);
+        settings.partition_key = KafkaPartitionKey::Path;
+        let writer = KafkaWriter::new(&settings);
+
+        let keys: Vec<String> = events()
+            .iter()
+            .map(|x| writer.record(x).unwrap().key)
+            .collect();
+
+        assert_eq!(
+            keys,
+            [
+                "/usr/bin/ls",
+                "/etc/hosts",
+                "/usr/bin/vi",
+                "/etc/shadow",
+                "/tmp/orphan"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Kafka broker allowed to create topics"]
+    async fn records_should_be_readable_from_broker() {
+        let run = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let mut settings = KafkaSettings::new(brokers());
+        settings.topic = format!("linux-fs-audit-test-{}-{{key}}", run);
+        settings.partition_key = KafkaPartitionKey::Path;
+        let mut writer = KafkaWriter::new(&settings);
+
+        writer.write(&events()).await.unwrap();
+
+        let topic = format!("linux-fs-audit-test-{}-write", run);
+        let mut consumer = Consumer::from_hosts(brokers())
+            .with_topic(topic)
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(None)
+            .create()
+            .unwrap();
+        let mut received = BTreeMap::new();
+        for message_set in consumer.poll().unwrap().iter() {
+            for message in message_set.messages() {
+                let key = String::from_utf8_lossy(message.key).to_string();
+                let value: Value = serde_json::from_slice(message.value).unwrap();
+                received.insert(key, value);
+            }
+        }
+        assert_eq!(received.len(), 2);
+        assert_eq!(received["/usr/bin/vi"]["event"], "operation");
+        assert_eq!(received["/etc/shadow"]["event"], "file");
+        assert_eq!(received["/etc/shadow"]["serial"], 571);
+    }
+}
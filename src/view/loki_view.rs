@@ -0,0 +1,251 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::LokiSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{hostname, to_json, JsonLine, LastOperation};
+use crate::view::{LokiView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::Client;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+const PUSH_PATH: &str = "/loki/api/v1/push";
+const TENANT_HEADER: &str = "X-Scope-OrgID";
+const NANOS_PER_MILLI: u128 = 1_000_000;
+
+/// The labels of a stream, kept to the few values that do not grow with the number of events.
+type Labels = BTreeMap<&'static str, String>;
+
+/// Pushes batches of events to Loki, one stream per distinct set of labels.
+pub(super) struct LokiWriter {
+    client: Client,
+    push_url: String,
+    job: String,
+    tenant_id: Option<String>,
+    last_operation: LastOperation,
+}
+
+impl LokiView {
+    pub(crate) fn new(settings: &LokiSettings, context: &ViewContext) -> Self {
+        let batcher = Batcher::spawn(
+            context,
+            LokiWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for LokiView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for LokiWriter {
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut streams: BTreeMap<Labels, Vec<(u128, String)>> = BTreeMap::new();
+        for event in events {
+            let (labels, audit_time, line) = match event {
+                ViewEvent::Operation(operation) => {
+                    self.last_operation.remember(operation);
+                    let line = to_json(JsonLine::Operation(operation))?;
+                    (self.labels(Some(operation)), operation.audit_time, line)
+                }
+                ViewEvent::File(file) => {
+                    let operation = self.last_operation.of(file);
+                    let line = to_json(JsonLine::File(file))?;
+                    (self.labels(operation.as_ref()), file.audit_time, line)
+                }
+            };
+            streams
+                .entry(labels)
+                .or_default()
+                .push((timestamp_nanos(audit_time), line));
+        }
+        let streams: Vec<_> = streams
+            .into_iter()
+            .map(|(labels, mut values)| {
+                values.sort_by_key(|(timestamp, _)| *timestamp);
+                let values: Vec<_> = values
+                    .into_iter()
+                    .map(|(timestamp, line)| json!([timestamp.to_string(), line]))
+                    .collect();
+                json!({ "stream": labels, "values": values })
+            })
+            .collect();
+        let body = gzip(json!({ "streams": streams }).to_string().as_bytes())
+            .map_err(|e| ViewError::permanent("Compressing push request").caused_by(e))?;
+        let mut request = self
+            .client
+            .post(&self.push_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(reqwest::header::CONTENT_ENCODING, "gzip")
+            .body(body);
+        if let Some(tenant_id) = &self.tenant_id {
+            request = request.header(TENANT_HEADER, tenant_id);
+        }
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ViewError::from_http(format!("Pushing to {}", self.push_url), e))?;
+        return Ok(());
+    }
+}
+
+impl LokiWriter {
+    pub(super) fn new(settings: &LokiSettings) -> Self {
+        return Self {
+            client: Client::new(),
+            push_url: format!("{}{}", settings.url.trim_end_matches('/'), PUSH_PATH),
+            job: settings.job.clone(),
+            tenant_id: settings.tenant_id.clone(),
+            last_operation: LastOperation::default(),
+        };
+    }
+
+    /// Files go into the stream of the operation that touched them, when it came before them.
+    fn labels(&self, operation: Option<&Operation>) -> Labels {
+        let mut labels = Labels::new();
+        labels.insert("job", self.job.clone());
+        if !hostname().is_empty() {
+            labels.insert("host", hostname().to_string());
+        }
+        if let Some(operation) = operation {
+            labels.insert("key", operation.key.to_string());
+            labels.insert("user", operation.user.clone());
+        }
+        return labels;
+    }
+}
+
+fn timestamp_nanos(audit_time: u64) -> u128 {
+    return match audit_time {
+        0 => Utc::now().timestamp_millis() as u128 * NANOS_PER_MILLI,
+        _ => audit_time as u128 * NANOS_PER_MILLI,
+    };
+}
+
+fn gzip(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    return encoder.finish();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::LokiSettings;
+    use crate::test_fixtures::{file, read_file, read_operation, write_operation};
+    use crate::view::batching::BatchWriter;
+    use crate::view::loki_view::LokiWriter;
+    use crate::view::{LokiView, View, ViewContext, ViewEvent};
+    use flate2::read::GzDecoder;
+    use serde_json::Value;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every push with `status`, keeping the decompressed bodies.
+    async fn loki_server(
+        status: usize,
+    ) -> (mockito::ServerGuard, mockito::Mock, Arc<Mutex<Vec<Value>>>) {
+        let mut server = mockito::Server::new_async().await;
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        let mock = server
+            .mock("POST", "/loki/api/v1/push")
+            .match_header("content-encoding", "gzip")
+            .match_header("x-scope-orgid", "ops")
+            .with_status(status)
+            .with_body_from_request(move |request| {
+                let mut body = String::new();
+                GzDecoder::new(request.body().unwrap().as_slice())
+                    .read_to_string(&mut body)
+                    .unwrap();
+                received
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::from_str(&body).unwrap());
+                return Vec::new();
+            })
+            .create_async()
+            .await;
+        return (server, mock, bodies);
+    }
+
+    fn settings(server: &mockito::ServerGuard) -> LokiSettings {
+        let mut settings = LokiSettings::new(&server.url());
+        settings.tenant_id = Some("ops".to_string());
+        return settings;
+    }
+
+    #[tokio::test]
+    async fn events_should_be_pushed_in_one_stream_per_key_and_user() {
+        let (server, mock, bodies) = loki_server(204).await;
+        let loki_view = LokiView::new(&settings(&server), &ViewContext::default());
+
+        loki_view.update(read_operation()).await.unwrap();
+        loki_view.report(read_file("/etc/hosts")).await.unwrap();
+        loki_view.update(write_operation()).await.unwrap();
+        loki_view
+            .report(file("/etc/shadow", 571, 1698576563120))
+            .await
+            .unwrap();
+        loki_view.flush().await.unwrap();
+
+        mock.assert_async().await;
+        let bodies = bodies.lock().unwrap();
+        let streams = bodies[0]["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        let read = streams
+            .iter()
+            .find(|x| x["stream"]["key"] == "READ")
+            .unwrap();
+        assert_eq!(read["stream"]["user"], "maciek");
+        assert_eq!(read["stream"]["job"], "linux-fs-audit");
+        let values = read["values"].as_array().unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0][0], "1698576562955000000");
+        let line: Value = serde_json::from_str(values[1][1].as_str().unwrap()).unwrap();
+        assert_eq!(line["event"], "file");
+        assert_eq!(line["name"], "/etc/hosts");
+        let write = streams
+            .iter()
+            .find(|x| x["stream"]["key"] == "WRITE")
+            .unwrap();
+        assert_eq!(write["stream"]["user"], "root");
+        assert_eq!(write["values"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn throttled_push_should_be_retryable() {
+        let (server, _mock, _) = loki_server(429).await;
+        let mut writer = LokiWriter::new(&settings(&server));
+
+        let error = writer
+            .write(&[ViewEvent::File(read_file("/etc/shadow"))])
+            .await
+            .unwrap_err();
+
+        assert!(error.is_retryable());
+    }
+}
@@ -1,10 +1,10 @@
 use crate::serializer::{FileOperatedOn, Operation};
-use crate::view::{MockView, View};
+use crate::view::{MockView, View, ViewError};
 use async_trait::async_trait;
 
 #[async_trait]
 impl View for MockView {
-    async fn update(&self, operation: Operation) -> Result<(), ()> {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
         log::debug!(
             "Operation parsed to a json: {}",
             serde_json::to_string(&operation).unwrap()
@@ -12,7 +12,11 @@ impl View for MockView {
         return Ok(());
     }
 
-    async fn report(&self, _files: FileOperatedOn) -> Result<(), ()> {
-        todo!()
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        log::debug!(
+            "File parsed to a json: {}",
+            serde_json::to_string(&files).unwrap()
+        );
+        return Ok(());
     }
 }
@@ -1,22 +1,199 @@
+mod batching;
+mod composite_view;
+mod elasticsearch_view;
+mod event_format;
+mod file_view;
+mod fluent_view;
+mod gelf_view;
 mod http_view;
+mod kafka_view;
+mod loki_view;
 mod mock_view;
+mod otlp_view;
+mod parquet_view;
+mod postgres_migrations;
+mod postgres_view;
+mod socket;
+mod splunk_view;
+mod sqlite_migrations;
+mod sqlite_retention;
 mod sqlite_view;
+mod stdout_view;
+mod syslog_view;
+mod view_error;
 
+use crate::controller::dead_letter::FailedEvents;
+use crate::filter::Filter;
 use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::ViewDefinition;
 use async_trait::async_trait;
 use reqwest::Client;
+use serde::Serialize;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+const RENDER_ATTEMPTS: u32 = 3;
+const RENDER_RETRY_BACKOFF: Duration = Duration::from_millis(200);
 
 #[async_trait]
 pub(crate) trait View: Send + Sync {
-    async fn update(&self, operation: Operation) -> Result<(), ()>;
-    async fn report(&self, files: FileOperatedOn) -> Result<(), ()>;
+    async fn update(&self, operation: Operation) -> Result<(), ViewError>;
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError>;
+
+    /// Waits until everything handed over so far has been rendered, for views which render in the background.
+    async fn flush(&self) -> Result<(), ViewError> {
+        return Ok(());
+    }
+
+    /// Whether the view only queues the events it is handed, counting what became of them once rendered itself.
+    fn renders_in_background(&self) -> bool {
+        return false;
+    }
+}
+
+/// A single observation handed over to a view.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ViewEvent {
+    Operation(Operation),
+    File(FileOperatedOn),
+}
+
+/// Why a view could not render an event. Retryable errors, like an unreachable endpoint or a full disk,
+/// may go away on their own, permanent ones will fail the same way on every attempt.
+#[derive(Debug)]
+pub(crate) struct ViewError {
+    kind: ViewErrorKind,
+    context: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ViewErrorKind {
+    Retryable,
+    Permanent,
 }
 
 pub(crate) struct HttpView {
     destination_url: String,
     client: Client,
+    event_format: Option<crate::settings::EventFormat>,
+    last_operation: event_format::LastOperation,
 }
 pub(crate) struct SqliteView {
-    db_path: String,
+    batcher: batching::Batcher,
+}
+pub(crate) struct PostgresView {
+    batcher: batching::Batcher,
+}
+/// Indexes every event into Elasticsearch or OpenSearch, see [`crate::settings::ElasticsearchSettings`].
+pub(crate) struct ElasticsearchView {
+    batcher: batching::Batcher,
+}
+/// Pushes every event to Grafana Loki, see [`crate::settings::LokiSettings`].
+pub(crate) struct LokiView {
+    batcher: batching::Batcher,
+}
+/// Sends every event to a Splunk HTTP Event Collector, see [`crate::settings::SplunkSettings`].
+pub(crate) struct SplunkView {
+    batcher: batching::Batcher,
+}
+/// Publishes every event to Kafka, see [`crate::settings::KafkaSettings`].
+pub(crate) struct KafkaView {
+    batcher: batching::Batcher,
+}
+/// Exports every event as an OpenTelemetry log record, see [`crate::settings::OtlpSettings`].
+pub(crate) struct OtlpView {
+    batcher: batching::Batcher,
+}
+/// Forwards every event to Fluentd or Fluent Bit, see [`crate::settings::FluentSettings`].
+pub(crate) struct FluentView {
+    batcher: batching::Batcher,
+}
+/// Writes every event to Parquet files partitioned by date and key, see [`crate::settings::ParquetSettings`].
+pub(crate) struct ParquetView {
+    batcher: batching::Batcher,
+}
+/// Appends every event to a json lines file, see [`crate::settings::FileSettings`].
+pub(crate) struct FileView {
+    file: std::sync::Arc<std::sync::Mutex<file_view::RotatingFile>>,
+    reopen: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    event_format: Option<crate::settings::EventFormat>,
+    last_operation: event_format::LastOperation,
+}
+/// Sends every event as a syslog message, see [`crate::settings::SyslogSettings`].
+pub(crate) struct SyslogView {
+    settings: crate::settings::SyslogSettings,
+    hostname: String,
+    connection: tokio::sync::Mutex<syslog_view::Connection>,
+    last_operation: event_format::LastOperation,
+}
+/// Sends every event as a GELF message to Graylog, see [`crate::settings::GelfSettings`].
+pub(crate) struct GelfView {
+    settings: crate::settings::GelfSettings,
+    connection: tokio::sync::Mutex<gelf_view::Connection>,
+    client: Client,
+    last_operation: event_format::LastOperation,
+}
+/// Prints every event as one line, see [`crate::settings::StdoutSettings`].
+pub(crate) struct StdoutView {
+    event_format: Option<crate::settings::EventFormat>,
+    last_operation: event_format::LastOperation,
 }
 pub(crate) struct MockView {}
+
+/// Fans every observation out to several views, each one draining its own queue.
+pub(crate) struct CompositeView {
+    sinks: std::sync::Arc<Vec<composite_view::Sink>>,
+    last_operation: event_format::LastOperation,
+}
+
+/// What a view is created with besides its own settings, shared with the rest of the application.
+#[derive(Clone, Default)]
+pub(crate) struct ViewContext {
+    /// Where views which render in the background put the events they give up on.
+    pub(crate) failed_events: Arc<FailedEvents>,
+    /// What the view is called in logs, metrics and health.
+    pub(crate) view_name: String,
+    /// Batches in a row the writer of a batched view may fail before it is no longer ready.
+    pub(crate) error_budget: u64,
+}
+
+impl ViewContext {
+    /// The context of the view `definition` describes.
+    pub(crate) fn of(&self, definition: &ViewDefinition) -> Self {
+        return Self {
+            failed_events: self.failed_events.clone(),
+            view_name: definition.display_name(),
+            error_budget: definition.error_budget,
+        };
+    }
+}
+
+/// A view taking part in a [`CompositeView`], rendering only the events its filter accepts.
+pub(crate) struct SinkDefinition {
+    pub(crate) name: String,
+    pub(crate) view: Box<dyn View>,
+    pub(crate) queue_capacity: usize,
+    pub(crate) filter: Option<Filter>,
+}
+
+/// Hands the event over to the view, trying again with a growing pause while the error is retryable.
+pub(crate) async fn render(view: &dyn View, event: &ViewEvent) -> Result<(), ViewError> {
+    let mut attempt = 1;
+    loop {
+        let result = match event.clone() {
+            ViewEvent::Operation(operation) => view.update(operation).await,
+            ViewEvent::File(file) => view.report(file).await,
+        };
+        match result {
+            Err(error) if error.is_retryable() && attempt < RENDER_ATTEMPTS => {
+                log::debug!("Attempt {} failed, retrying: {}", attempt, error);
+                tokio::time::sleep(RENDER_RETRY_BACKOFF * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
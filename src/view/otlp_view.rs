@@ -0,0 +1,530 @@
+use crate::serializer::{FileOperatedOn, Operation, OperationKey};
+use crate::settings::{OtlpProtocol, OtlpSettings};
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{hostname, LastOperation};
+use crate::view::{OtlpView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use chrono::Utc;
+use colored::Colorize;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_client::LogsServiceClient;
+use opentelemetry_proto::tonic::collector::logs::v1::{
+    ExportLogsServiceRequest, ExportLogsServiceResponse,
+};
+use opentelemetry_proto::tonic::common::v1::any_value::Value;
+use opentelemetry_proto::tonic::common::v1::{AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::{LogRecord, ResourceLogs, ScopeLogs, SeverityNumber};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+
+const LOGS_PATH: &str = "/v1/logs";
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+const SCOPE_NAME: &str = "linux-fs-audit";
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Exports batches of events to an OpenTelemetry collector, all of them in a single resource and scope.
+pub(super) struct OtlpWriter {
+    exporter: Exporter,
+    resource: Resource,
+    max_retries: usize,
+    retry_backoff: Duration,
+    last_operation: LastOperation,
+}
+
+enum Exporter {
+    Grpc {
+        client: LogsServiceClient<Channel>,
+        metadata: MetadataMap,
+    },
+    Http {
+        client: Client,
+        url: String,
+        headers: HeaderMap,
+    },
+}
+
+impl OtlpView {
+    pub(crate) fn new(settings: &OtlpSettings, context: &ViewContext) -> Self {
+        let writer = match OtlpWriter::new(settings) {
+            Ok(writer) => writer,
+            Err(e) => panic!("Fatal: OTLP exporter could not be set up: {}", e),
+        };
+        let batcher = Batcher::spawn(
+            context,
+            writer,
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for OtlpView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for OtlpWriter {
+    /// Exports the batch again, up to `max_retries` times, while the collector cannot take it. Records it
+    /// rejected fail the batch, they would be rejected the same way on every attempt.
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let request = self.request(events);
+        let mut attempt = 0;
+        let response = loop {
+            match self.exporter.export(&request).await {
+                Err(e) if e.is_retryable() && attempt < self.max_retries => {
+                    attempt += 1;
+                    log::warn!(
+                        "{} attempt {}: {}",
+                        "OTLP export failed, retrying".yellow(),
+                        attempt,
+                        e
+                    );
+                    tokio::time::sleep(self.retry_backoff * attempt as u32).await;
+                }
+                result => break result?,
+            }
+        };
+        return match response.partial_success {
+            Some(partial) if partial.rejected_log_records > 0 => {
+                Err(ViewError::permanent(format!(
+                    "Collector rejected {} log records: {}",
+                    partial.rejected_log_records, partial.error_message
+                )))
+            }
+            _ => Ok(()),
+        };
+    }
+}
+
+impl OtlpWriter {
+    pub(super) fn new(settings: &OtlpSettings) -> Result<Self, String> {
+        let endpoint = settings.endpoint().trim_end_matches('/');
+        let timeout = Duration::from_millis(settings.timeout_ms);
+        let exporter = match settings.protocol {
+            OtlpProtocol::Grpc => Exporter::Grpc {
+                client: LogsServiceClient::new(
+                    Endpoint::from_shared(endpoint.to_string())
+                        .map_err(|e| format!("{} is not a valid endpoint: {}", endpoint, e))?
+                        .timeout(timeout)
+                        .connect_lazy(),
+                ),
+                metadata: metadata(&settings.headers)?,
+            },
+            OtlpProtocol::HttpProtobuf => Exporter::Http {
+                client: Client::builder()
+                    .timeout(timeout)
+                    .build()
+                    .map_err(|e| e.to_string())?,
+                url: format!("{}{}", endpoint, LOGS_PATH),
+                headers: headers(&settings.headers)?,
+            },
+        };
+        let mut resource = vec![
+            attribute("service.name", string(&settings.service_name)),
+            attribute("service.version", string(env!("CARGO_PKG_VERSION"))),
+        ];
+        if !hostname().is_empty() {
+            resource.push(attribute("host.name", string(hostname())));
+        }
+        return Ok(Self {
+            exporter,
+            resource: Resource {
+                attributes: resource,
+                dropped_attributes_count: 0,
+            },
+            max_retries: settings.max_retries,
+            retry_backoff: RETRY_BACKOFF,
+            last_operation: LastOperation::default(),
+        });
+    }
+
+    fn request(&self, events: &[ViewEvent]) -> ExportLogsServiceRequest {
+        let observed_time = Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64;
+        let log_records = events
+            .iter()
+            .map(|event| self.log_record(event, observed_time))
+            .collect();
+        return ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(self.resource.clone()),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(InstrumentationScope {
+                        name: SCOPE_NAME.to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        attributes: vec![],
+                        dropped_attributes_count: 0,
+                    }),
+                    log_records,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+    }
+
+    /// Files carry the attributes of the operation that touched them, when it came before them.
+    /// A time past what nanoseconds since the epoch can count is left out as unknown.
+    fn log_record(&self, event: &ViewEvent, observed_time: u64) -> LogRecord {
+        let (operation, audit_time, body, mut attributes) = match event {
+            ViewEvent::Operation(operation) => {
+                self.last_operation.remember(operation);
+                let body = format!(
+                    "{} {} {}",
+                    operation.user, operation.key, operation.executable
+                );
+                let attributes = vec![attribute("event.name", string("linux_fs_audit.operation"))];
+                (
+                    Some(operation.clone()),
+                    operation.audit_time,
+                    body,
+                    attributes,
+                )
+            }
+            ViewEvent::File(file) => {
+                let operation = self.last_operation.of(file);
+                let body = match &operation {
+                    Some(operation) => format!("{} {}", operation.key, file.name),
+                    None => file.name.clone(),
+                };
+                let attributes = vec![
+                    attribute("event.name", string("linux_fs_audit.file")),
+                    attribute("file.path", string(&file.name)),
+                    attribute("linux_fs_audit.serial", Value::IntValue(file.serial as i64)),
+                ];
+                (operation, file.audit_time, body, attributes)
+            }
+        };
+        let severity = match operation.as_ref().map(|x| &x.key) {
+            Some(OperationKey::WRITE) => (SeverityNumber::Warn, "WARN"),
+            _ => (SeverityNumber::Info, "INFO"),
+        };
+        if let Some(operation) = &operation {
+            attributes.extend(operation_attributes(operation));
+        }
+        return LogRecord {
+            time_unix_nano: audit_time.checked_mul(NANOS_PER_MILLI).unwrap_or(0),
+            observed_time_unix_nano: observed_time,
+            severity_number: severity.0 as i32,
+            severity_text: severity.1.to_string(),
+            body: Some(AnyValue {
+                value: Some(string(&body)),
+            }),
+            attributes,
+            ..LogRecord::default()
+        };
+    }
+}
+
+impl Exporter {
+    async fn export(
+        &mut self,
+        request: &ExportLogsServiceRequest,
+    ) -> Result<ExportLogsServiceResponse, ViewError> {
+        return match self {
+            Exporter::Grpc { client, metadata } => {
+                let mut request = tonic::Request::new(request.clone());
+                *request.metadata_mut() = metadata.clone();
+                client
+                    .export(request)
+                    .await
+                    .map(tonic::Response::into_inner)
+                    .map_err(|e| ViewError::from_grpc("Exporting log records", e))
+            }
+            Exporter::Http {
+                client,
+                url,
+                headers,
+            } => {
+                let body = client
+                    .post(url.as_str())
+                    .headers(headers.clone())
+                    .header(reqwest::header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)
+                    .body(request.encode_to_vec())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| ViewError::from_http(format!("Posting to {}", url), e))?
+                    .bytes()
+                    .await
+                    .map_err(|e| ViewError::from_http("Reading export response", e))?;
+                ExportLogsServiceResponse::decode(body.as_ref())
+                    .map_err(|e| ViewError::permanent("Decoding export response").caused_by(e))
+            }
+        };
+    }
+}
+
+/// Semantic convention attributes where there is one, the rest namespaced as `linux_fs_audit`.
+fn operation_attributes(operation: &Operation) -> Vec<KeyValue> {
+    let mut attributes = vec![
+        attribute("user.name", string(&operation.user)),
+        attribute("process.executable.path", string(&operation.executable)),
+        attribute("process.command_line", string(&operation.command)),
+        attribute("linux_fs_audit.group", string(&operation.group)),
+        attribute("linux_fs_audit.key", string(&operation.key.to_string())),
+        attribute("linux_fs_audit.syscall", string(&operation.syscall)),
+        attribute(
+            "linux_fs_audit.success",
+            Value::BoolValue(operation.success),
+        ),
+        attribute(
+            "linux_fs_audit.serial",
+            Value::IntValue(operation.serial as i64),
+        ),
+    ];
+    if let Some(pid) = operation.pid {
+        attributes.push(attribute("process.pid", Value::IntValue(pid as i64)));
+    }
+    return attributes;
+}
+
+fn attribute(key: &str, value: Value) -> KeyValue {
+    return KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue { value: Some(value) }),
+    };
+}
+
+fn string(value: &str) -> Value {
+    return Value::StringValue(value.to_string());
+}
+
+fn metadata(headers: &BTreeMap<String, String>) -> Result<MetadataMap, String> {
+    let mut metadata = MetadataMap::new();
+    for (name, value) in headers {
+        let key = MetadataKey::from_bytes(name.to_lowercase().as_bytes())
+            .map_err(|e| format!("{} is not a valid header: {}", name, e))?;
+        let value = MetadataValue::try_from(value.as_str())
+            .map_err(|e| format!("{} is not a valid value of {}: {}", value, name, e))?;
+        metadata.insert(key, value);
+    }
+    return Ok(metadata);
+}
+
+fn headers(headers: &BTreeMap<String, String>) -> Result<HeaderMap, String> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let key = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| format!("{} is not a valid header: {}", name, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| format!("{} is not a valid value of {}: {}", value, name, e))?;
+        header_map.insert(key, value);
+    }
+    return Ok(header_map);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{OtlpProtocol, OtlpSettings};
+    use crate::test_fixtures::{file, write_events, write_file, write_operation};
+    use crate::view::batching::BatchWriter;
+    use crate::view::otlp_view::OtlpWriter;
+    use crate::view::{OtlpView, View, ViewContext, ViewEvent};
+    use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::{
+        LogsService, LogsServiceServer,
+    };
+    use opentelemetry_proto::tonic::collector::logs::v1::{
+        ExportLogsServiceRequest, ExportLogsServiceResponse,
+    };
+    use opentelemetry_proto::tonic::common::v1::any_value::Value;
+    use opentelemetry_proto::tonic::common::v1::KeyValue;
+    use opentelemetry_proto::tonic::logs::v1::{LogRecord, SeverityNumber};
+    use prost::Message;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    /// A request with the value of its `authorization` metadata.
+    type Received = (ExportLogsServiceRequest, Option<String>);
+
+    #[derive(Default, Clone)]
+    struct FakeCollector {
+        requests: Arc<Mutex<Vec<Received>>>,
+    }
+
+    #[tonic::async_trait]
+    impl LogsService for FakeCollector {
+        async fn export(
+            &self,
+            request: tonic::Request<ExportLogsServiceRequest>,
+        ) -> Result<tonic::Response<ExportLogsServiceResponse>, tonic::Status> {
+            let authorization = request
+                .metadata()
+                .get("authorization")
+                .and_then(|x| x.to_str().ok())
+                .map(String::from);
+            self.requests
+                .lock()
+                .unwrap()
+                .push((request.into_inner(), authorization));
+            return Ok(tonic::Response::new(ExportLogsServiceResponse::default()));
+        }
+    }
+
+    fn value<'a>(attributes: &'a [KeyValue], key: &str) -> &'a Value {
+        return attributes
+            .iter()
+            .find(|x| x.key == key)
+            .and_then(|x| x.value.as_ref())
+            .and_then(|x| x.value.as_ref())
+            .unwrap_or_else(|| panic!("{} is missing", key));
+    }
+
+    fn string(value: &str) -> Value {
+        return Value::StringValue(value.to_string());
+    }
+
+    fn log_records(request: &ExportLogsServiceRequest) -> &[LogRecord] {
+        return &request.resource_logs[0].scope_logs[0].log_records;
+    }
+
+    #[tokio::test]
+    async fn events_should_be_posted_as_protobuf_with_semantic_convention_attributes() {
+        let mut server = mockito::Server::new_async().await;
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        let mock = server
+            .mock("POST", "/v1/logs")
+            .match_header("content-type", "application/x-protobuf")
+            .match_header("authorization", "Bearer 8a1f6c2e")
+            .with_body_from_request(move |request| {
+                let body = request.body().unwrap().as_slice();
+                received
+                    .lock()
+                    .unwrap()
+                    .push(ExportLogsServiceRequest::decode(body).unwrap());
+                return Vec::new();
+            })
+            .create_async()
+            .await;
+        let mut settings = OtlpSettings::new(OtlpProtocol::HttpProtobuf);
+        settings.endpoint = Some(server.url());
+        settings
+            .headers
+            .insert("Authorization".to_string(), "Bearer 8a1f6c2e".to_string());
+        let otlp_view = OtlpView::new(&settings, &ViewContext::default());
+
+        otlp_view.update(write_operation()).await.unwrap();
+        otlp_view.report(write_file("/etc/shadow")).await.unwrap();
+        otlp_view.flush().await.unwrap();
+
+        mock.assert_async().await;
+        let bodies = bodies.lock().unwrap();
+        let resource = bodies[0].resource_logs[0].resource.as_ref().unwrap();
+        assert_eq!(
+            value(&resource.attributes, "service.name"),
+            &string("linux-fs-audit")
+        );
+        let records = log_records(&bodies[0]);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].time_unix_nano, 1698576563120000000);
+        assert_eq!(records[0].severity_number, SeverityNumber::Warn as i32);
+        assert_eq!(
+            value(&records[0].attributes, "process.pid"),
+            &Value::IntValue(20681)
+        );
+        assert_eq!(
+            value(&records[0].attributes, "process.executable.path"),
+            &string("/usr/bin/vi")
+        );
+        assert_eq!(
+            value(&records[1].attributes, "file.path"),
+            &string("/etc/shadow")
+        );
+        assert_eq!(value(&records[1].attributes, "user.name"), &string("root"));
+    }
+
+    #[tokio::test]
+    async fn events_should_be_exported_over_grpc_with_headers_as_metadata() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let collector = FakeCollector::default();
+        let incoming = futures::stream::unfold(listener, |listener| async {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            return Some((connection, listener));
+        });
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(LogsServiceServer::new(collector.clone()))
+                .serve_with_incoming(incoming),
+        );
+        let mut settings = OtlpSettings::new(OtlpProtocol::Grpc);
+        settings.endpoint = Some(format!("http://{}", address));
+        settings
+            .headers
+            .insert("Authorization".to_string(), "Bearer 8a1f6c2e".to_string());
+        let mut writer = OtlpWriter::new(&settings).unwrap();
+
+        writer.write(&write_events()).await.unwrap();
+
+        let requests = collector.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1.as_deref(), Some("Bearer 8a1f6c2e"));
+        let records = log_records(&requests[0].0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[1].body.as_ref().unwrap().value,
+            Some(string("WRITE /etc/shadow"))
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_collector_should_be_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/v1/logs")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let available = server
+            .mock("POST", "/v1/logs")
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+        let mut settings = OtlpSettings::new(OtlpProtocol::HttpProtobuf);
+        settings.endpoint = Some(server.url());
+        let mut writer = OtlpWriter::new(&settings).unwrap();
+        writer.retry_backoff = Duration::from_millis(10);
+
+        writer.write(&write_events()).await.unwrap();
+
+        unavailable.assert_async().await;
+        available.assert_async().await;
+    }
+    #[tokio::test]
+    async fn time_past_nanoseconds_range_should_be_left_unknown() {
+        let writer = OtlpWriter::new(&OtlpSettings::new(OtlpProtocol::HttpProtobuf)).unwrap();
+
+        let record = writer.log_record(
+            &ViewEvent::File(file("/etc/shadow", 571, u64::MAX / 1000)),
+            1,
+        );
+
+        assert_eq!(record.time_unix_nano, 0);
+        assert_eq!(record.observed_time_unix_nano, 1);
+    }
+}
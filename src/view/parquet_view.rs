@@ -0,0 +1,248 @@
+use crate::export::{write_parts, AccessRow};
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::ParquetSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::LastOperation;
+use crate::view::{ParquetView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use parquet::errors::ParquetError;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Writes every batch as new Parquet parts, one in each partition the batch touches.
+pub(super) struct ParquetWriter {
+    directory: PathBuf,
+    last_operation: LastOperation,
+    /// The operation which ended the last batch, its files may still come with the next one.
+    held_back: Option<Operation>,
+}
+
+impl ParquetView {
+    pub(crate) fn new(settings: &ParquetSettings, context: &ViewContext) -> Self {
+        let batcher = Batcher::spawn(
+            context,
+            ParquetWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for ParquetView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for ParquetWriter {
+    /// A failed batch may leave the parts of the partitions written before it, which are written again.
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let (rows, trailing) = self.rows(events);
+        self.write_rows(rows).await?;
+        self.held_back = trailing;
+        return Ok(());
+    }
+
+    /// Nothing follows the held back operation anymore, so it gets a row of its own.
+    async fn flush(&mut self) -> Result<(), ViewError> {
+        if let Some(operation) = &self.held_back {
+            self.write_rows(vec![AccessRow::of_operation(operation, None)])
+                .await?;
+            self.held_back = None;
+        }
+        return Ok(());
+    }
+
+    fn holds_back(&self) -> bool {
+        return self.held_back.is_some();
+    }
+}
+
+impl ParquetWriter {
+    fn new(settings: &ParquetSettings) -> Self {
+        return Self {
+            directory: PathBuf::from(&settings.directory),
+            last_operation: LastOperation::default(),
+            held_back: None,
+        };
+    }
+
+    async fn write_rows(&self, rows: Vec<AccessRow>) -> Result<(), ViewError> {
+        let directory = self.directory.clone();
+        return tokio::task::spawn_blocking(move || write_parts(&directory, &rows))
+            .await
+            .map_err(|e| ViewError::permanent("Parquet writer stopped").caused_by(e))?
+            .map(|_| ())
+            .map_err(from_parquet);
+    }
+
+    /// A row per file, with the operation which touched it. An operation gets a row of its own only when no file
+    /// follows it, so that accesses without a file, like a failed open, are kept too. Whether one follows the
+    /// operation ending the batch is only known with the next batch, so it is returned to be held back until then.
+    fn rows(&self, events: &[ViewEvent]) -> (Vec<AccessRow>, Option<Operation>) {
+        let held_back = self.held_back.clone().map(ViewEvent::Operation);
+        let events: Vec<&ViewEvent> = held_back.iter().chain(events).collect();
+        let mut rows = Vec::with_capacity(events.len());
+        let mut trailing = None;
+        for (i, event) in events.iter().enumerate() {
+            match event {
+                ViewEvent::Operation(operation) => {
+                    self.last_operation.remember(operation);
+                    match events.get(i + 1) {
+                        None => trailing = Some(operation.clone()),
+                        Some(ViewEvent::File(file))
+                            if file.serial == operation.serial
+                                && file.audit_time == operation.audit_time => {}
+                        Some(_) => rows.push(AccessRow::of_operation(operation, None)),
+                    }
+                }
+                ViewEvent::File(file) => rows.push(match self.last_operation.of(file) {
+                    Some(operation) => AccessRow::of_operation(&operation, Some(file.name.clone())),
+                    None => AccessRow::of_file(file),
+                }),
+            }
+        }
+        return (rows, trailing);
+    }
+}
+
+/// Failing to write the file, like on a full disk, may go away, anything Parquet rejects will not.
+fn from_parquet(error: ParquetError) -> ViewError {
+    return match error {
+        ParquetError::External(source) => match source.downcast::<std::io::Error>() {
+            Ok(error) => ViewError::from_io("Could not write Parquet file", *error),
+            Err(source) => {
+                ViewError::permanent(format!("Could not write Parquet file: {}", source))
+            }
+        },
+        error => ViewError::permanent("Could not write Parquet file").caused_by(error),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::export::read_part;
+    use crate::serializer::Operation;
+    use crate::settings::ParquetSettings;
+    use crate::test_fixtures::{
+        file, read_operation, write_file, write_operation, READ_LOG_LINE, WRITE_AUDIT_TIME,
+    };
+    use crate::view::batching::BatchWriter;
+    use crate::view::parquet_view::ParquetWriter;
+    use crate::view::{ParquetView, View, ViewContext, ViewEvent};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn events_should_be_readable_back_from_their_partitions() {
+        let directory = tempdir().unwrap();
+        let mut settings = ParquetSettings::new(directory.path().to_str().unwrap());
+        settings.batch_size = 100;
+        let view = ParquetView::new(&settings, &ViewContext::default());
+
+        view.update(write_operation()).await.unwrap();
+        view.report(write_file("/etc/hosts")).await.unwrap();
+        view.report(write_file("/etc/hosts.bak")).await.unwrap();
+        let failed_read = READ_LOG_LINE.replace("success=yes", "success=no");
+        view.update(Operation::new(failed_read).unwrap())
+            .await
+            .unwrap();
+        view.report(file("/tmp/orphan", 9, 1698576564000))
+            .await
+            .unwrap();
+        view.flush().await.unwrap();
+
+        let partition = |key: &str| {
+            let path = directory.path().join("date=2023-10-29").join(key);
+            let parts: Vec<_> = std::fs::read_dir(path)
+                .unwrap()
+                .map(|x| x.unwrap().path())
+                .collect();
+            assert_eq!(parts.len(), 1);
+            return read_part(&parts[0]);
+        };
+        let written = partition("key=WRITE");
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0].path.as_deref(), Some("/etc/hosts"));
+        assert_eq!(written[1].path.as_deref(), Some("/etc/hosts.bak"));
+        assert_eq!(written[0].executable.as_deref(), Some("/usr/bin/vi"));
+        assert_eq!(written[0].user.as_deref(), Some("root"));
+        assert_eq!(written[0].success, Some(true));
+        assert_eq!(written[0].audit_time, WRITE_AUDIT_TIME);
+        let read = partition("key=READ");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].path, None);
+        assert_eq!(read[0].success, Some(false));
+        let orphan = partition("key=unknown");
+        assert_eq!(orphan.len(), 1);
+        assert_eq!(orphan[0].path.as_deref(), Some("/tmp/orphan"));
+        assert_eq!(orphan[0].user, None);
+    }
+
+    #[tokio::test]
+    async fn operation_ending_a_batch_should_wait_for_its_files_in_the_next_one() {
+        let directory = tempdir().unwrap();
+        let mut writer =
+            ParquetWriter::new(&ParquetSettings::new(directory.path().to_str().unwrap()));
+        let rows = |key: &str| {
+            let path = directory.path().join("date=2023-10-29").join(key);
+            return match std::fs::read_dir(path) {
+                Ok(parts) => parts.flat_map(|x| read_part(&x.unwrap().path())).collect(),
+                Err(_) => Vec::new(),
+            };
+        };
+
+        writer
+            .write(&[ViewEvent::Operation(write_operation())])
+            .await
+            .unwrap();
+        assert!(rows("key=WRITE").is_empty());
+        writer
+            .write(&[
+                ViewEvent::File(write_file("/etc/hosts")),
+                ViewEvent::Operation(read_operation()),
+            ])
+            .await
+            .unwrap();
+        writer.flush().await.unwrap();
+
+        let written = rows("key=WRITE");
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0].path.as_deref(), Some("/etc/hosts"));
+        let read = rows("key=READ");
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].path, None);
+    }
+    #[tokio::test]
+    async fn operation_ending_a_batch_should_be_written_an_interval_later_without_files() {
+        let directory = tempdir().unwrap();
+        let mut settings = ParquetSettings::new(directory.path().to_str().unwrap());
+        settings.flush_interval_ms = 50;
+        let view = ParquetView::new(&settings, &ViewContext::default());
+
+        view.update(read_operation()).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+        let path = directory.path().join("date=2023-10-29").join("key=READ");
+        let read: Vec<_> = std::fs::read_dir(path)
+            .unwrap()
+            .flat_map(|x| read_part(&x.unwrap().path()))
+            .collect();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].path, None);
+    }
+}
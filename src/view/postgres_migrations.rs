@@ -0,0 +1,99 @@
+use colored::Colorize;
+use std::error::Error;
+use tokio_postgres::Client;
+
+/// Key of the advisory lock held while migrating, so agents starting at once migrate one after another.
+const MIGRATION_LOCK: i64 = 0x6c66_7361_7564_6974;
+
+/// The logical schema of the Sqlite store, see `sqlite_migrations`.
+const V1_INITIAL_SCHEMA: &str = r#"
+create table events
+                (
+                    id         BIGSERIAL primary key,
+                    serial     BIGINT not null,
+                    audit_time BIGINT not null,
+                    unique (serial, audit_time)
+                );
+create table operations
+                (
+                    id            BIGSERIAL primary key,
+                    event_id      BIGINT not null references events (id) on delete cascade,
+                    "user"        TEXT not null,
+                    users_group   TEXT not null,
+                    executable    TEXT not null,
+                    command       TEXT not null default '',
+                    syscall       TEXT not null,
+                    operation_key TEXT not null,
+                    unix_observation_time BIGINT
+                );
+create table operated_on_files
+                (
+                    id            BIGSERIAL primary key,
+                    event_id      BIGINT not null references events (id) on delete cascade,
+                    absolute_path TEXT not null,
+                    unix_observation_time BIGINT
+                );
+
+create index events_audit_time on events (audit_time);
+create index operations_event on operations (event_id);
+create index operations_user on operations ("user");
+create index operations_key on operations (operation_key);
+create index files_event on operated_on_files (event_id);
+create index files_path on operated_on_files (absolute_path);
+"#;
+
+/// Ordered migrations, applying the n-th one brings the schema to version n.
+/// Released migrations must never change, new ones are appended.
+const MIGRATIONS: &[&str] = &[V1_INITIAL_SCHEMA];
+
+pub(super) fn latest_version() -> i32 {
+    return MIGRATIONS.len() as i32;
+}
+
+/// Applies every migration newer than the version recorded in `schema_version`, all in one transaction.
+/// Refuses to touch a schema written by a newer version of the application.
+pub(super) async fn migrate(client: &mut Client, schema: &str) -> Result<(), Box<dyn Error>> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+        .await?;
+    transaction
+        .batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS {schema};
+             CREATE TABLE IF NOT EXISTS {schema}.schema_version (version INTEGER not null);",
+            schema = quote_identifier(schema)
+        ))
+        .await?;
+    let current: i32 = transaction
+        .query_one("SELECT coalesce(max(version), 0) FROM schema_version", &[])
+        .await?
+        .get(0);
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than the supported version {}",
+            current,
+            latest_version()
+        )
+        .into());
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        log::info!(
+            "Migrating {} schema to version {}",
+            "PostgreSQL".yellow(),
+            version + 1
+        );
+        transaction.batch_execute(migration).await?;
+    }
+    transaction
+        .batch_execute(&format!(
+            "DELETE FROM schema_version; INSERT INTO schema_version VALUES ({});",
+            current.max(latest_version())
+        ))
+        .await?;
+    transaction.commit().await?;
+    return Ok(());
+}
+
+pub(super) fn quote_identifier(identifier: &str) -> String {
+    return format!("\"{}\"", identifier.replace('"', "\"\""));
+}
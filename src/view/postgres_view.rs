@@ -0,0 +1,365 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::PostgresSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::postgres_migrations::quote_identifier;
+use crate::view::{postgres_migrations, PostgresView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use colored::Colorize;
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod};
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+use tokio_postgres::{Config, NoTls};
+
+const INSERT_EVENTS: &str = "INSERT INTO events (serial, audit_time) \
+     SELECT * FROM unnest($1::BIGINT[], $2::BIGINT[]) ON CONFLICT (serial, audit_time) DO NOTHING";
+const SELECT_EVENTS: &str = "SELECT e.id, e.serial, e.audit_time FROM events e \
+     JOIN unnest($1::BIGINT[], $2::BIGINT[]) AS k (serial, audit_time) \
+     ON e.serial = k.serial AND e.audit_time = k.audit_time";
+const INSERT_OPERATIONS: &str = "INSERT INTO operations \
+     (event_id, \"user\", users_group, executable, command, syscall, operation_key, unix_observation_time) \
+     SELECT * FROM unnest($1::BIGINT[], $2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TEXT[], $8::BIGINT[])";
+const INSERT_FILES: &str =
+    "INSERT INTO operated_on_files (event_id, absolute_path, unix_observation_time) \
+     SELECT * FROM unnest($1::BIGINT[], $2::TEXT[], $3::BIGINT[])";
+
+/// Writes every batch in one transaction, with one multi-row insert per table.
+struct PostgresWriter {
+    pool: Pool,
+}
+
+impl PostgresView {
+    pub(crate) async fn new(settings: &PostgresSettings, context: &ViewContext) -> Self {
+        let pool = Self::open(settings).await.unwrap_or_else(|e| {
+            panic!(
+                "Fatal: could not initiate PostgreSQL schema, check if your chosen database is reachable: {}",
+                e
+            )
+        });
+        let batcher = Batcher::spawn(
+            context,
+            PostgresWriter { pool },
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+
+    async fn open(settings: &PostgresSettings) -> Result<Pool, Box<dyn Error>> {
+        log::info!("Opening a {} connection pool", "PostgreSQL".yellow());
+        let mut config: Config = settings.url.parse()?;
+        config.options(format!(
+            "-csearch_path={}",
+            quote_identifier(&settings.schema)
+        ));
+        let manager = Manager::from_config(
+            config,
+            NoTls,
+            ManagerConfig {
+                recycling_method: RecyclingMethod::Fast,
+            },
+        );
+        let pool = Pool::builder(manager)
+            .max_size(settings.pool_size.max(1))
+            .build()?;
+        log::debug!(
+            "Migrating the {} of {}",
+            "schema".green(),
+            settings.schema.green()
+        );
+        let mut client = pool.get().await?;
+        postgres_migrations::migrate(&mut client, &settings.schema).await?;
+        return Ok(pool);
+    }
+}
+
+#[async_trait]
+impl View for PostgresView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for PostgresWriter {
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| ViewError::from_postgres_pool("Connecting to PostgreSQL", e))?;
+        let transaction = client
+            .transaction()
+            .await
+            .map_err(|e| ViewError::from_postgres("Starting transaction", e))?;
+
+        let (serials, audit_times): (Vec<i64>, Vec<i64>) = events
+            .iter()
+            .map(|event| match event {
+                ViewEvent::Operation(operation) => (operation.serial, operation.audit_time),
+                ViewEvent::File(file) => (file.serial, file.audit_time),
+            })
+            .map(|(serial, audit_time)| (serial as i64, audit_time as i64))
+            .unzip();
+        transaction
+            .execute(INSERT_EVENTS, &[&serials, &audit_times])
+            .await
+            .map_err(|e| ViewError::from_postgres("Inserting events", e))?;
+        let event_ids: HashMap<(i64, i64), i64> = transaction
+            .query(SELECT_EVENTS, &[&serials, &audit_times])
+            .await
+            .map_err(|e| ViewError::from_postgres("Inserting events", e))?
+            .iter()
+            .map(|row| ((row.get(1), row.get(2)), row.get(0)))
+            .collect();
+        let event_id = |serial: u64, audit_time: u64| -> i64 {
+            return event_ids[&(serial as i64, audit_time as i64)];
+        };
+
+        let mut operations = OperationColumns::default();
+        let mut files = FileColumns::default();
+        for event in events {
+            match event {
+                ViewEvent::Operation(operation) => {
+                    operations.push(event_id(operation.serial, operation.audit_time), operation)
+                }
+                ViewEvent::File(file) => files.push(event_id(file.serial, file.audit_time), file),
+            }
+        }
+        if !operations.event_ids.is_empty() {
+            transaction
+                .execute(
+                    INSERT_OPERATIONS,
+                    &[
+                        &operations.event_ids,
+                        &operations.users,
+                        &operations.groups,
+                        &operations.executables,
+                        &operations.commands,
+                        &operations.syscalls,
+                        &operations.keys,
+                        &operations.observation_times,
+                    ],
+                )
+                .await
+                .map_err(|e| ViewError::from_postgres("Inserting operations", e))?;
+        }
+        if !files.event_ids.is_empty() {
+            transaction
+                .execute(
+                    INSERT_FILES,
+                    &[&files.event_ids, &files.paths, &files.observation_times],
+                )
+                .await
+                .map_err(|e| ViewError::from_postgres("Inserting operated on files", e))?;
+        }
+        return transaction
+            .commit()
+            .await
+            .map_err(|e| ViewError::from_postgres("Committing transaction", e));
+    }
+}
+
+/// Operations of a batch, one array per column, as taken apart by `unnest`.
+#[derive(Default)]
+struct OperationColumns {
+    event_ids: Vec<i64>,
+    users: Vec<String>,
+    groups: Vec<String>,
+    executables: Vec<String>,
+    commands: Vec<String>,
+    syscalls: Vec<String>,
+    keys: Vec<String>,
+    observation_times: Vec<Option<i64>>,
+}
+
+impl OperationColumns {
+    fn push(&mut self, event_id: i64, operation: &Operation) {
+        self.event_ids.push(event_id);
+        self.users.push(operation.user.clone());
+        self.groups.push(operation.group.clone());
+        self.executables.push(operation.executable.clone());
+        self.commands.push(operation.command.clone());
+        self.syscalls.push(operation.syscall.clone());
+        self.keys.push(operation.key.to_string());
+        self.observation_times
+            .push(operation.timestamp.parse().ok());
+    }
+}
+
+/// Files of a batch, one array per column, as taken apart by `unnest`.
+#[derive(Default)]
+struct FileColumns {
+    event_ids: Vec<i64>,
+    paths: Vec<String>,
+    observation_times: Vec<Option<i64>>,
+}
+
+impl FileColumns {
+    fn push(&mut self, event_id: i64, file: &FileOperatedOn) {
+        self.event_ids.push(event_id);
+        self.paths.push(file.name.clone());
+        self.observation_times.push(file.timestamp.parse().ok());
+    }
+}
+
+/// Runs against the server in `LINUX_FS_AUDIT_POSTGRES_URL`, by default a local one,
+/// every test in a schema of its own: `cargo test postgres -- --ignored`
+#[cfg(test)]
+mod test {
+    use crate::serializer::{FileOperatedOn, Operation};
+    use crate::settings::PostgresSettings;
+    use crate::test_fixtures::{read_operation, READ_LOG_LINE};
+    use crate::view::postgres_migrations::{latest_version, migrate};
+    use crate::view::{PostgresView, View, ViewContext};
+    use tokio_postgres::{Client, NoTls};
+
+    const DEFAULT_URL: &str = "host=localhost user=postgres";
+    const FILE_LOG_LINE: &str =
+        "type=PATH msg=audit(1698576562.955:570): item=0 name=\"/etc/ssh/sshd_config\" inode=409248";
+
+    fn url() -> String {
+        return std::env::var("LINUX_FS_AUDIT_POSTGRES_URL")
+            .unwrap_or_else(|_| DEFAULT_URL.to_string());
+    }
+
+    /// Settings pointing at a fresh schema, dropped again by [`drop_schema`].
+    async fn settings(schema: &str) -> PostgresSettings {
+        drop_schema(schema).await;
+        let mut settings = PostgresSettings::new(&url());
+        settings.schema = schema.to_string();
+        settings.batch_size = 100;
+        return settings;
+    }
+
+    async fn connect(schema: &str) -> Client {
+        let config = format!("{} options=-csearch_path={}", url(), schema);
+        let (client, connection) = tokio_postgres::connect(&config, NoTls).await.unwrap();
+        tokio::spawn(connection);
+        return client;
+    }
+
+    async fn drop_schema(schema: &str) {
+        connect("public")
+            .await
+            .batch_execute(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+            .await
+            .unwrap();
+    }
+
+    async fn count(client: &Client, table: &str) -> i64 {
+        return client
+            .query_one(&format!("SELECT count(*) FROM {}", table), &[])
+            .await
+            .unwrap()
+            .get(0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server"]
+    async fn files_should_be_linked_to_operation_of_same_event() {
+        let schema = "test_linked_files";
+        let postgres_view =
+            PostgresView::new(&settings(schema).await, &ViewContext::default()).await;
+        let operation = read_operation();
+        let file_of_other_event = FILE_LOG_LINE.replace("1698576562.955:570", "1698576563.000:571");
+
+        postgres_view.update(operation).await.unwrap();
+        for line in [FILE_LOG_LINE.to_string(), file_of_other_event] {
+            for file in FileOperatedOn::new(line, "123".to_string()).unwrap() {
+                postgres_view.report(file).await.unwrap();
+            }
+        }
+        postgres_view.flush().await.unwrap();
+
+        let client = connect(schema).await;
+        let touched_by: Vec<(i64, Option<String>, Option<String>)> = client
+            .query(
+                "SELECT e.serial, o.executable, o.command FROM operated_on_files f \
+                 JOIN events e ON e.id = f.event_id \
+                 LEFT JOIN operations o ON o.event_id = e.id ORDER BY e.serial",
+                &[],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect();
+        assert_eq!(
+            touched_by,
+            vec![
+                (570, Some("/usr/bin/ls".to_string()), Some("ls".to_string())),
+                (571, None, None),
+            ]
+        );
+        assert_eq!(count(&client, "events").await, 2);
+        drop_schema(schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server"]
+    async fn events_over_batch_size_should_be_written_in_several_batches() {
+        let schema = "test_several_batches";
+        let postgres_view =
+            PostgresView::new(&settings(schema).await, &ViewContext::default()).await;
+
+        for serial in 0..250 {
+            let stamp = format!("1698576562.955:{}", serial);
+            let operation = READ_LOG_LINE.replace("1698576562.955:570", &stamp);
+            let file = FILE_LOG_LINE.replace("1698576562.955:570", &stamp);
+            postgres_view
+                .update(Operation::new(operation).unwrap())
+                .await
+                .unwrap();
+            for file in FileOperatedOn::new(file, "123".to_string()).unwrap() {
+                postgres_view.report(file).await.unwrap();
+            }
+        }
+        postgres_view.flush().await.unwrap();
+
+        let client = connect(schema).await;
+        assert_eq!(count(&client, "events").await, 250);
+        assert_eq!(count(&client, "operations").await, 250);
+        assert_eq!(count(&client, "operated_on_files").await, 250);
+        drop_schema(schema).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL server"]
+    async fn schema_should_be_migrated_once_and_newer_one_refused() {
+        let schema = "test_migrations";
+        drop_schema(schema).await;
+        let mut client = connect(schema).await;
+
+        migrate(&mut client, schema).await.unwrap();
+        migrate(&mut client, schema).await.unwrap();
+        let version: i32 = client
+            .query_one("SELECT version FROM schema_version", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(version, latest_version());
+
+        client
+            .execute(
+                "UPDATE schema_version SET version = $1",
+                &[&(latest_version() + 1)],
+            )
+            .await
+            .unwrap();
+        assert!(migrate(&mut client, schema).await.is_err());
+        drop_schema(schema).await;
+    }
+}
@@ -0,0 +1,27 @@
+use std::io;
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects to `address`, giving up after [`CONNECT_TIMEOUT`].
+pub(super) async fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    return tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e))?;
+}
+
+/// A socket bound to any local port of the family of `address`, sending only to its first address.
+pub(super) async fn connect_udp(address: &str) -> io::Result<UdpSocket> {
+    let destination = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No address found"))?;
+    let any = match destination.is_ipv4() {
+        true => "0.0.0.0:0",
+        false => "[::]:0",
+    };
+    let socket = UdpSocket::bind(any).await?;
+    socket.connect(destination).await?;
+    return Ok(socket);
+}
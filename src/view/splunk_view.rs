@@ -0,0 +1,293 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::SplunkSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::event_format::{hostname, JsonLine};
+use crate::view::{SplunkView, View, ViewContext, ViewError, ViewEvent};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::Instant;
+
+const EVENT_PATH: &str = "/services/collector/event";
+const ACK_PATH: &str = "/services/collector/ack";
+const CHANNEL_HEADER: &str = "X-Splunk-Request-Channel";
+const ACK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const MILLIS_PER_SECOND: f64 = 1000.0;
+
+/// Sends batches of events to the HTTP Event Collector, waiting for them to be indexed when a channel is set.
+pub(super) struct SplunkWriter {
+    client: Client,
+    url: String,
+    authorization: String,
+    metadata: Value,
+    channel: Option<String>,
+    ack_timeout: Duration,
+    ack_poll_interval: Duration,
+}
+
+impl SplunkView {
+    pub(crate) fn new(settings: &SplunkSettings, context: &ViewContext) -> Self {
+        let batcher = Batcher::spawn(
+            context,
+            SplunkWriter::new(settings),
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        return Self { batcher };
+    }
+}
+
+#[async_trait]
+impl View for SplunkView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for SplunkWriter {
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let mut body = String::new();
+        for event in events {
+            body.push_str(&self.hec_event(event)?.to_string());
+            body.push('\n');
+        }
+        let url = format!("{}{}", self.url, EVENT_PATH);
+        let response: Value = self
+            .request(&url)
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ViewError::from_http(format!("Posting to {}", url), e))?
+            .json()
+            .await
+            .map_err(|e| ViewError::from_http("Reading collector response", e))?;
+        if self.channel.is_none() {
+            return Ok(());
+        }
+        return match response["ackId"].as_u64() {
+            Some(ack_id) => self.wait_for_ack(ack_id).await,
+            None => Err(ViewError::permanent(
+                "Collector did not return an ackId, check if indexer acknowledgement is enabled for the token",
+            )),
+        };
+    }
+}
+
+impl SplunkWriter {
+    pub(super) fn new(settings: &SplunkSettings) -> Self {
+        let mut metadata = json!({
+            "source": settings.source,
+            "sourcetype": settings.sourcetype,
+        });
+        if let Some(index) = &settings.index {
+            metadata["index"] = json!(index);
+        }
+        if !hostname().is_empty() {
+            metadata["host"] = json!(hostname());
+        }
+        return Self {
+            client: Client::new(),
+            url: settings.url.trim_end_matches('/').to_string(),
+            authorization: format!("Splunk {}", settings.token),
+            metadata,
+            channel: settings.channel.clone(),
+            ack_timeout: Duration::from_millis(settings.ack_timeout_ms),
+            ack_poll_interval: ACK_POLL_INTERVAL,
+        };
+    }
+
+    fn request(&self, url: &str) -> RequestBuilder {
+        let mut request = self
+            .client
+            .post(url)
+            .header(reqwest::header::AUTHORIZATION, &self.authorization);
+        if let Some(channel) = &self.channel {
+            request = request.header(CHANNEL_HEADER, channel);
+        }
+        return request;
+    }
+
+    /// The event with its metadata, the audit timestamp as `time` in seconds.
+    fn hec_event(&self, event: &ViewEvent) -> Result<Value, ViewError> {
+        let (line, audit_time) = match event {
+            ViewEvent::Operation(operation) => {
+                (JsonLine::Operation(operation), operation.audit_time)
+            }
+            ViewEvent::File(file) => (JsonLine::File(file), file.audit_time),
+        };
+        let mut hec_event = self.metadata.clone();
+        hec_event["event"] = serde_json::to_value(&line)
+            .map_err(|e| ViewError::permanent("Serializing event").caused_by(e))?;
+        if audit_time > 0 {
+            hec_event["time"] = json!(audit_time as f64 / MILLIS_PER_SECOND);
+        }
+        return Ok(hec_event);
+    }
+
+    /// Polls until the indexers confirm the batch. Running out of time leaves it to be sent again.
+    async fn wait_for_ack(&self, ack_id: u64) -> Result<(), ViewError> {
+        let url = format!(
+            "{}{}?channel={}",
+            self.url,
+            ACK_PATH,
+            self.channel.as_deref().unwrap_or_default()
+        );
+        let deadline = Instant::now() + self.ack_timeout;
+        loop {
+            let response: Value = self
+                .request(&url)
+                .json(&json!({ "acks": [ack_id] }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| ViewError::from_http(format!("Polling {}", url), e))?
+                .json()
+                .await
+                .map_err(|e| ViewError::from_http("Reading acknowledgement", e))?;
+            if response["acks"][ack_id.to_string()] == Value::Bool(true) {
+                return Ok(());
+            }
+            if Instant::now() + self.ack_poll_interval > deadline {
+                return Err(ViewError::retryable(format!(
+                    "Collector did not acknowledge batch {} in time",
+                    ack_id
+                )));
+            }
+            tokio::time::sleep(self.ack_poll_interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::SplunkSettings;
+    use crate::test_fixtures::{read_file, read_operation};
+    use crate::view::batching::BatchWriter;
+    use crate::view::splunk_view::SplunkWriter;
+    use crate::view::{SplunkView, View, ViewContext, ViewEvent};
+    use mockito::Matcher;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const TOKEN: &str = "8a1f6c2e-3f4b-4c55-9a0e-2d7b8c9e1f00";
+    const CHANNEL: &str = "0aeeac95-ac74-4aa9-b30d-6c4c0ac581ba";
+
+    /// Acknowledges the batch once it was polled for `polls_before_ack` times.
+    async fn acknowledging_server(
+        polls_before_ack: usize,
+    ) -> (mockito::ServerGuard, Arc<AtomicUsize>) {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/services/collector/event")
+            .match_header("x-splunk-request-channel", CHANNEL)
+            .with_body(r#"{"text":"Success","code":0,"ackId":7}"#)
+            .create_async()
+            .await;
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counted = polls.clone();
+        server
+            .mock("POST", "/services/collector/ack")
+            .match_query(Matcher::UrlEncoded("channel".into(), CHANNEL.into()))
+            .match_body(Matcher::Json(json!({ "acks": [7] })))
+            .with_body_from_request(move |_| {
+                let acknowledged = counted.fetch_add(1, Ordering::Relaxed) + 1 > polls_before_ack;
+                return json!({ "acks": { "7": acknowledged } })
+                    .to_string()
+                    .into_bytes();
+            })
+            .create_async()
+            .await;
+        return (server, polls);
+    }
+
+    fn acknowledging_writer(server: &mockito::ServerGuard, ack_timeout_ms: u64) -> SplunkWriter {
+        let mut settings = SplunkSettings::new(&server.url(), TOKEN);
+        settings.channel = Some(CHANNEL.to_string());
+        settings.ack_timeout_ms = ack_timeout_ms;
+        let mut writer = SplunkWriter::new(&settings);
+        writer.ack_poll_interval = Duration::from_millis(10);
+        return writer;
+    }
+
+    #[tokio::test]
+    async fn events_should_be_sent_with_token_metadata_and_audit_time() {
+        let mut server = mockito::Server::new_async().await;
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let received = bodies.clone();
+        let mock = server
+            .mock("POST", "/services/collector/event")
+            .match_header("authorization", format!("Splunk {}", TOKEN).as_str())
+            .with_body_from_request(move |request| {
+                let body = String::from_utf8_lossy(request.body().unwrap()).to_string();
+                received.lock().unwrap().push(body);
+                return br#"{"text":"Success","code":0}"#.to_vec();
+            })
+            .create_async()
+            .await;
+        let mut settings = SplunkSettings::new(&server.url(), TOKEN);
+        settings.index = Some("security".to_string());
+        let splunk_view = SplunkView::new(&settings, &ViewContext::default());
+
+        splunk_view.update(read_operation()).await.unwrap();
+        splunk_view.report(read_file("/etc/shadow")).await.unwrap();
+        splunk_view.flush().await.unwrap();
+
+        mock.assert_async().await;
+        let bodies = bodies.lock().unwrap();
+        let events: Vec<Value> = bodies[0]
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["time"], json!(1698576562.955));
+        assert_eq!(events[0]["index"], "security");
+        assert_eq!(events[0]["source"], "linux-fs-audit");
+        assert_eq!(events[0]["sourcetype"], "linux-fs-audit:json");
+        assert_eq!(events[0]["event"]["event"], "operation");
+        assert_eq!(events[0]["event"]["executable"], "/usr/bin/ls");
+        assert_eq!(events[1]["event"]["name"], "/etc/shadow");
+    }
+
+    #[tokio::test]
+    async fn batch_should_be_written_once_acknowledged() {
+        let (server, polls) = acknowledging_server(2).await;
+        let mut writer = acknowledging_writer(&server, 5000);
+
+        writer
+            .write(&[ViewEvent::File(read_file("/etc/shadow"))])
+            .await
+            .unwrap();
+
+        assert_eq!(polls.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn batch_not_acknowledged_in_time_should_be_retryable() {
+        let (server, _) = acknowledging_server(usize::MAX).await;
+        let mut writer = acknowledging_writer(&server, 50);
+
+        let error = writer
+            .write(&[ViewEvent::File(read_file("/etc/shadow"))])
+            .await
+            .unwrap_err();
+
+        assert!(error.is_retryable());
+    }
+}
@@ -0,0 +1,386 @@
+use colored::Colorize;
+use rusqlite::Connection;
+use std::error::Error;
+
+/// Schema of 2.0.1, which did not keep a version yet. Databases created by it are at version 0
+/// and get this migration applied as a no-op.
+const V1_INITIAL_SCHEMA: &str = r#"
+create table IF NOT EXISTS operations
+                (
+                    user          TEXT not null,
+                    users_group   TEXT not null,
+                    executable    TEXT not null,
+                    syscall       TEXT not null,
+                    operation_key TEXT not null,
+                    unix_observation_time INTEGER
+                );
+create table IF NOT EXISTS operated_on_files
+                (
+                    absolute_path TEXT not null,
+                    unix_observation_time INTEGER
+                );
+"#;
+
+/// Every audit event is identified by its serial and time, operations and the files
+/// they touched point at the event they belong to. Rows written before events were recorded
+/// get serial 0 and are grouped by their observation time, which is how they used to be joined.
+const V2_EVENTS: &str = r#"
+create table events
+                (
+                    id         INTEGER primary key,
+                    serial     INTEGER not null,
+                    audit_time INTEGER not null,
+                    unique (serial, audit_time)
+                );
+insert into events (serial, audit_time)
+    select distinct 0, coalesce(unix_observation_time, 0) * 1000 from operations
+    union
+    select distinct 0, coalesce(unix_observation_time, 0) * 1000 from operated_on_files;
+
+create table operations_with_events
+                (
+                    event_id      INTEGER not null references events (id) on delete cascade,
+                    user          TEXT not null,
+                    users_group   TEXT not null,
+                    executable    TEXT not null,
+                    syscall       TEXT not null,
+                    operation_key TEXT not null,
+                    unix_observation_time INTEGER
+                );
+insert into operations_with_events
+    select e.id, o.user, o.users_group, o.executable, o.syscall, o.operation_key, o.unix_observation_time
+    from operations o
+    join events e on e.serial = 0 and e.audit_time = coalesce(o.unix_observation_time, 0) * 1000;
+drop table operations;
+alter table operations_with_events rename to operations;
+
+create table operated_on_files_with_events
+                (
+                    event_id      INTEGER not null references events (id) on delete cascade,
+                    absolute_path TEXT not null,
+                    unix_observation_time INTEGER
+                );
+insert into operated_on_files_with_events
+    select e.id, f.absolute_path, f.unix_observation_time
+    from operated_on_files f
+    join events e on e.serial = 0 and e.audit_time = coalesce(f.unix_observation_time, 0) * 1000;
+drop table operated_on_files;
+alter table operated_on_files_with_events rename to operated_on_files;
+
+create index events_audit_time on events (audit_time);
+create index operations_event on operations (event_id);
+create index operations_user on operations (user);
+create index operations_key on operations (operation_key);
+create index files_event on operated_on_files (event_id);
+create index files_path on operated_on_files (absolute_path);
+"#;
+
+/// Databases which already link rows to events without a version were written by the release
+/// which introduced the events table, before migrations, and have the schema of version 2.
+const UNVERSIONED_EVENTS_VERSION: u32 = 2;
+const HAS_EVENTS_TABLE: &str =
+    "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'events'";
+
+/// Operations keep the command line they were run with. Operations and files get ids that survive
+/// a vacuum, which the full-text indexes of paths, executables and command lines point at.
+/// The trigram tokenizer lets any part of at least three characters match, regardless of case.
+const V3_SEARCH: &str = r#"
+create table operations_with_ids
+                (
+                    id            INTEGER primary key,
+                    event_id      INTEGER not null references events (id) on delete cascade,
+                    user          TEXT not null,
+                    users_group   TEXT not null,
+                    executable    TEXT not null,
+                    command       TEXT not null default '',
+                    syscall       TEXT not null,
+                    operation_key TEXT not null,
+                    unix_observation_time INTEGER
+                );
+insert into operations_with_ids
+    (id, event_id, user, users_group, executable, syscall, operation_key, unix_observation_time)
+    select rowid, event_id, user, users_group, executable, syscall, operation_key, unix_observation_time
+    from operations;
+drop table operations;
+alter table operations_with_ids rename to operations;
+
+create table operated_on_files_with_ids
+                (
+                    id            INTEGER primary key,
+                    event_id      INTEGER not null references events (id) on delete cascade,
+                    absolute_path TEXT not null,
+                    unix_observation_time INTEGER
+                );
+insert into operated_on_files_with_ids (id, event_id, absolute_path, unix_observation_time)
+    select rowid, event_id, absolute_path, unix_observation_time from operated_on_files;
+drop table operated_on_files;
+alter table operated_on_files_with_ids rename to operated_on_files;
+
+create index operations_event on operations (event_id);
+create index operations_user on operations (user);
+create index operations_key on operations (operation_key);
+create index files_event on operated_on_files (event_id);
+create index files_path on operated_on_files (absolute_path);
+
+create virtual table file_search using fts5
+    (absolute_path, content = 'operated_on_files', content_rowid = 'id', tokenize = 'trigram');
+create virtual table operation_search using fts5
+    (executable, command, content = 'operations', content_rowid = 'id', tokenize = 'trigram');
+insert into file_search (file_search) values ('rebuild');
+insert into operation_search (operation_search) values ('rebuild');
+
+create trigger file_search_insert after insert on operated_on_files begin
+    insert into file_search (rowid, absolute_path) values (new.id, new.absolute_path);
+end;
+create trigger file_search_delete after delete on operated_on_files begin
+    insert into file_search (file_search, rowid, absolute_path)
+        values ('delete', old.id, old.absolute_path);
+end;
+create trigger file_search_update after update on operated_on_files begin
+    insert into file_search (file_search, rowid, absolute_path)
+        values ('delete', old.id, old.absolute_path);
+    insert into file_search (rowid, absolute_path) values (new.id, new.absolute_path);
+end;
+create trigger operation_search_insert after insert on operations begin
+    insert into operation_search (rowid, executable, command)
+        values (new.id, new.executable, new.command);
+end;
+create trigger operation_search_delete after delete on operations begin
+    insert into operation_search (operation_search, rowid, executable, command)
+        values ('delete', old.id, old.executable, old.command);
+end;
+create trigger operation_search_update after update on operations begin
+    insert into operation_search (operation_search, rowid, executable, command)
+        values ('delete', old.id, old.executable, old.command);
+    insert into operation_search (rowid, executable, command)
+        values (new.id, new.executable, new.command);
+end;
+"#;
+
+/// File accesses counted per minute and per hour, by key, user, executable and top-level directory,
+/// with `bucket` being the start of the period in milliseconds. Files directly under the root count
+/// towards `/`, relative paths towards `.`. The counts are kept up to date on insert and are not
+/// touched when the events they were counted from are pruned.
+const V4_ROLLUPS: &str = r#"
+create table rollups_minute
+                (
+                    bucket        INTEGER not null,
+                    operation_key TEXT not null,
+                    user          TEXT not null,
+                    executable    TEXT not null,
+                    directory     TEXT not null,
+                    accesses      INTEGER not null,
+                    primary key (bucket, operation_key, user, executable, directory)
+                ) without rowid;
+create table rollups_hour
+                (
+                    bucket        INTEGER not null,
+                    operation_key TEXT not null,
+                    user          TEXT not null,
+                    executable    TEXT not null,
+                    directory     TEXT not null,
+                    accesses      INTEGER not null,
+                    primary key (bucket, operation_key, user, executable, directory)
+                ) without rowid;
+
+create view file_accesses as
+    select e.audit_time, o.operation_key, o.user, o.executable, f.id as file_id,
+           case
+               when f.absolute_path not like '/%' then '.'
+               when instr(substr(f.absolute_path, 2), '/') = 0 then '/'
+               else substr(f.absolute_path, 1, instr(substr(f.absolute_path, 2), '/'))
+           end as directory
+    from operated_on_files f
+    join events e on e.id = f.event_id
+    join operations o on o.event_id = f.event_id;
+
+insert into rollups_minute
+    select audit_time / 60000 * 60000, operation_key, user, executable, directory, count(*)
+    from file_accesses group by 1, 2, 3, 4, 5;
+insert into rollups_hour
+    select audit_time / 3600000 * 3600000, operation_key, user, executable, directory, count(*)
+    from file_accesses group by 1, 2, 3, 4, 5;
+
+create trigger rollups_file_insert after insert on operated_on_files begin
+    insert into rollups_minute
+        select audit_time / 60000 * 60000, operation_key, user, executable, directory, 1
+        from file_accesses where file_id = new.id
+        on conflict do update set accesses = accesses + 1;
+    insert into rollups_hour
+        select audit_time / 3600000 * 3600000, operation_key, user, executable, directory, 1
+        from file_accesses where file_id = new.id
+        on conflict do update set accesses = accesses + 1;
+end;
+"#;
+
+/// Files may be inserted before the operation of their event, which the trigger on files cannot count yet.
+/// Every pair of file and operation is now counted by whichever of them is inserted second.
+const V5_ROLLUPS_OF_EARLY_FILES: &str = r#"
+drop trigger rollups_file_insert;
+drop view file_accesses;
+
+create view file_accesses as
+    select e.audit_time, o.operation_key, o.user, o.executable, f.id as file_id, o.id as operation_id,
+           case
+               when f.absolute_path not like '/%' then '.'
+               when instr(substr(f.absolute_path, 2), '/') = 0 then '/'
+               else substr(f.absolute_path, 1, instr(substr(f.absolute_path, 2), '/'))
+           end as directory
+    from operated_on_files f
+    join events e on e.id = f.event_id
+    join operations o on o.event_id = f.event_id;
+
+create trigger rollups_file_insert after insert on operated_on_files begin
+    insert into rollups_minute
+        select audit_time / 60000 * 60000, operation_key, user, executable, directory, 1
+        from file_accesses where file_id = new.id
+        on conflict do update set accesses = accesses + 1;
+    insert into rollups_hour
+        select audit_time / 3600000 * 3600000, operation_key, user, executable, directory, 1
+        from file_accesses where file_id = new.id
+        on conflict do update set accesses = accesses + 1;
+end;
+
+create trigger rollups_operation_insert after insert on operations begin
+    insert into rollups_minute
+        select audit_time / 60000 * 60000, operation_key, user, executable, directory, count(*)
+        from file_accesses where operation_id = new.id group by 1, 2, 3, 4, 5
+        on conflict do update set accesses = accesses + excluded.accesses;
+    insert into rollups_hour
+        select audit_time / 3600000 * 3600000, operation_key, user, executable, directory, count(*)
+        from file_accesses where operation_id = new.id group by 1, 2, 3, 4, 5
+        on conflict do update set accesses = accesses + excluded.accesses;
+end;
+"#;
+
+/// Ordered migrations, applying the n-th one brings the schema to version n.
+/// Released migrations must never change, new ones are appended.
+const MIGRATIONS: &[&str] = &[
+    V1_INITIAL_SCHEMA,
+    V2_EVENTS,
+    V3_SEARCH,
+    V4_ROLLUPS,
+    V5_ROLLUPS_OF_EARLY_FILES,
+];
+
+pub(super) fn latest_version() -> u32 {
+    return MIGRATIONS.len() as u32;
+}
+
+/// Applies every migration newer than the `user_version` of the database, each in its own transaction.
+/// Refuses to touch a database written by a newer version of the application.
+pub(super) fn migrate(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    let mut current: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let has_events: i64 = conn.query_row(HAS_EVENTS_TABLE, [], |row| row.get(0))?;
+    if current == 0 && has_events > 0 {
+        conn.pragma_update(None, "user_version", UNVERSIONED_EVENTS_VERSION)?;
+        current = UNVERSIONED_EVENTS_VERSION;
+    }
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {} is newer than the supported version {}",
+            current,
+            latest_version()
+        )
+        .into());
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = version + 1;
+        log::info!(
+            "Migrating {} schema to version {}",
+            "Sqlite".yellow(),
+            version
+        );
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", version)?;
+        transaction.commit()?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::view::sqlite_migrations::{latest_version, migrate, V1_INITIAL_SCHEMA, V2_EVENTS};
+    use rusqlite::Connection;
+
+    const RELEASE_2_0_1_FIXTURE: &str =
+        include_str!("../../test_resources/sqlite_fixtures/2.0.1.sql");
+
+    fn user_version(conn: &Connection) -> u32 {
+        return conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+    }
+
+    #[test]
+    fn empty_database_should_be_migrated_to_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), latest_version());
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn), latest_version());
+    }
+
+    #[test]
+    fn database_of_release_2_0_1_should_be_upgraded_keeping_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(RELEASE_2_0_1_FIXTURE).unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(user_version(&conn), latest_version());
+        let touched_by: Vec<(String, String)> = conn
+            .prepare(
+                "SELECT f.absolute_path, o.user FROM operated_on_files f \
+                 JOIN operations o ON o.event_id = f.event_id ORDER BY f.absolute_path",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(
+            touched_by,
+            vec![
+                ("/etc/passwd".to_string(), "root".to_string()),
+                ("/etc/shadow".to_string(), "maciek".to_string()),
+            ]
+        );
+        let operations: i64 = conn
+            .query_row("SELECT count(*) FROM operations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(operations, 3);
+        let found: String = conn
+            .query_row(
+                "SELECT f.absolute_path FROM file_search s \
+                 JOIN operated_on_files f ON f.id = s.rowid WHERE file_search MATCH 'shad'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(found, "/etc/shadow");
+    }
+
+    #[test]
+    fn unversioned_database_with_events_should_continue_from_version_2() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(V1_INITIAL_SCHEMA).unwrap();
+        conn.execute_batch(V2_EVENTS).unwrap();
+        assert_eq!(user_version(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(user_version(&conn), latest_version());
+    }
+
+    #[test]
+    fn newer_database_should_be_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+        assert_eq!(user_version(&conn), latest_version() + 1);
+    }
+}
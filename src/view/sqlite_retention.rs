@@ -0,0 +1,465 @@
+use crate::settings::RetentionSettings;
+use colored::Colorize;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const SELECT_OLDEST: &str = "SELECT id FROM events ORDER BY audit_time, id LIMIT ?1";
+const SELECT_OLDER_THAN: &str =
+    "SELECT id FROM events WHERE audit_time < ?1 ORDER BY audit_time, id LIMIT ?2";
+const DELETE_EVENTS: &str = "DELETE FROM events WHERE id IN (SELECT value FROM json_each(?1))";
+const DELETE_MINUTE_ROLLUPS: &str = "DELETE FROM rollups_minute WHERE bucket < ?1";
+const DELETE_HOUR_ROLLUPS: &str = "DELETE FROM rollups_hour WHERE bucket < ?1";
+const SELECT_ARCHIVED_EVENTS: &str =
+    "SELECT id, serial, audit_time FROM events WHERE id IN (SELECT value FROM json_each(?1))";
+const SELECT_ARCHIVED_OPERATIONS: &str = "SELECT event_id, user, users_group, executable, syscall, operation_key, unix_observation_time, command FROM operations WHERE event_id IN (SELECT value FROM json_each(?1))";
+const SELECT_ARCHIVED_FILES: &str = "SELECT event_id, absolute_path, unix_observation_time FROM operated_on_files WHERE event_id IN (SELECT value FROM json_each(?1))";
+/// Deleted rows leave markers in the full-text indexes, which only go away with the segments
+/// they are merged into. A negative budget merges whatever segments there are, so it runs after deleting, not per batch.
+const MERGE_SEARCH_INDEXES: &str = r#"
+insert into file_search (file_search, rank) values ('merge', -64);
+insert into operation_search (operation_search, rank) values ('merge', -64);
+"#;
+
+/// Deletes the oldest events of the Sqlite store, with their operations and files,
+/// until it is within the configured limits again.
+pub(super) struct Pruner {
+    connection: Connection,
+    settings: RetentionSettings,
+}
+
+impl Pruner {
+    pub(super) fn open(
+        db_path: &str,
+        settings: &RetentionSettings,
+    ) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(db_path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        return Ok(Self {
+            connection,
+            settings: settings.clone(),
+        });
+    }
+
+    /// Space of deleted rows is only given back to the file system with incremental vacuum enabled,
+    /// which an existing database only picks up after a full vacuum.
+    pub(super) fn enable_incremental_vacuum(connection: &Connection) -> Result<(), Box<dyn Error>> {
+        let auto_vacuum: u32 =
+            connection.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+        if auto_vacuum != 2 {
+            log::info!("Enabling incremental vacuum of {}", "Sqlite".yellow());
+            connection.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+            connection.execute_batch("VACUUM")?;
+        }
+        return Ok(());
+    }
+
+    pub(super) fn spawn(mut self) {
+        let interval = Duration::from_secs(self.settings.interval_seconds.max(1));
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_millis() as u64)
+                .unwrap_or(0);
+            match self.prune(now) {
+                Ok(0) => log::debug!("No events to prune"),
+                Ok(pruned) => log::info!("Pruned {} events", pruned.to_string().green()),
+                Err(e) => log::error!("{} {}", "Error: pruning Sqlite failed:".red(), e),
+            }
+            match self.prune_rollups(now) {
+                Ok(0) => log::debug!("No rollups to prune"),
+                Ok(pruned) => log::info!("Pruned {} rollups", pruned.to_string().green()),
+                Err(e) => log::error!("{} {}", "Error: pruning Sqlite rollups failed:".red(), e),
+            }
+        });
+    }
+
+    /// Prunes events older than the maximum age, then the oldest ones over the row limit,
+    /// then the oldest ones while the live data is over the size limit. Returns the number of deleted events.
+    pub(super) fn prune(&mut self, now_millis: u64) -> Result<usize, Box<dyn Error>> {
+        let batch_size = self.settings.batch_size.max(1) as i64;
+        let archive =
+            self.settings.archive_directory.as_ref().map(|directory| {
+                Path::new(directory).join(format!("pruned-{}.jsonl.gz", now_millis))
+            });
+        let mut pruned = 0;
+        if let Some(max_age_seconds) = self.settings.max_age_seconds {
+            let cutoff = now_millis.saturating_sub(max_age_seconds.saturating_mul(1000)) as i64;
+            loop {
+                let ids = self.select_ids(SELECT_OLDER_THAN, params![cutoff, batch_size])?;
+                if ids.is_empty() {
+                    break;
+                }
+                pruned += self.delete(&ids, archive.as_deref())?;
+            }
+        }
+        if let Some(max_rows) = self.settings.max_rows {
+            loop {
+                let rows: u64 =
+                    self.connection
+                        .query_row("SELECT count(*) FROM events", [], |row| row.get(0))?;
+                if rows <= max_rows {
+                    break;
+                }
+                let over_limit = batch_size.min((rows - max_rows) as i64);
+                let ids = self.select_ids(SELECT_OLDEST, params![over_limit])?;
+                pruned += self.delete(&ids, archive.as_deref())?;
+            }
+        }
+        if pruned > 0 {
+            self.reclaim()?;
+        }
+        if let Some(max_file_size_bytes) = self.settings.max_file_size_bytes {
+            // The file only shrinks once deleted rows are merged out of the search indexes,
+            // so here every batch is reclaimed before measuring again.
+            while self.used_bytes()? > max_file_size_bytes {
+                let ids = self.select_ids(SELECT_OLDEST, params![batch_size])?;
+                if ids.is_empty() {
+                    break;
+                }
+                pruned += self.delete(&ids, archive.as_deref())?;
+                self.reclaim()?;
+            }
+        }
+        return Ok(pruned);
+    }
+
+    /// Deletes the rollups of periods that started before their maximum age. Returns the number of deleted rows.
+    pub(super) fn prune_rollups(&mut self, now_millis: u64) -> rusqlite::Result<usize> {
+        let rollups = match &self.settings.rollups {
+            Some(rollups) => rollups.clone(),
+            None => return Ok(0),
+        };
+        let limits = [
+            (DELETE_MINUTE_ROLLUPS, rollups.minute_max_age_seconds),
+            (DELETE_HOUR_ROLLUPS, rollups.hour_max_age_seconds),
+        ];
+        let mut pruned = 0;
+        for (delete, max_age_seconds) in limits {
+            if let Some(max_age_seconds) = max_age_seconds {
+                let cutoff = now_millis.saturating_sub(max_age_seconds.saturating_mul(1000)) as i64;
+                pruned += self.connection.execute(delete, [cutoff])?;
+            }
+        }
+        return Ok(pruned);
+    }
+
+    fn select_ids(
+        &self,
+        query: &str,
+        parameters: impl rusqlite::Params,
+    ) -> rusqlite::Result<Vec<i64>> {
+        return self
+            .connection
+            .prepare_cached(query)?
+            .query_map(parameters, |row| row.get(0))?
+            .collect();
+    }
+
+    fn used_bytes(&self) -> rusqlite::Result<u64> {
+        let page_size: u64 = self
+            .connection
+            .pragma_query_value(None, "page_size", |row| row.get(0))?;
+        let page_count: u64 = self
+            .connection
+            .pragma_query_value(None, "page_count", |row| row.get(0))?;
+        let free_pages: u64 =
+            self.connection
+                .pragma_query_value(None, "freelist_count", |row| row.get(0))?;
+        return Ok((page_count - free_pages) * page_size);
+    }
+
+    fn delete(&mut self, ids: &[i64], archive: Option<&Path>) -> Result<usize, Box<dyn Error>> {
+        let ids = serde_json::to_string(ids)?;
+        let transaction = self.connection.transaction()?;
+        if let Some(archive) = archive {
+            append_to_archive(archive, &export(&transaction, &ids)?)?;
+        }
+        let deleted = transaction.execute(DELETE_EVENTS, [&ids])?;
+        transaction.commit()?;
+        return Ok(deleted);
+    }
+
+    /// Merges the search indexes and gives the free pages back to the file system.
+    /// Every step of the pragma gives back a single page, it has to run to completion.
+    fn reclaim(&self) -> rusqlite::Result<()> {
+        self.connection.execute_batch(MERGE_SEARCH_INDEXES)?;
+        let mut statement = self
+            .connection
+            .prepare_cached("PRAGMA incremental_vacuum")?;
+        let mut rows = statement.query([])?;
+        while rows.next()?.is_some() {}
+        return Ok(());
+    }
+}
+
+/// Every event becomes a json object holding its operations and files.
+fn export(connection: &Connection, ids: &str) -> rusqlite::Result<Vec<Value>> {
+    let mut events: BTreeMap<i64, Value> = BTreeMap::new();
+    let mut statement = connection.prepare(SELECT_ARCHIVED_EVENTS)?;
+    let mut rows = statement.query([ids])?;
+    while let Some(row) = rows.next()? {
+        events.insert(
+            row.get(0)?,
+            json!({
+                "serial": row.get::<_, i64>(1)?,
+                "audit_time": row.get::<_, i64>(2)?,
+                "operations": [],
+                "files": [],
+            }),
+        );
+    }
+    let mut statement = connection.prepare(SELECT_ARCHIVED_OPERATIONS)?;
+    let mut rows = statement.query([ids])?;
+    while let Some(row) = rows.next()? {
+        let operation = json!({
+            "user": row.get::<_, String>(1)?,
+            "group": row.get::<_, String>(2)?,
+            "executable": row.get::<_, String>(3)?,
+            "syscall": row.get::<_, String>(4)?,
+            "key": row.get::<_, String>(5)?,
+            "timestamp": row.get::<_, Option<i64>>(6)?,
+            "command": row.get::<_, String>(7)?,
+        });
+        if let Some(event) = events.get_mut(&row.get(0)?) {
+            event["operations"].as_array_mut().unwrap().push(operation);
+        }
+    }
+    let mut statement = connection.prepare(SELECT_ARCHIVED_FILES)?;
+    let mut rows = statement.query([ids])?;
+    while let Some(row) = rows.next()? {
+        let file = json!({
+            "name": row.get::<_, String>(1)?,
+            "timestamp": row.get::<_, Option<i64>>(2)?,
+        });
+        if let Some(event) = events.get_mut(&row.get(0)?) {
+            event["files"].as_array_mut().unwrap().push(file);
+        }
+    }
+    let mut events: Vec<Value> = events.into_values().collect();
+    events.sort_by_key(|event| event["audit_time"].as_i64());
+    return Ok(events);
+}
+
+/// Every batch is appended as a gzip member of its own, so an interrupted run leaves a readable archive.
+fn append_to_archive(archive: &Path, events: &[Value]) -> Result<(), Box<dyn Error>> {
+    if let Some(directory) = archive.parent() {
+        create_dir_all(directory)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(archive)?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    for event in events {
+        serde_json::to_writer(&mut encoder, event)?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder.finish()?.sync_all()?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{RetentionSettings, RollupRetentionSettings};
+    use crate::view::sqlite_migrations::migrate;
+    use crate::view::sqlite_retention::Pruner;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    const DAY_MILLIS: u64 = 86_400_000;
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn retention(max_age_seconds: Option<u64>, max_rows: Option<u64>) -> RetentionSettings {
+        return RetentionSettings {
+            max_age_seconds,
+            max_rows,
+            max_file_size_bytes: None,
+            batch_size: 2,
+            interval_seconds: 300,
+            archive_directory: None,
+            rollups: None,
+        };
+    }
+
+    /// One event a day for the last `days` days, each with an operation and a file.
+    fn database_with_events(db_path: &str, days: u64) {
+        let mut conn = rusqlite::Connection::open(db_path).unwrap();
+        migrate(&mut conn).unwrap();
+        for day in 0..days {
+            let audit_time = NOW - day * DAY_MILLIS;
+            conn.execute(
+                "INSERT INTO events (serial, audit_time) VALUES (?1, ?2)",
+                [day, audit_time],
+            )
+            .unwrap();
+            let id = conn.last_insert_rowid();
+            conn.execute(
+                "INSERT INTO operations (event_id, user, users_group, executable, command, syscall, operation_key, unix_observation_time) \
+                 VALUES (?1, 'root', 'root', '/usr/bin/vim', 'vim /etc/hosts', 'unknown', 'WRITE', ?2)",
+                rusqlite::params![id, audit_time / 1000],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO operated_on_files (event_id, absolute_path, unix_observation_time) VALUES (?1, ?2, ?3)",
+                rusqlite::params![id, format!("/etc/day{}", day), audit_time / 1000],
+            )
+            .unwrap();
+        }
+    }
+
+    fn count(db_path: &str, table: &str) -> i64 {
+        return rusqlite::Connection::open(db_path)
+            .unwrap()
+            .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn events_older_than_max_age_should_be_pruned_with_their_rows() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        database_with_events(db_path, 10);
+
+        let mut pruner = Pruner::open(db_path, &retention(Some(3 * 86_400 + 1), None)).unwrap();
+        let pruned = pruner.prune(NOW).unwrap();
+
+        assert_eq!(pruned, 6);
+        assert_eq!(count(db_path, "events"), 4);
+        assert_eq!(count(db_path, "operations"), 4);
+        assert_eq!(count(db_path, "operated_on_files"), 4);
+        assert_eq!(
+            count(db_path, "file_search WHERE file_search MATCH 'day'"),
+            4
+        );
+        assert_eq!(
+            count(
+                db_path,
+                "operation_search WHERE operation_search MATCH 'vim'"
+            ),
+            4
+        );
+    }
+
+    #[test]
+    fn max_age_past_any_time_should_keep_every_event() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        database_with_events(db_path, 3);
+
+        let mut pruner = Pruner::open(db_path, &retention(Some(u64::MAX), None)).unwrap();
+        let pruned = pruner.prune(NOW).unwrap();
+
+        assert_eq!(pruned, 0);
+        assert_eq!(count(db_path, "events"), 3);
+    }
+
+    #[test]
+    fn rollups_should_outlive_events_and_be_pruned_by_their_own_limits() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        database_with_events(db_path, 10);
+        let mut settings = retention(Some(86_400), None);
+        settings.rollups = Some(RollupRetentionSettings {
+            minute_max_age_seconds: Some(2 * 86_400),
+            hour_max_age_seconds: Some(5 * 86_400),
+        });
+
+        let mut pruner = Pruner::open(db_path, &settings).unwrap();
+        pruner.prune(NOW).unwrap();
+        assert_eq!(count(db_path, "events"), 2);
+        assert_eq!(count(db_path, "rollups_minute"), 10);
+        let pruned = pruner.prune_rollups(NOW).unwrap();
+
+        assert_eq!(pruned, 8 + 5);
+        assert_eq!(count(db_path, "rollups_minute"), 2);
+        assert_eq!(count(db_path, "rollups_hour"), 5);
+    }
+
+    #[test]
+    fn oldest_events_over_max_rows_should_be_pruned() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        database_with_events(db_path, 7);
+
+        let mut pruner = Pruner::open(db_path, &retention(None, Some(2))).unwrap();
+        assert_eq!(pruner.prune(NOW).unwrap(), 5);
+
+        let newest: u64 = rusqlite::Connection::open(db_path)
+            .unwrap()
+            .query_row("SELECT min(audit_time) FROM events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(newest, NOW - DAY_MILLIS);
+    }
+
+    #[test]
+    fn events_over_max_file_size_should_be_pruned_and_space_reclaimed() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        database_with_events(db_path, 2000);
+        let mut settings = retention(None, None);
+        settings.batch_size = 100;
+
+        let mut pruner = Pruner::open(db_path, &settings).unwrap();
+        Pruner::enable_incremental_vacuum(&pruner.connection).unwrap();
+        let max_file_size_bytes = pruner.used_bytes().unwrap() / 2;
+        pruner.settings.max_file_size_bytes = Some(max_file_size_bytes);
+        let pages_before: u64 = pruner
+            .connection
+            .pragma_query_value(None, "page_count", |row| row.get(0))
+            .unwrap();
+        pruner.prune(NOW).unwrap();
+        let pages_after: u64 = pruner
+            .connection
+            .pragma_query_value(None, "page_count", |row| row.get(0))
+            .unwrap();
+
+        assert!(pruner.used_bytes().unwrap() <= max_file_size_bytes);
+        assert!(count(db_path, "events") > 0);
+        assert!(pages_after < pages_before);
+    }
+
+    #[test]
+    fn pruned_events_should_be_archived_before_deletion() {
+        let directory = tempdir().unwrap();
+        let db_path = directory.path().join("test.sqlite");
+        let db_path = db_path.to_str().unwrap();
+        let archive_directory = directory.path().join("archive");
+        database_with_events(db_path, 5);
+        let mut settings = retention(None, Some(1));
+        settings.archive_directory = Some(archive_directory.to_str().unwrap().to_string());
+
+        let mut pruner = Pruner::open(db_path, &settings).unwrap();
+        pruner.prune(NOW).unwrap();
+
+        let archive = archive_directory.join(format!("pruned-{}.jsonl.gz", NOW));
+        let mut archived = String::new();
+        MultiGzDecoder::new(std::fs::File::open(archive).unwrap())
+            .read_to_string(&mut archived)
+            .unwrap();
+        let events: Vec<serde_json::Value> = archived
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["audit_time"], NOW - 4 * DAY_MILLIS);
+        assert_eq!(events[0]["operations"][0]["executable"], "/usr/bin/vim");
+        assert_eq!(events[0]["files"][0]["name"], "/etc/day4");
+    }
+}
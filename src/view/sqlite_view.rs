@@ -1,115 +1,477 @@
 use crate::serializer::{FileOperatedOn, Operation};
-use crate::view::{SqliteView, View};
+use crate::settings::SqliteSettings;
+use crate::view::batching::{BatchWriter, Batcher};
+use crate::view::sqlite_retention::Pruner;
+use crate::view::{sqlite_migrations, SqliteView, View, ViewContext, ViewError, ViewEvent};
 use async_trait::async_trait;
 use colored::Colorize;
-use rusqlite::params;
+use rusqlite::{params, Connection};
 use std::error::Error;
-use tokio_rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const INSERT_EVENT: &str =
+    "INSERT INTO events (serial, audit_time) VALUES (?1,?2) ON CONFLICT (serial, audit_time) DO NOTHING";
+const SELECT_EVENT: &str = "SELECT id FROM events WHERE serial = ?1 AND audit_time = ?2";
+const INSERT_OPERATION: &str = "INSERT INTO operations (event_id,user,users_group,executable,command,syscall,operation_key,unix_observation_time) VALUES (?1,?2,?3,?4,?5,?6,?7,?8)";
+
+const INSERT_FILE: &str =
+    "INSERT INTO operated_on_files (event_id, absolute_path, unix_observation_time) VALUES (?1,?2,?3)";
+
+/// Owns the only connection to the database and inserts every batch in one transaction.
+struct SqliteWriter {
+    connection: Arc<Mutex<Connection>>,
+}
 
-const OPERATIONS_SCHEMA: &str = r#"
-create table IF NOT EXISTS operations
-                (
-                    user          TEXT not null,
-                    users_group   TEXT not null,
-                    executable    TEXT not null,
-                    syscall       TEXT not null,
-                    operation_key TEXT not null,
-                    unix_observation_time INTEGER
-                );
-"#;
-const FILES_SCHEMA: &str = r#"
-create table IF NOT EXISTS operated_on_files
-                (
-                    absolute_path TEXT not null,
-                    unix_observation_time INTEGER
-                );
-"#;
-const INSERT_OPERATION: &'static str = "INSERT INTO operations (user,users_group,executable,syscall,operation_key,unix_observation_time) VALUES (?1,?2,?3,?4,?5,?6)";
-
-const INSERT_FILE: &'static str =
-    "INSERT INTO operated_on_files (absolute_path, unix_observation_time) VALUES (?1,?2)";
 impl SqliteView {
-    pub(crate) fn new(db_path: &str) -> Self {
-        log::debug!("Script ran: {}", OPERATIONS_SCHEMA);
-        Self::create_schema_if_not_present(db_path)
-            .expect("Fatal: could not initiate schema, check if your chosen database exists.");
-        return Self {
-            db_path: db_path.to_string(),
-        };
+    pub(crate) fn new(settings: &SqliteSettings, context: &ViewContext) -> Self {
+        let connection = Self::open(settings).unwrap_or_else(|e| {
+            panic!(
+                "Fatal: could not initiate schema, check if your chosen database exists: {}",
+                e
+            )
+        });
+        let batcher = Batcher::spawn(
+            context,
+            SqliteWriter {
+                connection: Arc::new(Mutex::new(connection)),
+            },
+            settings.batch_size,
+            Duration::from_millis(settings.flush_interval_ms),
+        );
+        if let Some(retention) = &settings.retention {
+            Pruner::open(&settings.db_path, retention)
+                .unwrap_or_else(|e| panic!("Fatal: could not open Sqlite for pruning: {}", e))
+                .spawn();
+        }
+        return Self { batcher };
     }
 
-    fn create_schema_if_not_present(db_path: &str) -> Result<(), Box<dyn Error>> {
+    fn open(settings: &SqliteSettings) -> Result<Connection, Box<dyn Error>> {
         log::info!("Opening an {} connection", "Sqlite".yellow());
-        let conn = rusqlite::Connection::open(db_path)?;
-        log::debug!("Injecting a {} to {}", "schema".green(), db_path.green());
-        let _ = conn.execute(OPERATIONS_SCHEMA, [])?;
-        let _ = conn.execute(FILES_SCHEMA, [])?;
-        return Ok(());
+        let mut conn = Connection::open(&settings.db_path)?;
+        let journal_mode: String =
+            conn.pragma_update_and_check(None, "journal_mode", &settings.journal_mode, |row| {
+                row.get(0)
+            })?;
+        conn.pragma_update(None, "synchronous", settings.synchronous.to_string())?;
+        log::debug!(
+            "Journal mode {}, synchronous {}",
+            journal_mode.green(),
+            settings.synchronous.to_string().green()
+        );
+        log::debug!(
+            "Migrating the {} of {}",
+            "schema".green(),
+            settings.db_path.green()
+        );
+        sqlite_migrations::migrate(&mut conn)?;
+        if settings.retention.is_some() {
+            Pruner::enable_incremental_vacuum(&conn)?;
+        }
+        conn.pragma_update(None, "foreign_keys", true)?;
+        return Ok(conn);
     }
 }
 
 #[async_trait]
 impl View for SqliteView {
-    async fn update(&self, operation: Operation) -> Result<(), ()> {
-        log::info!("Opening an {} connection", "Sqlite".yellow());
-        let db_connection = Connection::open(self.db_path.clone()).await.unwrap();
-        log::info!(
-            "Inserting {}",
-            serde_json::to_string(&operation).unwrap().green()
-        );
-        db_connection
-            .call(move |conn| {
-                conn.execute(
-                    INSERT_OPERATION,
-                    params![
-                        operation.user,
-                        operation.group,
-                        operation.executable,
-                        operation.syscall,
-                        operation.key.to_string(),
-                        operation.timestamp
-                    ],
-                )
-            })
-            .await
-            .expect("Failed to insert operation data to the database");
-        Ok(())
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::Operation(operation));
     }
 
-    async fn report(&self, files: FileOperatedOn) -> Result<(), ()> {
-        log::info!("Opening an {} connection", "Sqlite".yellow());
-        let db_connection = Connection::open(self.db_path.clone()).await.unwrap();
-
-        db_connection
-            .clone()
-            .call(move |conn| {
-                conn.execute(
-                    INSERT_FILE,
-                    params![files.name.clone(), files.timestamp.clone()],
-                )
-            })
-            .await
-            .expect("Failed to insert operation data to the database");
-        Ok(())
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        return self.batcher.enqueue(ViewEvent::File(files));
+    }
+
+    /// Commits every event queued so far, waiting for the writer to finish.
+    async fn flush(&self) -> Result<(), ViewError> {
+        return self.batcher.flush().await;
+    }
+
+    fn renders_in_background(&self) -> bool {
+        return true;
+    }
+}
+
+#[async_trait]
+impl BatchWriter for SqliteWriter {
+    /// Sqlite blocks while inserting, so the transaction runs on a thread of its own.
+    async fn write(&mut self, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let connection = self.connection.clone();
+        let events = events.to_vec();
+        return tokio::task::spawn_blocking(move || {
+            Self::insert_all(&mut connection.lock().unwrap(), &events)
+        })
+        .await
+        .map_err(|e| ViewError::permanent("Sqlite writer stopped").caused_by(e))?;
+    }
+}
+
+impl SqliteWriter {
+    fn insert_all(connection: &mut Connection, events: &[ViewEvent]) -> Result<(), ViewError> {
+        let transaction = connection
+            .transaction()
+            .map_err(|e| ViewError::from_sqlite("Starting transaction", e))?;
+        {
+            let mut insert_event = transaction
+                .prepare_cached(INSERT_EVENT)
+                .map_err(|e| ViewError::from_sqlite("Inserting event", e))?;
+            let mut select_event = transaction
+                .prepare_cached(SELECT_EVENT)
+                .map_err(|e| ViewError::from_sqlite("Inserting event", e))?;
+            let mut insert_operation = transaction
+                .prepare_cached(INSERT_OPERATION)
+                .map_err(|e| ViewError::from_sqlite("Inserting operation", e))?;
+            let mut insert_file = transaction
+                .prepare_cached(INSERT_FILE)
+                .map_err(|e| ViewError::from_sqlite("Inserting operated on file", e))?;
+            for event in events {
+                let (serial, audit_time) = match event {
+                    ViewEvent::Operation(operation) => (operation.serial, operation.audit_time),
+                    ViewEvent::File(file) => (file.serial, file.audit_time),
+                };
+                insert_event
+                    .execute(params![serial, audit_time])
+                    .map_err(|e| ViewError::from_sqlite("Inserting event", e))?;
+                let event_id: i64 = select_event
+                    .query_row(params![serial, audit_time], |row| row.get(0))
+                    .map_err(|e| ViewError::from_sqlite("Inserting event", e))?;
+                match event {
+                    ViewEvent::Operation(operation) => insert_operation
+                        .execute(params![
+                            event_id,
+                            operation.user,
+                            operation.group,
+                            operation.executable,
+                            operation.command,
+                            operation.syscall,
+                            operation.key.to_string(),
+                            operation.timestamp
+                        ])
+                        .map_err(|e| ViewError::from_sqlite("Inserting operation", e))?,
+                    ViewEvent::File(file) => insert_file
+                        .execute(params![event_id, file.name, file.timestamp])
+                        .map_err(|e| ViewError::from_sqlite("Inserting operated on file", e))?,
+                };
+            }
+        }
+        return transaction
+            .commit()
+            .map_err(|e| ViewError::from_sqlite("Committing transaction", e));
     }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::admin::health;
     use crate::serializer::{FileOperatedOn, Operation, OperationKey};
-    use crate::view::{SqliteView, View};
-    use futures::executor;
+    use crate::settings::{SqliteSettings, SqliteSynchronous};
+    use crate::view::{SqliteView, View, ViewContext};
+    use std::time::{Duration, Instant};
     use tempfile::tempdir;
 
     const DB_FILE_NAME: &str = "test.sqlite";
     const COMPLIANT_LOG_LINE: &str = "type=SYSCALL msg=audit(1698576562.955:570): arch=c000003e syscall=257 success=yes exit=3 a0=ffffff9c a1=55a917750550 a2=90800 a3=0 items=1 ppid=20120 pid=20680 auid=1000 uid=1000 gid=1000 euid=1000 suid=1000 fsuid=1000 egid=1000 sgid=1000 fsgid=1000 tty=pts2 ses=14 comm=\"ls\" exe=\"/usr/bin/ls\" subj=unconfined key=\"READ\"ARCH=x86_64 AUID=\"maciek\" UID=\"maciek\" GID=\"maciek\" EUID=\"maciek\" SUID=\"maciek\" FSUID=\"maciek\" EGID=\"maciek\" SGID=\"maciek\"";
     const FILE_LOG_LINE: &str = "type=PATH msg=audit(1364481363.243:24287): item=0 name=\"/etc/ssh/sshd_config\" inode=409248 dev=fd:00 mode=0100600 ouid=0 ogid=0 rdev=00:00 obj=system_u:object_r:etc_t:s0  objtype=NORMAL cap_fp=none cap_fi=none cap_fe=0 cap_fver=0";
-    #[test]
-    fn if_file_has_been_operated_on_check_persistence() {
+    #[tokio::test]
+    async fn if_insert_fails_error_should_be_returned_instead_of_panic() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .execute("DROP TABLE operations", [])
+            .unwrap();
+
+        sqlite_view
+            .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        let result = sqlite_view.flush().await;
+        assert!(result.is_err());
+        assert!(!result.unwrap_err().is_retryable());
+    }
+
+    #[tokio::test]
+    async fn failed_commit_should_spend_error_budget_of_view() {
         let temporary_sqlite_directory = tempdir().unwrap();
         let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
-        let sqlite_view = SqliteView::new(db_path.to_str().unwrap());
-        insert_test_values(sqlite_view);
+        let context = ViewContext {
+            view_name: "sqlite-health-test".to_string(),
+            error_budget: 1,
+            ..Default::default()
+        };
+        let sqlite_view =
+            SqliteView::new(&SqliteSettings::new(db_path.to_str().unwrap()), &context);
+        let writer = || {
+            let readiness = serde_json::to_value(health().readiness()).unwrap();
+            return readiness["writers"]["sqlite-health-test"].clone();
+        };
+
+        sqlite_view
+            .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        sqlite_view.flush().await.unwrap();
+        rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .execute("DROP TABLE operations", [])
+            .unwrap();
+        for _ in 0..2 {
+            sqlite_view
+                .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+                .await
+                .unwrap();
+            assert!(sqlite_view.flush().await.is_err());
+        }
+
+        assert_eq!(writer()["delivered"], 1);
+        assert_eq!(writer()["failed"], 2);
+        assert_eq!(writer()["error_budget"], 1);
+        assert_eq!(writer()["ready"], false);
+    }
+
+    #[tokio::test]
+    async fn full_batch_should_be_committed_without_waiting_for_interval() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let mut settings = SqliteSettings::new(db_path.to_str().unwrap());
+        settings.batch_size = 2;
+        settings.flush_interval_ms = 3_600_000;
+        let sqlite_view = SqliteView::new(&settings, &ViewContext::default());
+
+        for _ in 0..3 {
+            sqlite_view
+                .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(count_operations(&db_path), 2);
+        sqlite_view.flush().await.unwrap();
+        assert_eq!(count_operations(&db_path), 3);
+    }
+
+    #[tokio::test]
+    async fn files_should_be_linked_to_operation_of_same_event() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        let operation = Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap();
+        let file_of_operation = FILE_LOG_LINE.replace("1364481363.243:24287", "1698576562.955:570");
+        let file_of_other_event = FILE_LOG_LINE.replace("sshd_config", "ssh_config");
+
+        sqlite_view.update(operation).await.unwrap();
+        for line in [file_of_operation, file_of_other_event] {
+            for file in FileOperatedOn::new(line, "123".to_string()).unwrap() {
+                sqlite_view.report(file).await.unwrap();
+            }
+        }
+        sqlite_view.flush().await.unwrap();
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let touched_by: Vec<(String, String)> = conn
+            .prepare(
+                "SELECT f.absolute_path, o.executable FROM operated_on_files f \
+                 JOIN events e ON e.id = f.event_id \
+                 LEFT JOIN operations o ON o.event_id = e.id ORDER BY f.absolute_path",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                ))
+            })
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(
+            touched_by,
+            vec![
+                ("/etc/ssh/ssh_config".to_string(), "".to_string()),
+                (
+                    "/etc/ssh/sshd_config".to_string(),
+                    "/usr/bin/ls".to_string()
+                ),
+            ]
+        );
+        let events: i64 = conn
+            .query_row("SELECT count(*) FROM events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(events, 2);
+    }
+
+    #[tokio::test]
+    async fn database_of_release_2_0_1_should_keep_its_rows_and_take_new_events() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .execute_batch(include_str!(
+                "../../test_resources/sqlite_fixtures/2.0.1.sql"
+            ))
+            .unwrap();
+
+        let sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        sqlite_view
+            .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        sqlite_view.flush().await.unwrap();
+
+        assert_eq!(count_operations(&db_path), 4);
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let shadow_read_by: String = conn
+            .query_row(
+                "SELECT o.executable FROM operated_on_files f \
+                 JOIN operations o ON o.event_id = f.event_id WHERE f.absolute_path = '/etc/shadow'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(shadow_read_by, "/usr/bin/cat");
+    }
+
+    #[tokio::test]
+    async fn file_accesses_should_be_counted_in_rollups_by_top_level_directory() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        let stamps = [
+            "1698576562.955:570",
+            "1698576570.000:571",
+            "1698580000.000:572",
+        ];
+        let paths = ["/etc/ssh/sshd_config", "/etc/hosts", "/vmlinuz"];
+        for (i, (stamp, path)) in stamps.iter().zip(paths).enumerate() {
+            let operation = COMPLIANT_LOG_LINE.replace("1698576562.955:570", stamp);
+            let file = FILE_LOG_LINE
+                .replace("1364481363.243:24287", stamp)
+                .replace("/etc/ssh/sshd_config", path);
+            let files = FileOperatedOn::new(file, "123".to_string()).unwrap();
+            // The file of the last event comes before its operation.
+            let file_first = i == stamps.len() - 1;
+            if file_first {
+                sqlite_view.report(files[0].clone()).await.unwrap();
+            }
+            sqlite_view
+                .update(Operation::new(operation).unwrap())
+                .await
+                .unwrap();
+            if !file_first {
+                sqlite_view.report(files[0].clone()).await.unwrap();
+            }
+        }
+        sqlite_view.flush().await.unwrap();
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        let rollups = |table: &str| -> Vec<(i64, String, i64)> {
+            return conn
+                .prepare(&format!(
+                    "SELECT bucket, directory, accesses FROM {} \
+                     WHERE operation_key = 'READ' AND user = 'maciek' AND executable = '/usr/bin/ls' \
+                     ORDER BY bucket, directory",
+                    table
+                ))
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .map(|x| x.unwrap())
+                .collect();
+        };
+        assert_eq!(
+            rollups("rollups_minute"),
+            vec![
+                (1698576540000, "/etc".to_string(), 2),
+                (1698579960000, "/".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            rollups("rollups_hour"),
+            vec![
+                (1698573600000, "/etc".to_string(), 2),
+                (1698577200000, "/".to_string(), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_should_use_configured_journal_mode() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let _sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        let journal_mode: String = rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "wal");
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture benchmark`.
+    #[tokio::test]
+    #[ignore]
+    async fn benchmark_insert_throughput() {
+        const EVENTS: usize = 50_000;
+        for (batch_size, synchronous) in [
+            (1, SqliteSynchronous::Full),
+            (1, SqliteSynchronous::Normal),
+            (500, SqliteSynchronous::Normal),
+            (5000, SqliteSynchronous::Normal),
+        ] {
+            let temporary_sqlite_directory = tempdir().unwrap();
+            let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+            let mut settings = SqliteSettings::new(db_path.to_str().unwrap());
+            settings.batch_size = batch_size;
+            settings.synchronous = synchronous;
+            let sqlite_view = SqliteView::new(&settings, &ViewContext::default());
+            let operation = Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap();
+
+            let started = Instant::now();
+            for _ in 0..EVENTS {
+                while sqlite_view.update(operation.clone()).await.is_err() {
+                    tokio::task::yield_now().await;
+                }
+            }
+            sqlite_view.flush().await.unwrap();
+            let elapsed = started.elapsed();
+
+            assert_eq!(count_operations(&db_path), EVENTS as i64);
+            println!(
+                "batch size {:>5}, synchronous {:>6}: {:>8.0} events/s",
+                batch_size,
+                synchronous.to_string(),
+                EVENTS as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn if_file_has_been_operated_on_check_persistence() {
+        let temporary_sqlite_directory = tempdir().unwrap();
+        let db_path = temporary_sqlite_directory.path().join(DB_FILE_NAME);
+        let sqlite_view = SqliteView::new(
+            &SqliteSettings::new(db_path.to_str().unwrap()),
+            &ViewContext::default(),
+        );
+        insert_test_values(sqlite_view).await;
         assert_one_entry_is_present_and_has_values_the_same_as_parsed_operation(
             db_path.clone().into_os_string().into_string().unwrap(),
         );
@@ -118,20 +480,30 @@ mod test {
         );
     }
 
-    fn insert_test_values(sqlite_view: SqliteView) {
-        executor::block_on(
-            sqlite_view.update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap()),
-        )
-        .unwrap();
-        executor::block_on(
-            sqlite_view.report(
+    #[allow(clippy::get_first)]
+    async fn insert_test_values(sqlite_view: SqliteView) {
+        sqlite_view
+            .update(Operation::new(COMPLIANT_LOG_LINE.to_string()).unwrap())
+            .await
+            .unwrap();
+        sqlite_view
+            .report(
                 FileOperatedOn::new(FILE_LOG_LINE.to_string(), 1701533809.to_string())
                     .unwrap()
                     .get(0)
                     .unwrap()
                     .clone(),
-            ),
-        ).unwrap();
+            )
+            .await
+            .unwrap();
+        sqlite_view.flush().await.unwrap();
+    }
+
+    fn count_operations(db_path: &std::path::Path) -> i64 {
+        return rusqlite::Connection::open(db_path)
+            .unwrap()
+            .query_row("SELECT count(*) FROM operations", [], |row| row.get(0))
+            .unwrap();
     }
 
     fn assert_one_entry_is_present_and_has_values_the_same_as_parsed_operation(db_path: String) {
@@ -141,10 +513,12 @@ mod test {
         assert_eq!(expected.key, result.key);
         assert_eq!(expected.syscall, result.syscall);
         assert_eq!(expected.executable, result.executable);
+        assert_eq!(expected.command, result.command);
         assert_eq!(expected.user, result.user);
         assert_eq!(expected.group, result.group);
     }
 
+    #[allow(clippy::get_first)]
     fn assert_one_entry_is_present_and_has_values_the_same_as_parsed_file_operated_on(
         db_path: String,
     ) {
@@ -158,7 +532,7 @@ mod test {
     fn get_last_entry_from_db(db_path: String) -> Result<Operation, Box<dyn std::error::Error>> {
         let conn = rusqlite::Connection::open(db_path).unwrap();
         let mut stmt = conn.prepare(
-            "SELECT user, users_group, executable, syscall, operation_key, unix_observation_time FROM operations",
+            "SELECT user, users_group, executable, syscall, operation_key, command FROM operations",
         )?;
         let operations_iter = stmt.query_map([], |row| {
             Ok(Operation {
@@ -167,7 +541,12 @@ mod test {
                 executable: row.get(2)?,
                 syscall: row.get(3)?,
                 timestamp: row.get(4)?,
+                command: row.get(5)?,
+                pid: None,
                 key: OperationKey::READ,
+                success: true,
+                serial: 0,
+                audit_time: 0,
             })
         })?;
         let result = operations_iter.last().unwrap()?;
@@ -182,7 +561,9 @@ mod test {
         let operations_iter = stmt.query_map([], |row| {
             Ok(FileOperatedOn {
                 name: row.get(1)?,
-                timestamp: 1701533809.to_string()
+                timestamp: 1701533809.to_string(),
+                serial: 0,
+                audit_time: 0,
             })
         })?;
         let result = operations_iter.last().unwrap()?;
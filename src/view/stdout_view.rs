@@ -0,0 +1,42 @@
+use crate::serializer::{FileOperatedOn, Operation};
+use crate::settings::StdoutSettings;
+use crate::view::event_format::{format_file, format_operation, to_json, JsonLine};
+use crate::view::{StdoutView, View, ViewError};
+use async_trait::async_trait;
+use std::io::Write;
+
+impl StdoutView {
+    pub(crate) fn new(settings: &StdoutSettings) -> Self {
+        return Self {
+            event_format: settings.event_format,
+            last_operation: Default::default(),
+        };
+    }
+
+    fn print(&self, line: String) -> Result<(), ViewError> {
+        let mut stdout = std::io::stdout().lock();
+        return writeln!(stdout, "{}", line)
+            .and_then(|_| stdout.flush())
+            .map_err(|e| ViewError::from_io("Printing event", e));
+    }
+}
+
+#[async_trait]
+impl View for StdoutView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        self.last_operation.remember(&operation);
+        let line = match self.event_format {
+            Some(format) => format_operation(format, &operation),
+            None => to_json(JsonLine::Operation(&operation))?,
+        };
+        return self.print(line);
+    }
+
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        let line = match self.event_format {
+            Some(format) => format_file(format, &files, self.last_operation.of(&files).as_ref()),
+            None => to_json(JsonLine::File(&files))?,
+        };
+        return self.print(line);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::serializer::{FileOperatedOn, Operation, OperationKey};
+use crate::settings::{SyslogFormat, SyslogSettings, SyslogTransport};
+use crate::view::event_format::{format_file, format_operation, hostname};
+use crate::view::socket::{connect_tcp, connect_udp};
+use crate::view::{SyslogView, View, ViewError};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use std::io;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket, UnixDatagram};
+
+/// Structured data id of RFC 5424 messages, under the enterprise number reserved for documentation.
+const STRUCTURED_DATA_ID: &str = "fsaudit@32473";
+/// Stands in for a missing header field of RFC 5424 messages.
+const NIL: &str = "-";
+
+/// An event on its way to syslog, before it is laid out in one of the formats.
+pub(super) struct Message {
+    pub(super) severity: u8,
+    pub(super) message_id: &'static str,
+    pub(super) text: String,
+    pub(super) parameters: Vec<(&'static str, String)>,
+    pub(super) audit_time: u64,
+}
+
+impl Message {
+    /// Sends `text` on its own, without the structured data or parameters.
+    fn replace_text(&mut self, text: String) {
+        self.text = text;
+        self.parameters.clear();
+    }
+}
+
+/// The socket of the configured transport, connected on first use and again after it failed.
+pub(super) struct Connection {
+    transport: SyslogTransport,
+    address: String,
+    socket: Option<Socket>,
+}
+
+enum Socket {
+    Local(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl SyslogView {
+    pub(crate) fn new(settings: &SyslogSettings) -> Self {
+        return Self {
+            settings: settings.clone(),
+            hostname: hostname().to_string(),
+            connection: tokio::sync::Mutex::new(Connection {
+                transport: settings.transport,
+                address: settings.address.clone(),
+                socket: None,
+            }),
+            last_operation: Default::default(),
+        };
+    }
+
+    fn severity_of(&self, key: &OperationKey) -> u8 {
+        return match key {
+            OperationKey::READ => self.settings.read_severity as u8,
+            OperationKey::WRITE => self.settings.write_severity as u8,
+        };
+    }
+
+    async fn send(&self, message: Message) -> Result<(), ViewError> {
+        let formatted = match self.settings.format {
+            SyslogFormat::Rfc5424 => format_rfc5424(&self.settings, &self.hostname, &message),
+            SyslogFormat::Rfc3164 => format_rfc3164(&self.settings, &self.hostname, &message),
+        };
+        return self
+            .connection
+            .lock()
+            .await
+            .send(formatted.as_bytes())
+            .await
+            .map_err(|e| ViewError::from_io(format!("Sending to {}", self.settings.address), e));
+    }
+}
+
+#[async_trait]
+impl View for SyslogView {
+    async fn update(&self, operation: Operation) -> Result<(), ViewError> {
+        let severity = self.severity_of(&operation.key);
+        self.last_operation.remember(&operation);
+        let mut message = operation_message(&operation, severity);
+        if let Some(format) = self.settings.event_format {
+            message.replace_text(format_operation(format, &operation));
+        }
+        return self.send(message).await;
+    }
+
+    /// Files are sent with the severity of the operation that touched them.
+    async fn report(&self, files: FileOperatedOn) -> Result<(), ViewError> {
+        let operation = self.last_operation.of(&files);
+        let severity = match &operation {
+            Some(operation) => self.severity_of(&operation.key),
+            None => self.settings.read_severity as u8,
+        };
+        let key = operation.as_ref().map(|x| x.key.clone());
+        let mut message = file_message(&files, key, severity);
+        if let Some(format) = self.settings.event_format {
+            message.replace_text(format_file(format, &files, operation.as_ref()));
+        }
+        return self.send(message).await;
+    }
+}
+
+impl Connection {
+    async fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        if self.socket.is_none() {
+            self.socket = Some(self.connect().await?);
+        }
+        let result = match self.socket.as_mut() {
+            Some(Socket::Local(socket)) => socket.send_to(message, &self.address).await.map(|_| ()),
+            Some(Socket::Udp(socket)) => socket.send(message).await.map(|_| ()),
+            Some(Socket::Tcp(stream)) => {
+                let framed = [format!("{} ", message.len()).as_bytes(), message].concat();
+                stream.write_all(&framed).await
+            }
+            None => Ok(()),
+        };
+        if result.is_err() {
+            self.socket = None;
+        }
+        return result;
+    }
+
+    async fn connect(&self) -> io::Result<Socket> {
+        return match self.transport {
+            SyslogTransport::Local => Ok(Socket::Local(UnixDatagram::unbound()?)),
+            SyslogTransport::Udp => Ok(Socket::Udp(connect_udp(&self.address).await?)),
+            SyslogTransport::Tcp => Ok(Socket::Tcp(connect_tcp(&self.address).await?)),
+        };
+    }
+}
+
+pub(super) fn operation_message(operation: &Operation, severity: u8) -> Message {
+    return Message {
+        severity,
+        message_id: "operation",
+        text: format!(
+            "{} {} {}",
+            operation.user, operation.key, operation.executable
+        ),
+        parameters: vec![
+            ("serial", operation.serial.to_string()),
+            ("user", operation.user.clone()),
+            ("group", operation.group.clone()),
+            ("executable", operation.executable.clone()),
+            ("command", operation.command.clone()),
+            ("syscall", operation.syscall.clone()),
+            ("key", operation.key.to_string()),
+            ("success", operation.success.to_string()),
+        ],
+        audit_time: operation.audit_time,
+    };
+}
+
+pub(super) fn file_message(
+    file: &FileOperatedOn,
+    key: Option<OperationKey>,
+    severity: u8,
+) -> Message {
+    let text = match &key {
+        Some(key) => format!("{} {}", key, file.name),
+        None => file.name.clone(),
+    };
+    return Message {
+        severity,
+        message_id: "file",
+        text,
+        parameters: vec![
+            ("serial", file.serial.to_string()),
+            ("path", file.name.clone()),
+        ],
+        audit_time: file.audit_time,
+    };
+}
+
+fn priority(settings: &SyslogSettings, severity: u8) -> u8 {
+    return settings.facility as u8 * 8 + severity;
+}
+
+fn time_of(message: &Message) -> DateTime<Utc> {
+    return DateTime::from_timestamp_millis(message.audit_time as i64)
+        .filter(|_| message.audit_time > 0)
+        .unwrap_or_else(Utc::now);
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD-ID param="value" ...] MSG`
+pub(super) fn format_rfc5424(
+    settings: &SyslogSettings,
+    hostname: &str,
+    message: &Message,
+) -> String {
+    let parameters: Vec<String> = message
+        .parameters
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_parameter(value)))
+        .collect();
+    let structured_data = match parameters.is_empty() {
+        true => NIL.to_string(),
+        false => format!("[{} {}]", STRUCTURED_DATA_ID, parameters.join(" ")),
+    };
+    return format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        priority(settings, message.severity),
+        time_of(message).to_rfc3339_opts(SecondsFormat::Millis, true),
+        header_field(hostname),
+        header_field(&settings.app_name),
+        std::process::id(),
+        message.message_id,
+        structured_data,
+        message.text
+    );
+}
+
+/// `<PRI>Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG param="value" ...`, in local time as BSD syslog has no zone.
+pub(super) fn format_rfc3164(
+    settings: &SyslogSettings,
+    hostname: &str,
+    message: &Message,
+) -> String {
+    let parameters: Vec<String> = message
+        .parameters
+        .iter()
+        .map(|(name, value)| format!(" {}={}", name, snailquote::escape(value)))
+        .collect();
+    let hostname = match hostname.is_empty() {
+        true => "localhost",
+        false => hostname,
+    };
+    return format!(
+        "<{}>{} {} {}[{}]: {}{}",
+        priority(settings, message.severity),
+        time_of(message)
+            .with_timezone(&Local)
+            .format("%b %e %H:%M:%S"),
+        hostname,
+        settings.app_name,
+        std::process::id(),
+        message.text,
+        parameters.concat()
+    );
+}
+
+/// Header fields are printable ascii without spaces, the nil value when there is nothing left.
+fn header_field(value: &str) -> String {
+    let field: String = value.chars().filter(|x| x.is_ascii_graphic()).collect();
+    return match field.is_empty() {
+        true => NIL.to_string(),
+        false => field,
+    };
+}
+
+/// Parameter values may contain anything but an unescaped `"`, `\` or `]`.
+pub(super) fn escape_parameter(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for x in value.chars() {
+        if matches!(x, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(x);
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod test {
+    use crate::settings::{EventFormat, SyslogFormat, SyslogSettings, SyslogTransport};
+    use crate::test_fixtures::{read_file, write_file, write_operation};
+    use crate::view::syslog_view::escape_parameter;
+    use crate::view::{SyslogView, View};
+    use tempfile::tempdir;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, UdpSocket, UnixDatagram};
+
+    async fn send_both(syslog_view: &SyslogView) {
+        syslog_view.update(write_operation()).await.unwrap();
+        syslog_view.report(write_file("/etc/shadow")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn udp_messages_should_follow_rfc5424_with_severity_of_key() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let syslog_view = SyslogView::new(&SyslogSettings::new(SyslogTransport::Udp, &address));
+
+        send_both(&syslog_view).await;
+
+        let mut buffer = [0u8; 2048];
+        let length = collector.recv(&mut buffer).await.unwrap();
+        let operation = String::from_utf8_lossy(&buffer[..length]).to_string();
+        let length = collector.recv(&mut buffer).await.unwrap();
+        let file = String::from_utf8_lossy(&buffer[..length]).to_string();
+        // auth facility (4) * 8 + notice (5)
+        assert!(operation.starts_with("<37>1 2023-10-29T10:49:23.120Z "));
+        assert!(operation.contains(" linux-fs-audit "));
+        assert!(operation.contains(" operation [fsaudit@32473 serial=\"571\" user=\"root\""));
+        assert!(operation.contains("key=\"WRITE\" success=\"true\"] root WRITE /usr/bin/vi"));
+        assert!(file.starts_with("<37>1 "));
+        assert!(file.ends_with(
+            " file [fsaudit@32473 serial=\"571\" path=\"/etc/shadow\"] WRITE /etc/shadow"
+        ));
+    }
+
+    #[tokio::test]
+    async fn tcp_messages_should_be_octet_counted() {
+        let collector = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut settings = SyslogSettings::new(SyslogTransport::Tcp, &address);
+        settings.format = SyslogFormat::Rfc3164;
+        let syslog_view = SyslogView::new(&settings);
+
+        send_both(&syslog_view).await;
+        drop(syslog_view);
+
+        let (mut stream, _) = collector.accept().await.unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        let mut frames = Vec::new();
+        let mut rest = received.as_str();
+        while let Some((length, message)) = rest.split_once(' ') {
+            let length: usize = length.parse().unwrap();
+            frames.push(message[..length].to_string());
+            rest = &message[length..];
+        }
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].starts_with("<37>Oct "));
+        assert!(frames[0].contains(" linux-fs-audit["));
+        assert!(frames[0].contains("]: root WRITE /usr/bin/vi serial=571 user=root"));
+        assert!(frames[1].ends_with("]: WRITE /etc/shadow serial=571 path=/etc/shadow"));
+    }
+
+    #[tokio::test]
+    async fn local_messages_should_be_sent_to_socket_path() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("log");
+        let collector = UnixDatagram::bind(&path).unwrap();
+        let settings = SyslogSettings::new(SyslogTransport::Local, path.to_str().unwrap());
+        let syslog_view = SyslogView::new(&settings);
+
+        syslog_view.report(read_file("/etc/shadow")).await.unwrap();
+
+        let mut buffer = [0u8; 2048];
+        let length = collector.recv(&mut buffer).await.unwrap();
+        let message = String::from_utf8_lossy(&buffer[..length]).to_string();
+        // auth facility (4) * 8 + info (6), no operation to take the severity from
+        assert!(message.starts_with("<38>1 "));
+        assert!(message.ends_with("] /etc/shadow"));
+    }
+
+    #[tokio::test]
+    async fn event_format_should_replace_message_and_structured_data() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let mut settings = SyslogSettings::new(SyslogTransport::Udp, &address);
+        settings.event_format = Some(EventFormat::Cef);
+        let syslog_view = SyslogView::new(&settings);
+
+        send_both(&syslog_view).await;
+
+        let mut buffer = [0u8; 2048];
+        collector.recv(&mut buffer).await.unwrap();
+        let length = collector.recv(&mut buffer).await.unwrap();
+        let file = String::from_utf8_lossy(&buffer[..length]).to_string();
+        assert!(file.starts_with("<37>1 "));
+        assert!(file.contains(" file - CEF:0|linux-fs-audit|"));
+        assert!(file.contains("|WRITE|WRITE file|5|"));
+        assert!(file.ends_with(" filePath=/etc/shadow fname=shadow"));
+    }
+
+    #[test]
+    fn parameter_values_should_escape_closing_characters() {
+        assert_eq!(
+            escape_parameter("sh -c \"cat [a]\\b\""),
+            "sh -c \\\"cat [a\\]\\\\b\\\""
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use crate::view::{ViewError, ViewErrorKind};
+use rusqlite::ErrorCode;
+use std::error::Error;
+use std::fmt;
+use std::io::ErrorKind;
+
+impl ViewError {
+    pub(crate) fn retryable(context: impl Into<String>) -> Self {
+        return Self {
+            kind: ViewErrorKind::Retryable,
+            context: context.into(),
+            source: None,
+        };
+    }
+
+    pub(crate) fn permanent(context: impl Into<String>) -> Self {
+        return Self {
+            kind: ViewErrorKind::Permanent,
+            context: context.into(),
+            source: None,
+        };
+    }
+
+    pub(crate) fn caused_by(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        return self;
+    }
+
+    pub(crate) fn is_retryable(&self) -> bool {
+        return self.kind == ViewErrorKind::Retryable;
+    }
+
+    /// Busy or locked databases and a full disk may clear up on their own, anything else,
+    /// like a broken schema or a violated constraint, will fail the same way again.
+    pub(crate) fn from_sqlite(context: impl Into<String>, error: rusqlite::Error) -> Self {
+        let retryable = matches!(
+            error.sqlite_error_code(),
+            Some(ErrorCode::DatabaseBusy)
+                | Some(ErrorCode::DatabaseLocked)
+                | Some(ErrorCode::DiskFull)
+                | Some(ErrorCode::SystemIoFailure)
+                | Some(ErrorCode::OutOfMemory)
+        );
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(error);
+    }
+
+    /// A full disk, an interrupted write or a lost connection may clear up, missing permissions
+    /// or a missing directory will not.
+    pub(crate) fn from_io(context: impl Into<String>, error: std::io::Error) -> Self {
+        let retryable = matches!(
+            error.kind(),
+            ErrorKind::StorageFull
+                | ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::TimedOut
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+        );
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(error);
+    }
+
+    /// Lost connections, conflicts between transactions and a server short of resources or shutting down
+    /// may clear up, a statement the server rejected will fail the same way again.
+    pub(crate) fn from_postgres(context: impl Into<String>, error: tokio_postgres::Error) -> Self {
+        let retryable = match error.code() {
+            Some(state) => matches!(&state.code()[..2], "08" | "40" | "53" | "57"),
+            None => {
+                error.is_closed()
+                    || error
+                        .source()
+                        .is_some_and(|source| source.is::<std::io::Error>())
+            }
+        };
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(error);
+    }
+
+    /// Waiting too long for a connection is worth another try, errors of the server are judged
+    /// like those of a connection.
+    pub(crate) fn from_postgres_pool(
+        context: impl Into<String>,
+        error: deadpool_postgres::PoolError,
+    ) -> Self {
+        return match error {
+            deadpool_postgres::PoolError::Backend(error) => Self::from_postgres(context, error),
+            deadpool_postgres::PoolError::Timeout(_) => Self::retryable(context).caused_by(error),
+            error => Self::permanent(context).caused_by(error),
+        };
+    }
+
+    /// Unreachable brokers and the errors Kafka itself calls retriable, like a partition
+    /// without a leader, are worth another try. Oversized or unauthorized records are not.
+    pub(crate) fn from_kafka(context: impl Into<String>, error: kafka::Error) -> Self {
+        let retryable = match &error {
+            kafka::Error::Io(_) | kafka::Error::NoHostReachable => true,
+            kafka::Error::Kafka(code) => is_retriable_kafka_code(*code),
+            kafka::Error::TopicPartitionError { error_code, .. } => {
+                is_retriable_kafka_code(*error_code)
+            }
+            _ => false,
+        };
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(error);
+    }
+
+    /// The codes OTLP names as retryable, a collector that is unavailable, throttling or short of time,
+    /// are worth another try. Anything else, like a request it could not parse, is not.
+    pub(crate) fn from_grpc(context: impl Into<String>, status: tonic::Status) -> Self {
+        use tonic::Code;
+        let retryable = matches!(
+            status.code(),
+            Code::Cancelled
+                | Code::DeadlineExceeded
+                | Code::ResourceExhausted
+                | Code::Aborted
+                | Code::OutOfRange
+                | Code::Unavailable
+                | Code::DataLoss
+        );
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(status);
+    }
+
+    /// Connection problems, timeouts, throttling and server side errors are worth another try,
+    /// a request the server refused as invalid is not.
+    pub(crate) fn from_http(context: impl Into<String>, error: reqwest::Error) -> Self {
+        let retryable = match error.status() {
+            Some(status) => status.is_server_error() || status.as_u16() == 429,
+            None => error.is_connect() || error.is_timeout() || error.is_request(),
+        };
+        return match retryable {
+            true => Self::retryable(context),
+            false => Self::permanent(context),
+        }
+        .caused_by(error);
+    }
+}
+
+fn is_retriable_kafka_code(code: kafka::error::KafkaCode) -> bool {
+    use kafka::error::KafkaCode;
+    return matches!(
+        code,
+        KafkaCode::UnknownTopicOrPartition
+            | KafkaCode::LeaderNotAvailable
+            | KafkaCode::NotLeaderForPartition
+            | KafkaCode::RequestTimedOut
+            | KafkaCode::BrokerNotAvailable
+            | KafkaCode::ReplicaNotAvailable
+            | KafkaCode::NetworkException
+            | KafkaCode::NotEnoughReplicas
+            | KafkaCode::NotEnoughReplicasAfterAppend
+    );
+}
+
+impl fmt::Display for ViewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            ViewErrorKind::Retryable => "retryable",
+            ViewErrorKind::Permanent => "permanent",
+        };
+        match &self.source {
+            Some(source) => write!(f, "{} ({}): {}", self.context, kind, source),
+            None => write!(f, "{} ({})", self.context, kind),
+        }
+    }
+}
+
+impl Error for ViewError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return self
+            .source
+            .as_ref()
+            .map(|source| source.as_ref() as &(dyn Error + 'static));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::view::ViewError;
+    use rusqlite::ffi;
+
+    #[test]
+    fn full_disk_should_be_retryable() {
+        let error = rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_FULL), None);
+        let view_error = ViewError::from_sqlite("Inserting operation", error);
+        assert!(view_error.is_retryable());
+        assert!(view_error
+            .to_string()
+            .starts_with("Inserting operation (retryable): "));
+    }
+
+    #[test]
+    fn constraint_violation_should_be_permanent() {
+        let error = rusqlite::Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_CONSTRAINT), None);
+        let view_error = ViewError::from_sqlite("Inserting operation", error);
+        assert!(!view_error.is_retryable());
+    }
+
+    #[test]
+    fn partition_without_leader_should_be_retryable_unlike_oversized_record() {
+        let leaderless = kafka::Error::TopicPartitionError {
+            topic_name: "linux-fs-audit-read".to_string(),
+            partition_id: 0,
+            error_code: kafka::error::KafkaCode::LeaderNotAvailable,
+        };
+        let oversized = kafka::Error::Kafka(kafka::error::KafkaCode::MessageSizeTooLarge);
+        assert!(ViewError::from_kafka("Producing", leaderless).is_retryable());
+        assert!(!ViewError::from_kafka("Producing", oversized).is_retryable());
+    }
+
+    #[test]
+    fn unavailable_collector_should_be_retryable_unlike_invalid_request() {
+        let unavailable = tonic::Status::unavailable("collector is shutting down");
+        let invalid = tonic::Status::invalid_argument("log record without body");
+        assert!(ViewError::from_grpc("Exporting", unavailable).is_retryable());
+        assert!(!ViewError::from_grpc("Exporting", invalid).is_retryable());
+    }
+}
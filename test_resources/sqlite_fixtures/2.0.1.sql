@@ -0,0 +1,20 @@
+-- A reports.sqlite as written by release 2.0.1, before the schema was versioned.
+create table IF NOT EXISTS operations
+                (
+                    user          TEXT not null,
+                    users_group   TEXT not null,
+                    executable    TEXT not null,
+                    syscall       TEXT not null,
+                    operation_key TEXT not null,
+                    unix_observation_time INTEGER
+                );
+create table IF NOT EXISTS operated_on_files
+                (
+                    absolute_path TEXT not null,
+                    unix_observation_time INTEGER
+                );
+INSERT INTO operations (user,users_group,executable,syscall,operation_key,unix_observation_time) VALUES ('maciek','maciek','/usr/bin/cat','unknown','READ','1701533809');
+INSERT INTO operations (user,users_group,executable,syscall,operation_key,unix_observation_time) VALUES ('root','root','/usr/bin/vim','unknown','WRITE','1701533815');
+INSERT INTO operations (user,users_group,executable,syscall,operation_key,unix_observation_time) VALUES ('maciek','maciek','/usr/bin/ls','unknown','READ','1701533900');
+INSERT INTO operated_on_files (absolute_path, unix_observation_time) VALUES ('/etc/shadow','1701533809');
+INSERT INTO operated_on_files (absolute_path, unix_observation_time) VALUES ('/etc/passwd','1701533815');